@@ -0,0 +1,50 @@
+#include <pthread.h>
+#include <stdio.h>
+#include <unistd.h>
+
+/* Two threads taking two mutexes in opposite order
+   run with `snapped -i 2 ./deadlock` to see the lock report */
+
+pthread_mutex_t m1 = PTHREAD_MUTEX_INITIALIZER;
+pthread_mutex_t m2 = PTHREAD_MUTEX_INITIALIZER;
+
+void *take_m1_then_m2(void *arg)
+{
+	pthread_mutex_lock(&m1);
+	sleep(1);
+	pthread_mutex_lock(&m2);
+
+	pthread_mutex_unlock(&m2);
+	pthread_mutex_unlock(&m1);
+
+	return NULL;
+}
+
+void *take_m2_then_m1(void *arg)
+{
+	pthread_mutex_lock(&m2);
+	sleep(1);
+	pthread_mutex_lock(&m1);
+
+	pthread_mutex_unlock(&m1);
+	pthread_mutex_unlock(&m2);
+
+	return NULL;
+}
+
+int main(int argc, char **argv)
+{
+	pthread_t t1, t2;
+
+	fprintf(stderr, "Program has started\n");
+
+	pthread_create(&t1, NULL, take_m1_then_m2, NULL);
+	pthread_create(&t2, NULL, take_m2_then_m1, NULL);
+
+	pthread_join(t1, NULL);
+	pthread_join(t2, NULL);
+
+	fprintf(stderr, "Not deadlocked ?\n");
+
+	return 0;
+}
@@ -0,0 +1,118 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+
+/* Stand-in for MPI point-to-point calls with the standard signatures
+   so that the MPI wait-for analysis can be exercised without MPI:
+
+   snapped -p 3 sh -c 'for i in 0 1 2; do PMI_RANK=$i PMI_SIZE=3 snapped ./fake_mpi $1 & done; wait' -- ring
+
+   - `ring` (default): each rank receives from the next one (cycle)
+   - `wait`: same cycle through MPI_Irecv and MPI_Wait
+   - `exit`: rank 0 exits while the others receive from it
+*/
+
+typedef int MPI_Comm;
+typedef int MPI_Datatype;
+typedef struct { int MPI_SOURCE; int MPI_TAG; } MPI_Status;
+
+/* Layout of the point-to-point requests of Open MPI */
+typedef struct mca_pml_base_request_t { int req_peer; int req_tag; } mca_pml_base_request_t;
+typedef mca_pml_base_request_t *MPI_Request;
+
+#define MPI_COMM_WORLD 0x44000000
+#define MPI_INT 0x4c000405
+
+static int world_rank = 0;
+static int world_size = 1;
+
+int MPI_Init(int *argc, char ***argv)
+{
+	char *rank = getenv("PMI_RANK");
+	char *size = getenv("PMI_SIZE");
+
+	if (rank)
+		world_rank = atoi(rank);
+	if (size)
+		world_size = atoi(size);
+
+	return 0;
+}
+
+int MPI_Comm_rank(MPI_Comm comm, int *rank)
+{
+	*rank = world_rank;
+	return 0;
+}
+
+int MPI_Comm_size(MPI_Comm comm, int *size)
+{
+	*size = world_size;
+	return 0;
+}
+
+__attribute__((noinline)) int MPI_Recv(void *buf, int count, MPI_Datatype datatype,
+                                       int source, int tag, MPI_Comm comm, MPI_Status *status)
+{
+	/* Nobody ever sends */
+	while (1)
+		sleep(1);
+
+	return 0;
+}
+
+__attribute__((noinline)) int MPI_Send(const void *buf, int count, MPI_Datatype datatype,
+                                       int dest, int tag, MPI_Comm comm)
+{
+	/* Rendez-vous never happens */
+	while (1)
+		sleep(1);
+
+	return 0;
+}
+
+int MPI_Irecv(void *buf, int count, MPI_Datatype datatype, int source, int tag,
+              MPI_Comm comm, MPI_Request *request)
+{
+	*request = malloc(sizeof(mca_pml_base_request_t));
+	(*request)->req_peer = source;
+	(*request)->req_tag = tag;
+
+	return 0;
+}
+
+__attribute__((noinline)) int MPI_Wait(MPI_Request *request, MPI_Status *status)
+{
+	/* The matching send never comes */
+	while (1)
+		sleep(1);
+
+	return 0;
+}
+
+int main(int argc, char **argv)
+{
+	int rank, size, data = 0;
+	MPI_Status status;
+	MPI_Request request;
+
+	MPI_Init(&argc, &argv);
+	MPI_Comm_rank(MPI_COMM_WORLD, &rank);
+	MPI_Comm_size(MPI_COMM_WORLD, &size);
+
+	fprintf(stderr, "FAKE MPI: %d / %d\n", rank, size);
+
+	if ((argc > 1) && (argv[1][0] == 'e')) {
+		if (rank == 0)
+			return 0;
+
+		MPI_Recv(&data, 1, MPI_INT, 0, 42, MPI_COMM_WORLD, &status);
+	} else if ((argc > 1) && (argv[1][0] == 'w')) {
+		MPI_Irecv(&data, 1, MPI_INT, (rank + 1) % size, 42, MPI_COMM_WORLD, &request);
+		MPI_Wait(&request, &status);
+	} else {
+		MPI_Recv(&data, 1, MPI_INT, (rank + 1) % size, 42, MPI_COMM_WORLD, &status);
+	}
+
+	return 0;
+}
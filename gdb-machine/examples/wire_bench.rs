@@ -0,0 +1,246 @@
+//! Latency of a snapshot over a simulated tree of a 10k process job
+//!
+//! Aggregators are threads linked to their parent by a local TCP connection,
+//! as the pivots of a real tree. The leaves are simulated by their
+//! aggregator: they answer after the snapshot latency, each with its own
+//! call stacks (the worst case for the merge). Each format sends the
+//! snapshot and the process table up the tree, the time covers the whole
+//! walk as seen by the root.
+//!
+//! `cargo run --release --example wire_bench [LEAVES] [ARITY] [SNAPSHOT_MS]`
+
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::ops::Range;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use gdb_machine::metadata::{BacktraceState, DisplayFrame, ProcessInfo, ThreadRole};
+use gdb_machine::wire::{self, WireFormat};
+use serde::{Deserialize, Serialize};
+
+const ROUNDS: usize = 10;
+
+type Snapshot = HashMap<u64, (u64, Vec<BacktraceState>)>;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+enum Request {
+    Snapshot,
+    Processes,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Reply {
+    Snapshot(Snapshot),
+    Processes(HashMap<u64, ProcessInfo>),
+}
+
+impl Reply {
+    fn merge(&mut self, other: Reply) -> Result<()> {
+        match (self, other) {
+            (Reply::Snapshot(a), Reply::Snapshot(b)) => a.extend(b),
+            (Reply::Processes(a), Reply::Processes(b)) => a.extend(b),
+            _ => return Err(anyhow!("Mismatched replies")),
+        }
+
+        Ok(())
+    }
+}
+
+fn snapshot(leaves: Range<u64>) -> Snapshot {
+    let mut ret = HashMap::new();
+
+    for id in leaves {
+        for th in 0..4 {
+            let mut bt = vec![BacktraceState::Thread(if th == 0 {
+                ThreadRole::Main
+            } else {
+                ThreadRole::Worker
+            })];
+
+            for depth in 0..12 {
+                bt.push(BacktraceState::Frame(DisplayFrame {
+                    func: format!("solver_step_{}", depth),
+                    file: Some("src/solver/kernel.c".to_string()),
+                    line: Some(100 + depth * 7 + (id % 13) as u32),
+                    offset: None,
+                    from: None,
+                    inlined: false,
+                }));
+            }
+
+            ret.insert(id * 4 + th, (1, bt));
+        }
+    }
+
+    ret
+}
+
+fn processes(leaves: Range<u64>) -> HashMap<u64, ProcessInfo> {
+    leaves
+        .map(|id| {
+            let hostname = format!("node{:04}", id / 64);
+            (
+                id,
+                ProcessInfo {
+                    mpirank: Some(id as u32),
+                    pid: 4000 + id % 64,
+                    locality_descriptor: format!("{}-0-{}", hostname, 4000 + id % 64),
+                    hostname,
+                },
+            )
+        })
+        .collect()
+}
+
+/// A link to an aggregator
+struct Link {
+    sock: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Link {
+    fn connect(addr: SocketAddr) -> Result<Link> {
+        let sock = TcpStream::connect(addr)?;
+        let reader = BufReader::new(sock.try_clone()?);
+        Ok(Link { sock, reader })
+    }
+
+    fn ask(&mut self, format: WireFormat, req: Request) -> Result<Reply> {
+        format.send(&mut self.sock, &req)?;
+        wire::recv::<Reply>(&mut self.reader)?
+            .map(|(reply, _)| reply)
+            .ok_or(anyhow!("Aggregator closed the connection"))
+    }
+}
+
+/// Start the aggregator of `leaves` and the subtree below it
+///
+/// Aggregators of at most `arity` leaves simulate them, the others split
+/// their leaves between `arity` child aggregators.
+fn aggregator(leaves: Range<u64>, arity: u64, latency: Duration) -> Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    let count = leaves.end - leaves.start;
+    let mut children = Vec::new();
+
+    if count > arity {
+        let chunk = count.div_ceil(arity);
+
+        for start in leaves.clone().step_by(chunk as usize) {
+            let end = (start + chunk).min(leaves.end);
+            children.push(Link::connect(aggregator(start..end, arity, latency)?)?);
+        }
+    }
+
+    thread::spawn(move || -> Result<()> {
+        let (mut sock, _) = listener.accept()?;
+        let mut reader = BufReader::new(sock.try_clone()?);
+
+        while let Some((req, format)) = wire::recv::<Request>(&mut reader)? {
+            let reply = if children.is_empty() {
+                /* The leaves snapshot in parallel */
+                thread::sleep(latency);
+
+                match req {
+                    Request::Snapshot => Reply::Snapshot(snapshot(leaves.clone())),
+                    Request::Processes => Reply::Processes(processes(leaves.clone())),
+                }
+            } else {
+                let replies: Vec<Result<Reply>> = thread::scope(|s| {
+                    let handles: Vec<_> = children
+                        .iter_mut()
+                        .map(|c| s.spawn(move || c.ask(format, req)))
+                        .collect();
+
+                    handles
+                        .into_iter()
+                        .map(|h| h.join().map_err(|_| anyhow!("Link thread panicked"))?)
+                        .collect()
+                });
+
+                let mut replies = replies.into_iter();
+                let mut ret = replies.next().ok_or(anyhow!("No children"))??;
+
+                for r in replies {
+                    ret.merge(r?)?;
+                }
+
+                ret
+            };
+
+            format.send(&mut sock, &reply)?;
+        }
+
+        Ok(())
+    });
+
+    Ok(addr)
+}
+
+/// Median time of a request at the root and size of its reply
+fn bench(root: &mut Link, format: WireFormat, req: Request) -> Result<(usize, Duration)> {
+    let mut times = Vec::new();
+    let mut size = 0;
+
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let reply = root.ask(format, req)?;
+        times.push(start.elapsed());
+
+        if size == 0 {
+            size = format.encode(&reply)?.len();
+        }
+    }
+
+    times.sort();
+
+    Ok((size, times[ROUNDS / 2]))
+}
+
+fn main() -> Result<()> {
+    let arg = |n: usize, default: u64| -> Result<u64> {
+        Ok(std::env::args()
+            .nth(n)
+            .map(|v| v.parse())
+            .transpose()?
+            .unwrap_or(default))
+    };
+
+    let leaves = arg(1, 10000)?;
+    let arity = arg(2, 24)?.max(2);
+    let latency = Duration::from_millis(arg(3, 50)?);
+
+    let mut root = Link::connect(aggregator(0..leaves, arity, latency)?)?;
+
+    println!(
+        "{} leaves, arity {}, {}ms per snapshot, median of {} requests at the root",
+        leaves,
+        arity,
+        latency.as_millis(),
+        ROUNDS
+    );
+    println!(
+        "{:<8} {:>14} {:>12} {:>14} {:>12}",
+        "format", "snapshot (B)", "latency", "processes (B)", "latency"
+    );
+
+    for format in [WireFormat::Json, WireFormat::Binary, WireFormat::Compressed] {
+        let (ssize, stime) = bench(&mut root, format, Request::Snapshot)?;
+        let (psize, ptime) = bench(&mut root, format, Request::Processes)?;
+
+        println!(
+            "{:<8} {:>14} {:>10.1}ms {:>14} {:>10.1}ms",
+            format.to_string(),
+            ssize,
+            stime.as_secs_f64() * 1000.0,
+            psize,
+            ptime.as_secs_f64() * 1000.0
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,546 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::transport::Stream;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Shared secret of the tree, set by the root and inherited by the leaves
+pub const TOKEN_ENV: &str = "GDBW_TOKEN";
+
+/// How the links are authenticated (`token` or `hmac`)
+pub const AUTH_MODE_ENV: &str = "GDBW_AUTH";
+
+/// First bytes of the challenge sent by a server to each new connection
+///
+/// Changes with the handshake so that peers of another one stop at once.
+const CHALLENGE_MAGIC: [u8; 2] = *b"SB";
+
+const NONCE_LEN: usize = 16;
+const TAG_LEN: usize = 32;
+
+/// Time given to a new connection to answer the challenge
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest record accepted in HMAC mode
+const MAX_RECORD_LEN: usize = 1 << 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuthMode {
+    /// Each connection proves it knows the token
+    #[default]
+    Token,
+    /// As `Token` and every message carries an HMAC of its content
+    Hmac,
+}
+
+impl std::fmt::Display for AuthMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthMode::Token => write!(f, "token"),
+            AuthMode::Hmac => write!(f, "hmac"),
+        }
+    }
+}
+
+impl FromStr for AuthMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "token" => Ok(AuthMode::Token),
+            "hmac" => Ok(AuthMode::Hmac),
+            _ => Err(anyhow!(
+                "Unknown authentication mode '{}' (token or hmac)",
+                s
+            )),
+        }
+    }
+}
+
+impl AuthMode {
+    fn from_env() -> AuthMode {
+        std::env::var(AUTH_MODE_ENV)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let mut ret = vec![0; len];
+    File::open("/dev/urandom")?.read_exact(&mut ret)?;
+    Ok(ret)
+}
+
+fn token() -> Option<String> {
+    std::env::var(TOKEN_ENV).ok().filter(|t| !t.is_empty())
+}
+
+/// Make sure the tree has a token, generating one if none was given
+pub fn ensure_token() -> Result<()> {
+    if token().is_none() {
+        let token: String = random_bytes(32)?
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        std::env::set_var(TOKEN_ENV, token);
+    }
+
+    Ok(())
+}
+
+fn mac(key: &[u8], parts: &[&[u8]]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any size");
+
+    for p in parts {
+        mac.update(p);
+    }
+
+    mac
+}
+
+/// Label of the proofs sent by clients
+const CLIENT_PROOF: &[u8] = b"snapped-auth";
+/// Label of the proofs sent by servers, a client proof cannot be sent back
+const SERVER_PROOF: &[u8] = b"snapped-server";
+
+/// Answer to a challenge, proves the knowledge of the token
+fn proof(token: &str, label: &[u8], nonce: &[u8]) -> Vec<u8> {
+    mac(token.as_bytes(), &[label, nonce])
+        .finalize()
+        .into_bytes()
+        .to_vec()
+}
+
+fn check_proof(token: &str, label: &[u8], nonce: &[u8], answer: &[u8]) -> bool {
+    mac(token.as_bytes(), &[label, nonce])
+        .verify_slice(answer)
+        .is_ok()
+}
+
+fn session_key(token: &str, nonce: &[u8]) -> Vec<u8> {
+    mac(token.as_bytes(), &[b"snapped-session", nonce])
+        .finalize()
+        .into_bytes()
+        .to_vec()
+}
+
+/// Writes a record with the HMAC of its content on each flush
+struct SealedWriter {
+    sock: Stream,
+    key: Vec<u8>,
+    direction: u8,
+    seq: u64,
+    pending: Vec<u8>,
+}
+
+impl Write for SealedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let tag = mac(
+            &self.key,
+            &[&[self.direction], &self.seq.to_le_bytes(), &self.pending],
+        )
+        .finalize()
+        .into_bytes();
+        self.seq += 1;
+
+        let mut record = Vec::with_capacity(self.pending.len() + 4 + TAG_LEN);
+        record.extend_from_slice(&(self.pending.len() as u32).to_le_bytes());
+        record.append(&mut self.pending);
+        record.extend_from_slice(&tag);
+
+        self.sock.write_all(&record)?;
+        self.sock.flush()
+    }
+}
+
+/// Reads records and only hands out the content of authentic ones
+struct SealedReader {
+    sock: Stream,
+    key: Vec<u8>,
+    direction: u8,
+    seq: u64,
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl SealedReader {
+    fn next_record(&mut self) -> std::io::Result<bool> {
+        let mut len = [0; 4];
+
+        match self.sock.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        let len = u32::from_le_bytes(len) as usize;
+
+        if len > MAX_RECORD_LEN {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Authenticated record is too large",
+            ));
+        }
+
+        let mut data = vec![0; len];
+        self.sock.read_exact(&mut data)?;
+        let mut tag = [0; TAG_LEN];
+        self.sock.read_exact(&mut tag)?;
+
+        mac(
+            &self.key,
+            &[&[self.direction], &self.seq.to_le_bytes(), &data],
+        )
+        .verify_slice(&tag)
+        .map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Message failed HMAC authentication",
+            )
+        })?;
+        self.seq += 1;
+
+        self.data = data;
+        self.pos = 0;
+
+        Ok(true)
+    }
+}
+
+impl Read for SealedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos == self.data.len() && !self.next_record()? {
+            return Ok(0);
+        }
+
+        let n = buf.len().min(self.data.len() - self.pos);
+        buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+/// Read and write halves of an authenticated connection
+pub type Channel = (BufReader<Box<dyn Read + Send>>, Box<dyn Write + Send>);
+
+fn plain(sock: &Stream) -> Result<Channel> {
+    Ok((
+        BufReader::new(Box::new(sock.try_clone()?)),
+        Box::new(sock.try_clone()?),
+    ))
+}
+
+fn channel(
+    sock: &Stream,
+    mode: AuthMode,
+    token: &str,
+    nonce: &[u8],
+    server: bool,
+) -> Result<Channel> {
+    if mode == AuthMode::Token {
+        return plain(sock);
+    }
+
+    let key = session_key(token, nonce);
+    let (rdir, wdir) = if server { (b'C', b'S') } else { (b'S', b'C') };
+
+    Ok((
+        BufReader::new(Box::new(SealedReader {
+            sock: sock.try_clone()?,
+            key: key.clone(),
+            direction: rdir,
+            seq: 0,
+            data: Vec::new(),
+            pos: 0,
+        })),
+        Box::new(SealedWriter {
+            sock: sock.try_clone()?,
+            key,
+            direction: wdir,
+            seq: 0,
+            pending: Vec::new(),
+        }),
+    ))
+}
+
+/// Challenge a new connection, fails if it does not know the token
+///
+/// The connection challenges the server in turn so that a client never
+/// talks to a server which does not know the token either. Without a
+/// token in the environment connections are not checked.
+pub fn accept(sock: &Stream) -> Result<Channel> {
+    match token() {
+        Some(token) => accept_with(sock, &token, AuthMode::from_env()),
+        None => plain(sock),
+    }
+}
+
+fn accept_with(sock: &Stream, token: &str, mode: AuthMode) -> Result<Channel> {
+    let nonce = random_bytes(NONCE_LEN)?;
+
+    let mut challenge = CHALLENGE_MAGIC.to_vec();
+    challenge.push((mode == AuthMode::Hmac) as u8);
+    challenge.extend_from_slice(&nonce);
+
+    let mut sock_w = sock.try_clone()?;
+    sock_w.write_all(&challenge)?;
+
+    /* Proof of the client followed by its own challenge */
+    let mut answer = [0; TAG_LEN + NONCE_LEN];
+    let timeout = sock.read_timeout()?;
+    sock.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    sock.try_clone()?.read_exact(&mut answer)?;
+    sock.set_read_timeout(timeout)?;
+
+    let (answer, client_nonce) = answer.split_at(TAG_LEN);
+
+    if !check_proof(token, CLIENT_PROOF, &nonce, answer) {
+        sock_w.write_all(&[0])?;
+        return Err(anyhow!("Connection does not know the token"));
+    }
+
+    let mut status = vec![1];
+    status.extend_from_slice(&proof(token, SERVER_PROOF, client_nonce));
+    sock_w.write_all(&status)?;
+
+    channel(sock, mode, token, &[&nonce, client_nonce].concat(), true)
+}
+
+/// Answer the challenge of the server `sock` is connected to
+///
+/// Fails if the server does not prove it knows the token as well.
+pub fn connect(sock: &Stream) -> Result<Channel> {
+    match token() {
+        Some(token) => connect_with(sock, &token),
+        None => plain(sock),
+    }
+}
+
+fn connect_with(sock: &Stream, token: &str) -> Result<Channel> {
+    let mut challenge = [0; 3 + NONCE_LEN];
+    let timeout = sock.read_timeout()?;
+    sock.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut sock_rw = sock.try_clone()?;
+    sock_rw.read_exact(&mut challenge)?;
+
+    if challenge[..2] != CHALLENGE_MAGIC {
+        return Err(anyhow!("Server did not send an authentication challenge"));
+    }
+
+    let mode = if challenge[2] == 1 {
+        AuthMode::Hmac
+    } else {
+        AuthMode::Token
+    };
+    let nonce = &challenge[3..];
+    let client_nonce = random_bytes(NONCE_LEN)?;
+
+    let mut answer = proof(token, CLIENT_PROOF, nonce);
+    answer.extend_from_slice(&client_nonce);
+    sock_rw.write_all(&answer)?;
+
+    let mut status = [0; 1];
+    sock_rw.read_exact(&mut status)?;
+
+    if status[0] != 1 {
+        return Err(anyhow!(
+            "Server refused the token ({} is not the one of the tree)",
+            TOKEN_ENV
+        ));
+    }
+
+    let mut server_proof = [0; TAG_LEN];
+    sock_rw.read_exact(&mut server_proof)?;
+    sock.set_read_timeout(timeout)?;
+
+    if !check_proof(token, SERVER_PROOF, &client_nonce, &server_proof) {
+        return Err(anyhow!("Server does not know the token of the tree"));
+    }
+
+    channel(sock, mode, token, &[nonce, &client_nonce].concat(), false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    fn pair() -> (Stream, Stream) {
+        let (a, b) = UnixStream::pair().unwrap();
+        (Stream::Unix(a), Stream::Unix(b))
+    }
+
+    /// Run the handshake between a server knowing `server` and a client knowing `client`
+    fn handshake(server: &str, client: &str, mode: AuthMode) -> (Result<Channel>, Result<Channel>) {
+        let (s, c) = pair();
+        let client = client.to_string();
+
+        let connect = thread::spawn(move || connect_with(&c, &client));
+        let accepted = accept_with(&s, server, mode);
+
+        (accepted, connect.join().unwrap())
+    }
+
+    fn sealed(sock: Stream, key: &[u8], direction: u8) -> (SealedWriter, SealedReader) {
+        (
+            SealedWriter {
+                sock: sock.try_clone().unwrap(),
+                key: key.to_vec(),
+                direction,
+                seq: 0,
+                pending: Vec::new(),
+            },
+            SealedReader {
+                sock,
+                key: key.to_vec(),
+                direction,
+                seq: 0,
+                data: Vec::new(),
+                pos: 0,
+            },
+        )
+    }
+
+    /// Raw records of the given messages as written on the socket
+    fn records(key: &[u8], msgs: &[&[u8]]) -> Vec<Vec<u8>> {
+        let (tx, mut rx) = pair();
+        let (mut writer, _) = sealed(tx, key, b'C');
+
+        msgs.iter()
+            .map(|m| {
+                writer.write_all(m).unwrap();
+                writer.flush().unwrap();
+
+                let mut raw = vec![0; 4 + m.len() + TAG_LEN];
+                rx.read_exact(&mut raw).unwrap();
+                raw
+            })
+            .collect()
+    }
+
+    /// Read the messages a reader accepts out of raw records
+    fn read_records(key: &[u8], raw: &[Vec<u8>]) -> (Vec<String>, Option<std::io::Error>) {
+        let (mut tx, rx) = pair();
+        let (_, reader) = sealed(rx, key, b'C');
+
+        for r in raw {
+            tx.write_all(r).unwrap();
+        }
+        drop(tx);
+
+        let mut reader = BufReader::new(reader);
+        let mut ret = Vec::new();
+
+        loop {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) => return (ret, None),
+                Ok(_) => ret.push(line),
+                Err(e) => return (ret, Some(e)),
+            }
+        }
+    }
+
+    #[test]
+    fn challenge_response() {
+        for mode in [AuthMode::Token, AuthMode::Hmac] {
+            let (server, client) = handshake("secret", "secret", mode);
+            let (mut sr, mut sw) = server.unwrap();
+            let (mut cr, mut cw) = client.unwrap();
+
+            cw.write_all(b"ping\n").unwrap();
+            cw.flush().unwrap();
+            let mut line = String::new();
+            sr.read_line(&mut line).unwrap();
+            assert_eq!(line, "ping\n", "{}", mode);
+
+            sw.write_all(b"pong\n").unwrap();
+            sw.flush().unwrap();
+            line.clear();
+            cr.read_line(&mut line).unwrap();
+            assert_eq!(line, "pong\n", "{}", mode);
+        }
+    }
+
+    #[test]
+    fn wrong_token() {
+        let (server, client) = handshake("secret", "guess", AuthMode::Token);
+
+        assert!(server.is_err());
+        assert!(client
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("refused the token"));
+    }
+
+    /// A server which accepts any answer and replays the proof it was sent
+    #[test]
+    fn impostor_server() {
+        let (s, c) = pair();
+        let connect = thread::spawn(move || connect_with(&c, "secret"));
+
+        let mut challenge = CHALLENGE_MAGIC.to_vec();
+        challenge.push(0);
+        challenge.extend_from_slice(&[7; NONCE_LEN]);
+        let mut sock = s.try_clone().unwrap();
+        sock.write_all(&challenge).unwrap();
+
+        let mut answer = [0; TAG_LEN + NONCE_LEN];
+        sock.read_exact(&mut answer).unwrap();
+        sock.write_all(&[1]).unwrap();
+        sock.write_all(&answer[..TAG_LEN]).unwrap();
+
+        let err = connect.join().unwrap().err().unwrap();
+        assert!(err.to_string().contains("does not know the token"));
+    }
+
+    #[test]
+    fn sealed_frames() {
+        let key = session_key("secret", b"nonce");
+        let raw = records(&key, &[b"first\n", b"second\n"]);
+
+        let (msgs, err) = read_records(&key, &raw);
+        assert_eq!(msgs, vec!["first\n", "second\n"]);
+        assert!(err.is_none());
+
+        /* Content changed on the way */
+        let mut tampered = raw.clone();
+        tampered[1][6] ^= 1;
+        let (msgs, err) = read_records(&key, &tampered);
+        assert_eq!(msgs, vec!["first\n"]);
+        assert_eq!(err.unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        /* A record played again is out of sequence */
+        let replayed = vec![raw[0].clone(), raw[0].clone()];
+        let (msgs, err) = read_records(&key, &replayed);
+        assert_eq!(msgs, vec!["first\n"]);
+        assert_eq!(err.unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        /* Records of another session */
+        let other = session_key("secret", b"other");
+        let (msgs, err) = read_records(&other, &raw);
+        assert!(msgs.is_empty());
+        assert!(err.is_some());
+    }
+}
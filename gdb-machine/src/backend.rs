@@ -0,0 +1,79 @@
+//! Debuggers the leaves can drive their process with
+//!
+//! Each leaf reads [BACKEND_ENV] when it starts so that the backend can be
+//! chosen per leaf (e.g. through the environment given by the launcher).
+
+use anyhow::{anyhow, Result};
+use std::process::Child;
+use std::str::FromStr;
+
+use crate::debugger::Debugger;
+use crate::gdbmi::GdbMi;
+use crate::lldbdap::LldbDap;
+
+/// Debugger backend of the leaves: `gdb` (default) or `lldb`
+pub const BACKEND_ENV: &str = "GDBW_BACKEND";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Backend {
+    /// GDB over its machine interface
+    #[default]
+    Gdb,
+    /// LLDB over the Debug Adapter Protocol of `lldb-dap`
+    Lldb,
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Gdb => write!(f, "gdb"),
+            Backend::Lldb => write!(f, "lldb"),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gdb" => Ok(Backend::Gdb),
+            "lldb" => Ok(Backend::Lldb),
+            _ => Err(anyhow!("Unknown debugger backend '{}' (gdb or lldb)", s)),
+        }
+    }
+}
+
+impl Backend {
+    /// Backend set in [BACKEND_ENV] or the default one
+    pub fn from_env() -> Result<Backend> {
+        match std::env::var(BACKEND_ENV) {
+            Ok(v) => v.parse(),
+            Err(_) => Ok(Backend::default()),
+        }
+    }
+
+    /// Wrap `cmd` in the debugger, the program is only started by `start`
+    pub fn run(&self, cmd: &[&str]) -> Result<Box<dyn Debugger>> {
+        Ok(match self {
+            Backend::Gdb => Box::new(GdbMi::run(cmd)?),
+            Backend::Lldb => Box::new(LldbDap::run(cmd)?),
+        })
+    }
+
+    /// Same as [Backend::run] handing the debugger process to the caller
+    pub fn run_local(&self, cmd: &[&str]) -> Result<(Box<dyn Debugger>, Option<Child>)> {
+        Ok(match self {
+            Backend::Gdb => {
+                let mut gdb = GdbMi::run(cmd)?;
+                let child = gdb.take_child();
+                (Box::new(gdb), child)
+            }
+            Backend::Lldb => {
+                let mut lldb = LldbDap::run(cmd)?;
+                let child = lldb.take_child();
+                (Box::new(lldb), child)
+            }
+        })
+    }
+}
@@ -0,0 +1,237 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use anyhow::{anyhow, Result};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const PT_NOTE: u32 = 4;
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// Size of a program header in 64 and 32-bit files
+const PHDR64_LEN: u16 = 56;
+const PHDR32_LEN: u16 = 32;
+
+/// Notes larger than this are not build-ids
+const MAX_NOTE_SEGMENT: u64 = 1 << 20;
+
+/// Reads the fields of an ELF file in its own class and byte order
+struct ElfFields {
+    is64: bool,
+    le: bool,
+}
+
+impl ElfFields {
+    fn u16(&self, b: &[u8]) -> u16 {
+        let v = [b[0], b[1]];
+        if self.le {
+            u16::from_le_bytes(v)
+        } else {
+            u16::from_be_bytes(v)
+        }
+    }
+
+    fn u32(&self, b: &[u8]) -> u32 {
+        let v = [b[0], b[1], b[2], b[3]];
+        if self.le {
+            u32::from_le_bytes(v)
+        } else {
+            u32::from_be_bytes(v)
+        }
+    }
+
+    fn u64(&self, b: &[u8]) -> u64 {
+        let mut v = [0; 8];
+        v.copy_from_slice(&b[..8]);
+        if self.le {
+            u64::from_le_bytes(v)
+        } else {
+            u64::from_be_bytes(v)
+        }
+    }
+
+    /// Address sized field
+    fn addr(&self, b: &[u8]) -> u64 {
+        if self.is64 {
+            self.u64(b)
+        } else {
+            self.u32(b) as u64
+        }
+    }
+}
+
+/// Find the GNU build-id in the notes of a segment
+fn note_build_id(fields: &ElfFields, notes: &[u8]) -> Option<String> {
+    let mut off = 0;
+
+    while off + 12 <= notes.len() {
+        let namesz = fields.u32(&notes[off..]) as usize;
+        let descsz = fields.u32(&notes[off + 4..]) as usize;
+        let ntype = fields.u32(&notes[off + 8..]);
+
+        let name = off + 12;
+        let desc = name + namesz.div_ceil(4) * 4;
+        let next = desc + descsz.div_ceil(4) * 4;
+
+        if desc + descsz > notes.len() {
+            return None;
+        }
+
+        if ntype == NT_GNU_BUILD_ID && &notes[name..name + namesz] == b"GNU\0" {
+            return Some(
+                notes[desc..desc + descsz]
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect(),
+            );
+        }
+
+        off = next;
+    }
+
+    None
+}
+
+/// GNU build-id of an ELF binary or shared library
+///
+/// Returns `None` for binaries linked without `--build-id`.
+pub fn build_id(path: &str) -> Result<Option<String>> {
+    let mut f = File::open(path).map_err(|e| anyhow!("Failed to open {} : {}", path, e))?;
+    let len = f.metadata()?.len();
+
+    read_build_id(&mut f, len).map_err(|e| anyhow!("{} : {}", path, e))
+}
+
+/// Build-id of the ELF image of `len` bytes read from `f`
+fn read_build_id(f: &mut (impl Read + Seek), len: u64) -> Result<Option<String>> {
+    let mut hdr = [0; 64];
+    f.read_exact(&mut hdr[..52])?;
+
+    if hdr[..4] != ELF_MAGIC {
+        return Err(anyhow!("Not an ELF file"));
+    }
+
+    let fields = ElfFields {
+        is64: hdr[4] == 2,
+        le: hdr[5] == 1,
+    };
+
+    if fields.is64 {
+        f.read_exact(&mut hdr[52..])?;
+    }
+
+    let (phoff, phentsize, phnum) = if fields.is64 {
+        (
+            fields.u64(&hdr[32..]),
+            fields.u16(&hdr[54..]),
+            fields.u16(&hdr[56..]),
+        )
+    } else {
+        (
+            fields.u32(&hdr[28..]) as u64,
+            fields.u16(&hdr[42..]),
+            fields.u16(&hdr[44..]),
+        )
+    };
+
+    let min_phentsize = if fields.is64 { PHDR64_LEN } else { PHDR32_LEN };
+
+    /* Not a layout we can read the fields of (0 when there are no headers) */
+    if phentsize < min_phentsize {
+        return Ok(None);
+    }
+
+    let phsize = phentsize as u64 * phnum as u64;
+
+    if phoff.checked_add(phsize).is_none_or(|end| end > len) {
+        return Err(anyhow!("Program headers lie outside of the file"));
+    }
+
+    let mut phdrs = vec![0; phsize as usize];
+    f.seek(SeekFrom::Start(phoff))?;
+    f.read_exact(&mut phdrs)?;
+
+    for ph in phdrs.chunks(phentsize as usize) {
+        if fields.u32(ph) != PT_NOTE {
+            continue;
+        }
+
+        let (offset, size) = if fields.is64 {
+            (fields.addr(&ph[8..]), fields.addr(&ph[32..]))
+        } else {
+            (fields.addr(&ph[4..]), fields.addr(&ph[16..]))
+        };
+
+        if size > MAX_NOTE_SEGMENT || offset.checked_add(size).is_none_or(|end| end > len) {
+            continue;
+        }
+
+        let mut notes = vec![0; size as usize];
+        f.seek(SeekFrom::Start(offset))?;
+        f.read_exact(&mut notes)?;
+
+        if let Some(id) = note_build_id(&fields, &notes) {
+            return Ok(Some(id));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const BUILD_ID: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+
+    /// 64-bit little endian ELF with a note segment after its program headers
+    fn elf64(phentsize: u16, phnum: u16) -> Vec<u8> {
+        let mut notes = Vec::new();
+        notes.extend_from_slice(&4u32.to_le_bytes());
+        notes.extend_from_slice(&(BUILD_ID.len() as u32).to_le_bytes());
+        notes.extend_from_slice(&NT_GNU_BUILD_ID.to_le_bytes());
+        notes.extend_from_slice(b"GNU\0");
+        notes.extend_from_slice(&BUILD_ID);
+
+        let mut elf = vec![0; 64];
+        elf[..4].copy_from_slice(&ELF_MAGIC);
+        elf[4] = 2;
+        elf[5] = 1;
+        elf[32..40].copy_from_slice(&64u64.to_le_bytes());
+        elf[54..56].copy_from_slice(&phentsize.to_le_bytes());
+        elf[56..58].copy_from_slice(&phnum.to_le_bytes());
+
+        let notes_off = 64 + PHDR64_LEN as u64;
+        let mut ph = vec![0; PHDR64_LEN as usize];
+        ph[..4].copy_from_slice(&PT_NOTE.to_le_bytes());
+        ph[8..16].copy_from_slice(&notes_off.to_le_bytes());
+        ph[32..40].copy_from_slice(&(notes.len() as u64).to_le_bytes());
+
+        elf.extend(ph);
+        elf.extend(notes);
+        elf
+    }
+
+    fn read(elf: &[u8]) -> Result<Option<String>> {
+        read_build_id(&mut Cursor::new(elf), elf.len() as u64)
+    }
+
+    #[test]
+    fn note_segment() {
+        let elf = elf64(PHDR64_LEN, 1);
+        assert_eq!(read(&elf).unwrap().as_deref(), Some("deadbeef"));
+
+        /* Note segment pointing past the end of the file */
+        let mut cut = elf.clone();
+        cut.truncate(elf.len() - 2);
+        assert_eq!(read(&cut).unwrap(), None);
+    }
+
+    #[test]
+    fn malformed_program_headers() {
+        assert_eq!(read(&elf64(0, 1)).unwrap(), None);
+        assert_eq!(read(&elf64(8, 1)).unwrap(), None);
+        assert!(read(&elf64(PHDR64_LEN, u16::MAX)).is_err());
+        assert!(read(b"#!/bin/sh\n").is_err());
+    }
+}
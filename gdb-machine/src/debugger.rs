@@ -1,11 +1,17 @@
 use std::{
     any::Any,
     collections::HashMap,
-    sync::{Arc, Mutex},
+    sync::{mpsc::Sender, Arc, Mutex},
 };
 
 use crate::{
-    metadata::{BacktraceState, RunState, SymbolTable},
+    health::SubtreeHealth,
+    metadata::{
+        BacktraceState, LibraryReport, LockReport, MpiRankState, ProcessInfo, ProgramSnapshot,
+        RunState, SnapshotPart, SymbolTable,
+    },
+    topology::TreeLayout,
+    wire::Capabilities,
     TreeState,
 };
 use anyhow::{anyhow, Result};
@@ -142,9 +148,79 @@ pub trait Debugger: Send + Any {
     /// Snapshot a stopped program
     fn snapshot(&mut self) -> Result<HashMap<u64, (u64, Vec<BacktraceState>)>>;
 
+    /// Snapshot a stopped program, sending the stacks as the processes report them
+    ///
+    /// Merging all the parts gives the result of [Debugger::snapshot], which
+    /// debuggers without children send in a single part.
+    fn snapshot_stream(&mut self, parts: &Sender<SnapshotPart>) -> Result<()> {
+        let part = SnapshotPart {
+            components: self.snapshot()?,
+            reported: self.count()?,
+        };
+
+        if !part.is_empty() {
+            parts
+                .send(part)
+                .map_err(|_| anyhow!("Snapshot receiver is gone"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Full state of process `id` (every thread, frame, argument and local)
+    ///
+    /// Returns `None` when the process is not hosted by this debugger.
+    fn process_snapshot(&mut self, id: u64) -> Result<Option<ProgramSnapshot>>;
+
+    /// Restrict the following commands to a subset of the processes
+    ///
+    /// Commands are then only routed down the subtrees holding the targets,
+    /// `None` targets all processes again. Debuggers without children ignore it.
+    #[allow(unused)]
+    fn set_targets(&mut self, targets: Option<Targets>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Release the debugged program and terminate the debugger
+    fn detach(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Health of the subtrees below this debugger as seen by heartbeats
+    fn health(&mut self) -> Result<Vec<SubtreeHealth>> {
+        Ok(Vec::new())
+    }
+
+    /// Features supported by all the processes below this debugger
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        Ok(Capabilities::local())
+    }
+
+    /// Layout of the tree (only known by the root)
+    fn layout(&mut self) -> Result<TreeLayout> {
+        Err(anyhow!("This debugger is not the root of a tree"))
+    }
+
+    /// Evaluate an expression in each process (errors are returned as values)
+    fn eval(&mut self, expr: &str) -> Result<HashMap<u64, String>>;
+
+    /// Get the description of each process (rank, host, pid)
+    fn processes(&mut self) -> Result<HashMap<u64, ProcessInfo>>;
+
     /// Get Symbol table
     fn symbols(&mut self) -> Result<SymbolTable>;
 
+    /// Analyze threads blocked on pthread mutexes for each process
+    fn mutex_deadlocks(&mut self) -> Result<HashMap<u64, LockReport>>;
+
+    /// Get the blocking MPI calls of each process
+    fn mpi_waits(&mut self) -> Result<HashMap<u64, MpiRankState>>;
+
+    /// Shared libraries loaded by the processes with their build-ids
+    fn libraries(&mut self) -> Result<LibraryReport> {
+        Ok(LibraryReport::default())
+    }
+
     fn as_any_mut(&mut self) -> &mut dyn Any;
 
     // New method to downcast to TreeState
@@ -153,6 +229,15 @@ pub trait Debugger: Send + Any {
     }
 }
 
+/// A set of processes targeted by commands
+#[derive(Debug, Clone)]
+pub enum Targets {
+    /// Debugger ids as returned in the results
+    Ids(Vec<u64>),
+    /// MPI ranks (resolved to ids by the root)
+    Ranks(Vec<u32>),
+}
+
 pub struct DummyDebugger;
 
 impl Debugger for DummyDebugger {
@@ -190,11 +275,35 @@ impl Debugger for DummyDebugger {
         Ok(HashMap::new())
     }
 
+    #[allow(unused)]
+    fn process_snapshot(&mut self, id: u64) -> Result<Option<ProgramSnapshot>> {
+        Ok(None)
+    }
+
+    #[allow(unused)]
+    fn eval(&mut self, expr: &str) -> Result<HashMap<u64, String>> {
+        Ok(HashMap::new())
+    }
+
+    fn processes(&mut self) -> Result<HashMap<u64, ProcessInfo>> {
+        Ok(HashMap::new())
+    }
+
     /// Get Symbol table
     fn symbols(&mut self) -> Result<SymbolTable> {
         Err(anyhow!("Dummy debugger"))
     }
 
+    /// Analyze mutex deadlocks
+    fn mutex_deadlocks(&mut self) -> Result<HashMap<u64, LockReport>> {
+        Ok(HashMap::new())
+    }
+
+    /// Get blocking MPI calls
+    fn mpi_waits(&mut self) -> Result<HashMap<u64, MpiRankState>> {
+        Ok(HashMap::new())
+    }
+
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
@@ -9,10 +9,86 @@ use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
 use std::time::Instant;
 
+use crate::auth;
+use crate::binary;
 use crate::debugger::Debugger;
 use crate::metadata::*;
+use crate::pyframes::{self, PyFrame};
 use crate::tools::*;
 
+/// GDB `follow-fork-mode` (`parent` or `child`)
+pub const FOLLOW_FORK_ENV: &str = "GDBW_FOLLOW_FORK";
+
+/// Set to `off` to keep debugging both processes after a fork
+pub const DETACH_ON_FORK_ENV: &str = "GDBW_DETACH_ON_FORK";
+
+/// GDB executable of the leaves (`gdb` from the `PATH` by default)
+pub const GDB_PATH_ENV: &str = "GDBW_GDB";
+
+/// Extra options of GDB (e.g. `-nx`), one per line
+pub const GDB_ARGS_ENV: &str = "GDBW_GDB_ARGS";
+
+/// Command files sourced by GDB before loading the program, one per line
+pub const GDB_INIT_ENV: &str = "GDBW_GDB_INIT";
+
+/// GDB commands run before starting the program, one per line
+pub const GDB_COMMANDS_ENV: &str = "GDBW_GDB_COMMANDS";
+
+const DEFAULT_GDB: &str = "gdb";
+
+/// How GDB is run on a leaf, set by the root through the environment
+///
+/// This lets sites use the GDB of a module, skip or complete the
+/// `.gdbinit`, and set the sysroot or the pretty-printers of the program.
+#[derive(Debug, Clone)]
+pub struct GdbConfig {
+    pub path: String,
+    pub args: Vec<String>,
+    pub init_files: Vec<String>,
+    pub commands: Vec<String>,
+}
+
+impl GdbConfig {
+    fn env_lines(var: &str) -> Vec<String> {
+        std::env::var(var)
+            .unwrap_or_default()
+            .lines()
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    pub fn from_env() -> GdbConfig {
+        GdbConfig {
+            path: std::env::var(GDB_PATH_ENV).unwrap_or(DEFAULT_GDB.to_string()),
+            args: GdbConfig::env_lines(GDB_ARGS_ENV),
+            init_files: GdbConfig::env_lines(GDB_INIT_ENV),
+            commands: GdbConfig::env_lines(GDB_COMMANDS_ENV),
+        }
+    }
+}
+
+/// Number of instructions shown before and after the PC of frames without line info
+pub const DISASSEMBLE_ENV: &str = "GDBW_DISASSEMBLE";
+
+/// Symbols further than this from the PC are not disassembled from their start
+const MAX_DISASSEMBLY_SPAN: u64 = 4096;
+
+/// Upper bound of the size of an instruction on the supported architectures
+const MAX_INSTRUCTION_SIZE: u64 = 15;
+
+fn disassembly_window() -> usize {
+    std::env::var(DISASSEMBLE_ENV)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0)
+}
+
+/// Parse a GDB address such as `0x00007ffff7e2d1a4`
+fn parse_address(addr: &str) -> Option<u64> {
+    u64::from_str_radix(addr.strip_prefix("0x")?, 16).ok()
+}
+
 enum GdbMiRemote {
     Command(Vec<String>),
     #[allow(unused)]
@@ -22,10 +98,17 @@ enum GdbMiRemote {
 }
 
 impl GdbMiRemote {
-    fn gdbargs(&self) -> Vec<String> {
+    fn gdbargs(&self, config: &GdbConfig) -> Vec<String> {
         let mut ret = Vec::new();
 
         ret.push("--interpreter=mi3".to_string());
+        ret.extend(config.args.iter().cloned());
+
+        for init in config.init_files.iter() {
+            /* After the gdbinit files, before loading the program */
+            ret.push("-ix".to_string());
+            ret.push(init.clone());
+        }
 
         match self {
             GdbMiRemote::Command(cmd) => {
@@ -109,6 +192,18 @@ struct GdbFileSymbols {
     symbols: Vec<GdbSymbol>,
 }
 
+/// Tags the output of [INLINED_FRAMES_SCRIPT]
+const INLINED_FRAMES_TAG: &str = "inlined:";
+
+/// Print the frame types of the selected thread, `1` for inlined frames
+const INLINED_FRAMES_SCRIPT: &str = "f = gdb.newest_frame()
+t = ''
+while f is not None:
+    t += '1' if f.type() == gdb.INLINE_FRAME else '0'
+    f = f.older()
+print('inlined:' + t)
+";
+
 #[derive(Deserialize, Debug)]
 struct GdbSymbolRecord {
     debug: Option<Vec<GdbFileSymbols>>,
@@ -123,6 +218,68 @@ pub struct GdbMiState {
     gdblog: Vec<String>,
     resps: HashMap<u64, GdbMiCommandResponse>,
     runstate: Option<RunState>,
+    /// Offset of the frame addresses in their symbol
+    symbol_offsets: HashMap<u64, Option<u64>>,
+    /// Can GDB run Python commands (None until checked)
+    python: Option<bool>,
+    /// Are the Python helpers of libpython usable (None until checked)
+    python_helpers: Option<bool>,
+    /// Entry of [pyframes::FRAME_LAYOUTS] matching the interpreter
+    python_layout: Option<usize>,
+    /// Processes debugged by GDB by inferior number
+    inferiors: HashMap<u32, Inferior>,
+}
+
+/// (name, is argument, value) of the variables listed by `-stack-list-variables`
+///
+/// Values of structures and strings hold braces and escaped quotes.
+fn parse_locals(resp: &str) -> Vec<(String, bool, String)> {
+    let mut ret = Vec::new();
+
+    for var in split_gdb_tuples(resp) {
+        let entries = parse_gdb_fields(&var);
+
+        if let (Some(name), Some(value)) = (entries.get("name"), entries.get("value")) {
+            let is_arg = entries.get("arg").is_some_and(|v| v == "1");
+            ret.push((name.to_string(), is_arg, value.to_string()));
+        }
+    }
+
+    ret
+}
+
+/// Address of a pointer printed as `(void *) 0x0`
+fn pointer_value(value: &str) -> String {
+    value.rsplit(' ').next().unwrap_or_default().to_string()
+}
+
+/// Pair the answers of `-data-list-register-names` and `-data-list-register-values`
+/// and keep the general purpose registers
+fn parse_registers(names: &str, values: &str) -> Result<Vec<(String, String)>> {
+    let re = Regex::new("\"([^\"]*)\"")?;
+    let names: Vec<String> = re
+        .captures_iter(names)
+        .flat_map(|v| v.get(1))
+        .map(|v| v.as_str().to_string())
+        .collect();
+
+    let values: HashMap<usize, String> = split_gdb_tuples(values)
+        .iter()
+        .map(|v| parse_gdb_equal_list(v))
+        .flat_map(|v| {
+            let num = v.get("number")?.parse::<usize>().ok()?;
+            Some((num, v.get("value")?.to_string()))
+        })
+        .collect();
+
+    /* GDB lists the general purpose registers first */
+    let registers = names
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| !name.is_empty())
+        .flat_map(|(num, name)| Some((name.to_string(), values.get(&num)?.to_string())));
+
+    Ok(CrashInfo::general_registers(registers))
 }
 
 impl GdbMiState {
@@ -164,6 +321,48 @@ impl GdbMiState {
         Err(anyhow!("Failed to lock"))
     }
 
+    fn track_inferiors(state: Arc<Mutex<GdbMiState>>, record: &str) -> Result<()> {
+        if let Ok(ls) = state.lock().as_mut() {
+            Inferior::track(&mut ls.inferiors, record);
+            return Ok(());
+        }
+
+        Err(anyhow!("Failed to lock"))
+    }
+
+    fn inferiors(state: &Arc<Mutex<GdbMiState>>) -> HashMap<u32, Inferior> {
+        state
+            .lock()
+            .map(|ls| ls.inferiors.clone())
+            .unwrap_or_default()
+    }
+
+    /// Is this the exit of a forked process while the main one is alive
+    ///
+    /// GDB reports the exit of a forked process as a stop of the whole
+    /// program (its `=thread-group-exited` comes first). The exit of the
+    /// main process is always reported, even if some of its children live.
+    fn other_inferior_exited(state: Arc<Mutex<GdbMiState>>, rstate: &RunState) -> Result<bool> {
+        if let RunState::Stopped(stop) = rstate {
+            if stop.exited() {
+                let inferiors = GdbMiState::inferiors(&state);
+                return Ok(Inferior::primary(&inferiors).is_some_and(|inf| inf.is_live()));
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Continue all the inferiors without waiting for the answer
+    fn resume_all(state: Arc<Mutex<GdbMiState>>) -> Result<()> {
+        if let Ok(ls) = state.lock().as_mut() {
+            ls.gdb_in.write_all(b"-exec-continue\n")?;
+            return Ok(());
+        }
+
+        Err(anyhow!("Failed to lock"))
+    }
+
     fn isrunning(state: Arc<Mutex<GdbMiState>>) -> Result<bool> {
         if let Ok(ls) = state.lock() {
             if let Some(RunState::Stopped(stop_state)) = &ls.runstate {
@@ -203,11 +402,19 @@ impl GdbMiState {
                 GdbMiState::pushresp(state.clone(), id, GdbMiCommandResponse::new(resp.as_str()))?;
             } else if let Some(srstate) = line.strip_prefix("*") {
                 let rstate = RunState::new_from_gdb(srstate)?;
-                GdbMiState::setrunstate(state.clone(), rstate)?;
+
+                if GdbMiState::other_inferior_exited(state.clone(), &rstate)? {
+                    /* Keep the remaining processes running */
+                    GdbMiState::resume_all(state.clone())?;
+                } else {
+                    GdbMiState::setrunstate(state.clone(), rstate)?;
+                }
             } else if let Some(_) = line.strip_prefix("~") {
                 /* SKIPPED */
-            } else if let Some(_) = line.strip_prefix("=") {
-                /* SKIPPED */
+            } else if let Some(record) = line.strip_prefix("=") {
+                GdbMiState::track_inferiors(state.clone(), record.trim_end())?;
+            } else if line.starts_with("^") {
+                /* SKIPPED : result of a command sent without token */
             } else if let Some(_) = line.strip_prefix("&") {
                 /* SKIPPED */
             } else if line.starts_with("(gdb)") {
@@ -286,22 +493,101 @@ impl GdbMiState {
         Ok(cap)
     }
 
+    fn thread_info(state: Arc<Mutex<GdbMiState>>) -> Result<HashMap<u32, ThreadInfo>> {
+        let resp = GdbMiState::command(state, "-thread-info")?;
+
+        let threads = resp
+            .split_once("threads=")
+            .map(|(_, t)| t)
+            .unwrap_or_default();
+
+        let ret = split_gdb_tuples(threads)
+            .iter()
+            .flat_map(|v| ThreadInfo::new(v))
+            .map(|v| (v.id, v))
+            .collect();
+
+        Ok(ret)
+    }
+
     fn backtrace(state: Arc<Mutex<GdbMiState>>) -> Result<Vec<DebugFrame>> {
-        let resp = GdbMiState::command(state, "-stack-list-frames 0 1000")?;
+        let resp = GdbMiState::command(state.clone(), "-stack-list-frames 0 1000")?;
 
         let re = Regex::new("frame=\\{([^\\}]+)\\}")?;
 
-        let cap: Vec<DebugFrame> = re
+        let mut cap: Vec<DebugFrame> = re
             .captures_iter(resp.as_str())
             .flat_map(|v| v.get(1))
             .flat_map(|v| DebugFrame::new(v.as_str()))
             .collect();
 
+        match GdbMiState::inlined_frames(state) {
+            Some(inlined) => {
+                for (f, i) in cap.iter_mut().zip(inlined) {
+                    f.inlined = i;
+                }
+            }
+            None => DebugFrame::mark_inlined(&mut cap),
+        }
+
         Ok(cap)
     }
 
-    fn symbols(state: Arc<Mutex<GdbMiState>>) -> Result<SymbolTable> {
-        let mut ret = SymbolTable::default();
+    /// Which frames of the selected thread are inlined, innermost first
+    ///
+    /// None when GDB was built without Python.
+    fn inlined_frames(state: Arc<Mutex<GdbMiState>>) -> Option<Vec<bool>> {
+        if state.lock().ok().and_then(|st| st.python) == Some(false) {
+            return None;
+        }
+
+        let out = GdbMiState::console(
+            state.clone(),
+            &pyframes::exec_command(INLINED_FRAMES_SCRIPT),
+        );
+
+        if let Ok(mut st) = state.lock() {
+            st.python = Some(out.is_ok());
+        }
+
+        let out = out.ok()?;
+        let line = out.lines().find(|l| l.starts_with(INLINED_FRAMES_TAG))?;
+
+        Some(
+            line[INLINED_FRAMES_TAG.len()..]
+                .chars()
+                .map(|c| c == '1')
+                .collect(),
+        )
+    }
+
+    /// Paths of the shared libraries loaded by the inferior
+    fn shared_libraries(state: Arc<Mutex<GdbMiState>>) -> Result<Vec<String>> {
+        let resp = GdbMiState::command(state, "-file-list-shared-libraries")?;
+
+        let re = Regex::new("host-name=\"([^\"]+)\"")?;
+
+        Ok(re
+            .captures_iter(&resp)
+            .flat_map(|v| v.get(1))
+            .map(|v| v.as_str().to_string())
+            .collect())
+    }
+
+    /// Path of the program run by the inferior
+    fn executable(state: Arc<Mutex<GdbMiState>>) -> Result<String> {
+        let resp = GdbMiState::command(state, "-list-thread-groups")?;
+
+        let re = Regex::new("executable=\"([^\"]+)\"")?;
+
+        re.captures(&resp)
+            .and_then(|c| c.get(1))
+            .map(|v| v.as_str().to_string())
+            .ok_or(anyhow!("GDB did not report the executable"))
+    }
+
+    fn symbols(state: Arc<Mutex<GdbMiState>>) -> Result<HashMap<String, Vec<Symbol>>> {
+        let mut ret = HashMap::new();
 
         let resp = GdbMiState::command(state, "-symbol-info-functions --include-nondebug")?;
 
@@ -312,7 +598,7 @@ impl GdbMiState {
 
             if let Some(per_file) = symbs.debug {
                 for f in per_file {
-                    ret.symbols_per_file.insert(
+                    ret.insert(
                         f.fullname,
                         f.symbols.iter().map(|v| v.to_common_symbol()).collect(),
                     );
@@ -320,7 +606,7 @@ impl GdbMiState {
             }
 
             if let Some(nodebug) = symbs.nondebug {
-                ret.symbols_per_file.insert(
+                ret.insert(
                     "Unknown".to_string(),
                     nodebug.iter().map(|v| v.to_common_symbol()).collect(),
                 );
@@ -330,64 +616,462 @@ impl GdbMiState {
         Ok(ret)
     }
 
-    #[allow(unused)]
     fn locals(
         state: Arc<Mutex<GdbMiState>>,
         threadid: u32,
         frameid: u32,
     ) -> Result<Vec<(String, bool, String)>> {
-        let mut ret = Vec::new();
         let cmd = format!(
             "-stack-list-variables --thread {} --frame {} --all-values",
             threadid, frameid
         );
         let resp = GdbMiState::command(state, &cmd)?;
 
-        let groups = extract_gdb_group(&resp);
+        Ok(parse_locals(&resp))
+    }
 
-        for g in groups {
-            let entries = parse_gdb_equal_list(&g);
+    /// Run a CLI command and return what it printed
+    fn console(state: Arc<Mutex<GdbMiState>>, command: &str) -> Result<String> {
+        /* Drop what GDB printed so far */
+        GdbMiState::getlog(state.clone())?;
 
-            if let (Some(name), Some(value)) = (entries.get("name"), entries.get("value")) {
-                let is_arg = if let Some(arg) = entries.get("arg") {
-                    arg == "1"
-                } else {
-                    false
-                };
+        let cmd = format!(
+            "-interpreter-exec console \"{}\"",
+            command.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        GdbMiState::command(state.clone(), &cmd)?;
+
+        Ok(GdbMiState::getlog(state)?
+            .iter()
+            .map(|v| unescape_gdb_string(v))
+            .collect())
+    }
+
+    /// Load [pyframes::HELPER_SCRIPT] and tell if libpython provides its helpers
+    fn load_python_helpers(state: Arc<Mutex<GdbMiState>>) -> Result<bool> {
+        if let Some(loaded) = state.lock().ok().and_then(|st| st.python_helpers) {
+            return Ok(loaded);
+        }
 
-                ret.push((name.to_string(), is_arg, value.to_string()));
+        GdbMiState::console(state.clone(), &pyframes::helper_command())?;
+
+        /* The helpers can only be found in the context of a thread */
+        let out = GdbMiState::console(state.clone(), "python _snapped_pyframes()")?;
+        let loaded = !out.contains(pyframes::NO_HELPERS);
+
+        if let Ok(mut st) = state.lock() {
+            st.python_helpers = Some(loaded);
+        }
+
+        Ok(loaded)
+    }
+
+    /// Read the Python frame evaluated by a C frame from its arguments
+    fn read_python_frame(
+        state: Arc<Mutex<GdbMiState>>,
+        threadid: u32,
+        level: u32,
+        layout: usize,
+    ) -> Result<PyFrame> {
+        let (code, line) = pyframes::FRAME_LAYOUTS[layout];
+
+        let string = |field: &str| -> Result<String> {
+            let cmd = format!(
+                "-data-evaluate-expression --thread {} --frame {} \"{}\"",
+                threadid,
+                level,
+                pyframes::ascii_expr(&format!("{}->{}", code, field))
+            );
+            let resp = GdbMiState::command(state.clone(), &cmd)?;
+
+            /* Printed as `value="0x7ffff7 \"name\""` */
+            match (resp.find("\\\""), resp.rfind("\\\"")) {
+                (Some(start), Some(end)) if start + 2 < end => {
+                    Ok(unescape_gdb_string(&resp[start + 2..end]))
+                }
+                _ => Err(anyhow!("No string in {}", resp)),
+            }
+        };
+
+        let func = string("co_name")?;
+        let file = string("co_filename")?;
+
+        /* Fallback on the first line of the function */
+        let line = GdbMiState::evaluate(state.clone(), threadid, level, line)
+            .or_else(|_| {
+                GdbMiState::evaluate(
+                    state.clone(),
+                    threadid,
+                    level,
+                    &format!("{}->co_firstlineno", code),
+                )
+            })?
+            .parse::<u32>()
+            .unwrap_or(0);
+
+        Ok(PyFrame {
+            level,
+            func,
+            file,
+            line,
+        })
+    }
+
+    /// Python frames of the selected thread, innermost first
+    fn python_frames(
+        state: Arc<Mutex<GdbMiState>>,
+        threadid: u32,
+        bt: &[DebugFrame],
+    ) -> Result<Vec<PyFrame>> {
+        if !bt.iter().any(pyframes::is_eval_frame) {
+            return Ok(Vec::new());
+        }
+
+        if GdbMiState::load_python_helpers(state.clone())? {
+            let out = GdbMiState::console(state, "python _snapped_pyframes()")?;
+            return Ok(out.lines().flat_map(PyFrame::new).collect());
+        }
+
+        let mut ret = Vec::new();
+
+        for frame in bt.iter().filter(|f| pyframes::is_eval_frame(f)) {
+            let known = state.lock().ok().and_then(|st| st.python_layout);
+
+            let layouts: Vec<usize> = match known {
+                Some(l) => vec![l],
+                None => (0..pyframes::FRAME_LAYOUTS.len()).collect(),
+            };
+
+            for l in layouts {
+                if let Ok(pf) =
+                    GdbMiState::read_python_frame(state.clone(), threadid, frame.level, l)
+                {
+                    if let Ok(mut st) = state.lock() {
+                        st.python_layout = Some(l);
+                    }
+                    ret.push(pf);
+                    break;
+                }
             }
         }
 
         Ok(ret)
     }
 
-    fn snapshot(state: Arc<Mutex<GdbMiState>>) -> Result<ProgramSnapshot> {
+    /// Snapshot all threads, `with_locals` also retrieves the args and locals of each frame
+    fn snapshot(state: Arc<Mutex<GdbMiState>>, with_locals: bool) -> Result<ProgramSnapshot> {
         let mut ret: HashMap<u32, Vec<DebugFrame>> = HashMap::new();
 
         let threads = GdbMiState::list_thread_id(state.clone())?;
 
         for th in threads {
             GdbMiState::select_thread(state.clone(), th)?;
-            let bt = GdbMiState::backtrace(state.clone())?;
+            let mut bt = GdbMiState::backtrace(state.clone())?;
+
+            if with_locals {
+                for frame in bt.iter_mut() {
+                    if let Ok(vars) = GdbMiState::locals(state.clone(), th, frame.level) {
+                        frame.attach_locals(vars);
+                    }
+                }
+            }
 
-            //for frame in bt.iter_mut() {
-            //    if let Ok(vars) = GdbMiState::locals(state.clone(), th, frame.level) {
-            //        frame.attach_locals(vars);
-            //    }
-            //}
+            if pyframes::enabled() {
+                match GdbMiState::python_frames(state.clone(), th, &bt) {
+                    Ok(pf) => bt = pyframes::decorate(bt, &pf),
+                    Err(e) => log::debug!("No Python frames for thread {} : {}", th, e),
+                }
+            }
+
+            /* The disassembly only goes with the full state of the process */
+            let window = if with_locals { disassembly_window() } else { 0 };
+            GdbMiState::resolve_frames(state.clone(), &mut bt, window);
 
             ret.insert(th, bt);
         }
 
+        let threads = GdbMiState::thread_info(state.clone()).unwrap_or_default();
+
         let stop_state: Option<StopReason> = GdbMiState::get_stop_state(&state);
 
+        let crash = match &stop_state {
+            Some(stop) if stop.is_fault() => GdbMiState::crash_info(state.clone(), stop).ok(),
+            _ => None,
+        };
+
         Ok(ProgramSnapshot {
             state: ret,
             stop_state,
+            threads,
+            crash,
+            inferiors: GdbMiState::inferiors(&state),
         })
     }
 
+    /// Disassemble the given `-data-disassemble` range
+    fn disassemble(state: Arc<Mutex<GdbMiState>>, range: &str) -> Result<Vec<Instruction>> {
+        let resp = GdbMiState::command(state, &format!("-data-disassemble {} -- 0", range))?;
+
+        Ok(split_gdb_tuples(&resp)
+            .iter()
+            .map(|v| parse_gdb_equal_list(v))
+            .flat_map(|insn| {
+                Some(Instruction {
+                    address: insn.get("address").cloned().unwrap_or_default(),
+                    func: insn.get("func-name").cloned(),
+                    offset: insn.get("offset").and_then(|v| v.parse::<u64>().ok()),
+                    text: insn.get("inst")?.to_string(),
+                })
+            })
+            .collect())
+    }
+
+    /// Instruction the innermost frame of a thread is on
+    fn current_instruction(state: Arc<Mutex<GdbMiState>>, threadid: u32) -> Result<Instruction> {
+        let range = format!("--thread {} --frame 0 -s $pc -e \"$pc + 1\"", threadid);
+
+        GdbMiState::disassemble(state, &range)?
+            .into_iter()
+            .next()
+            .ok_or(anyhow!("Failed to disassemble the program counter"))
+    }
+
+    /// Offset of an address in its symbol, cached as code does not move
+    fn symbol_offset(state: Arc<Mutex<GdbMiState>>, addr: u64) -> Option<u64> {
+        if let Ok(st) = state.lock() {
+            if let Some(off) = st.symbol_offsets.get(&addr) {
+                return *off;
+            }
+        }
+
+        let range = format!("-s {:#x} -e {:#x}", addr, addr + 1);
+
+        let off = GdbMiState::disassemble(state.clone(), &range)
+            .ok()
+            .and_then(|v| v.first().and_then(|i| i.offset));
+
+        if let Ok(mut st) = state.lock() {
+            st.symbol_offsets.insert(addr, off);
+        }
+
+        off
+    }
+
+    /// Instructions within `window` of the PC of a frame
+    ///
+    /// Disassembly starts from the symbol so that variable length
+    /// instructions are decoded from a valid boundary.
+    fn disassembly_window(
+        state: Arc<Mutex<GdbMiState>>,
+        addr: u64,
+        offset: u64,
+        window: usize,
+    ) -> Result<Vec<Instruction>> {
+        let start = if offset <= MAX_DISASSEMBLY_SPAN {
+            addr - offset
+        } else {
+            addr
+        };
+        let end = addr + (window as u64 + 1) * MAX_INSTRUCTION_SIZE;
+
+        let insns = GdbMiState::disassemble(state, &format!("-s {:#x} -e {:#x}", start, end))?;
+
+        let pc = insns
+            .iter()
+            .position(|i| parse_address(&i.address) == Some(addr))
+            .ok_or(anyhow!("PC {:#x} is not on an instruction boundary", addr))?;
+
+        Ok(insns
+            .into_iter()
+            .skip(pc.saturating_sub(window))
+            .take(2 * window + 1)
+            .collect())
+    }
+
+    /// Locate the frames without line info in their symbol
+    fn resolve_frames(state: Arc<Mutex<GdbMiState>>, bt: &mut [DebugFrame], window: usize) {
+        for frame in bt.iter_mut().filter(|f| f.line.is_none()) {
+            let addr = match parse_address(&frame.addr) {
+                Some(a) => a,
+                None => continue,
+            };
+
+            frame.offset = GdbMiState::symbol_offset(state.clone(), addr);
+
+            if window > 0 {
+                frame.disassembly = GdbMiState::disassembly_window(
+                    state.clone(),
+                    addr,
+                    frame.offset.unwrap_or(0),
+                    window,
+                )
+                .ok();
+            }
+        }
+    }
+
+    /// General purpose registers of the innermost frame of a thread
+    fn registers(state: Arc<Mutex<GdbMiState>>, threadid: u32) -> Result<Vec<(String, String)>> {
+        let names = GdbMiState::command(state.clone(), "-data-list-register-names")?;
+
+        let cmd = format!(
+            "-data-list-register-values --thread {} --frame 0 x",
+            threadid
+        );
+        let values = GdbMiState::command(state, &cmd)?;
+
+        parse_registers(&names, &values)
+    }
+
+    /// Analyze the fault which stopped the program
+    fn crash_info(state: Arc<Mutex<GdbMiState>>, stop: &StopReason) -> Result<CrashInfo> {
+        let th = match stop.thread_id {
+            Some(th) => th,
+            None => *GdbMiState::list_thread_id(state.clone())?
+                .first()
+                .ok_or(anyhow!("No thread to analyze"))?,
+        };
+
+        let fault_addr = GdbMiState::evaluate(
+            state.clone(),
+            th,
+            0,
+            "$_siginfo._sifields._sigfault.si_addr",
+        )
+        .ok()
+        .map(|v| pointer_value(&v));
+
+        let code = GdbMiState::evaluate(state.clone(), th, 0, "$_siginfo.si_code")
+            .ok()
+            .and_then(|v| v.parse::<i32>().ok());
+
+        Ok(CrashInfo {
+            signal: stop.signal_name.clone().unwrap_or_default(),
+            fault_addr,
+            code,
+            instruction: GdbMiState::current_instruction(state.clone(), th).ok(),
+            registers: GdbMiState::registers(state, th).unwrap_or_default(),
+        })
+    }
+
+    /// Evaluate an expression in the context of a given thread and frame
+    fn evaluate(
+        state: Arc<Mutex<GdbMiState>>,
+        threadid: u32,
+        frameid: u32,
+        expr: &str,
+    ) -> Result<String> {
+        let cmd = format!(
+            "-data-evaluate-expression --thread {} --frame {} \"{}\"",
+            threadid,
+            frameid,
+            expr.replace('"', "\\\"")
+        );
+        let resp = GdbMiState::command(state, &cmd)?;
+
+        parse_gdb_equal_list(&resp)
+            .get("value")
+            .cloned()
+            .ok_or(anyhow!("No value returned when evaluating {}", expr))
+    }
+
+    /// Find the mutex a thread is waiting on and its owner
+    ///
+    /// The mutex is read from the `mutex` argument of `pthread_mutex_lock` and if
+    /// not available from the `futex` argument of `__lll_lock_wait` which points to
+    /// `__data.__lock`, the first member of `pthread_mutex_t`. This requires the
+    /// debug information of the libc to be available.
+    fn lock_wait(
+        state: Arc<Mutex<GdbMiState>>,
+        threadid: u32,
+        bt: &[DebugFrame],
+    ) -> Option<(String, Option<u64>)> {
+        for func in MUTEX_LOCK_FUNCTIONS.iter() {
+            let frame = match bt.iter().find(|f| f.func == *func) {
+                Some(f) => f,
+                None => continue,
+            };
+
+            let mutex = if *func == "__lll_lock_wait" {
+                "((pthread_mutex_t *)futex)"
+            } else {
+                "mutex"
+            };
+
+            let addr = GdbMiState::evaluate(
+                state.clone(),
+                threadid,
+                frame.level,
+                &format!("(unsigned long){}", mutex),
+            );
+
+            if let Ok(addr) = addr {
+                let owner = GdbMiState::evaluate(
+                    state.clone(),
+                    threadid,
+                    frame.level,
+                    &format!("{}->__data.__owner", mutex),
+                )
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|v| *v != 0);
+
+                let addr = addr
+                    .parse::<u64>()
+                    .map(|v| format!("{:#x}", v))
+                    .unwrap_or(addr);
+
+                return Some((addr, owner));
+            }
+        }
+
+        None
+    }
+
+    fn lock_report(state: Arc<Mutex<GdbMiState>>) -> Result<LockReport> {
+        let threads = GdbMiState::thread_info(state.clone())?;
+        let mut waits = Vec::new();
+
+        let lwp_to_thread: HashMap<u64, u32> = threads
+            .values()
+            .filter_map(|t| t.lwp().map(|l| (l, t.id)))
+            .collect();
+
+        for th in GdbMiState::list_thread_id(state.clone())? {
+            GdbMiState::select_thread(state.clone(), th)?;
+            let bt = GdbMiState::backtrace(state.clone())?;
+
+            if let Some((mutex, owner_lwp)) = GdbMiState::lock_wait(state.clone(), th, &bt) {
+                waits.push(LockWait {
+                    thread: th,
+                    mutex,
+                    owner_lwp,
+                    owner: owner_lwp.and_then(|l| lwp_to_thread.get(&l).cloned()),
+                });
+            }
+        }
+
+        Ok(LockReport::new(waits))
+    }
+
+    /// List the blocking MPI calls of all threads with their peer, tag and communicator
+    fn mpi_waits(state: Arc<Mutex<GdbMiState>>) -> Result<Vec<MpiWait>> {
+        let mut ret = Vec::new();
+
+        for th in GdbMiState::list_thread_id(state.clone())? {
+            GdbMiState::select_thread(state.clone(), th)?;
+            let bt = GdbMiState::backtrace(state.clone())?;
+
+            if let Some((frame, peer)) = MpiWait::find_call(&bt) {
+                let eval =
+                    |expr: &str| GdbMiState::evaluate(state.clone(), th, frame.level, expr).ok();
+                ret.push(MpiWait::new(th, frame, peer, eval));
+            }
+        }
+
+        Ok(ret)
+    }
+
     fn select_thread(state: Arc<Mutex<GdbMiState>>, id: u32) -> Result<()> {
         let cmd = format!("-thread-select {}", id);
         GdbMiState::command(state, cmd.as_str())?;
@@ -417,6 +1101,11 @@ impl GdbMiState {
                 gdblog: Vec::new(),
                 resps: HashMap::new(),
                 runstate: None,
+                symbol_offsets: HashMap::new(),
+                python: None,
+                python_helpers: None,
+                python_layout: None,
+                inferiors: HashMap::new(),
             };
 
             let ret = Arc::new(Mutex::new(ret));
@@ -433,8 +1122,11 @@ impl GdbMiState {
 pub struct GdbMi {
     id: u64,
     target: GdbMiRemote,
+    config: GdbConfig,
     state: Option<Arc<Mutex<GdbMiState>>>,
     child_proc: Option<Child>,
+    /* Build-ids of the binaries already read (None when they have none) */
+    build_ids: HashMap<String, Option<String>>,
 }
 
 impl Debugger for GdbMi {
@@ -452,6 +1144,10 @@ impl Debugger for GdbMi {
 
     /// Start the debugged program (program is not started by default)
     fn start(&mut self) -> Result<()> {
+        if !self.state()?.is_empty() {
+            /* Already started (e.g. late joiner starting on its own) */
+            return Ok(());
+        }
         self.cmd("-exec-run")?;
         Ok(())
     }
@@ -512,7 +1208,7 @@ impl Debugger for GdbMi {
                 return Ok(ProgramSnapshot::generate_components(ret));
             }
 
-            let snap = GdbMiState::snapshot(st.clone())?;
+            let snap = GdbMiState::snapshot(st.clone(), false)?;
 
             let mut ret = HashMap::new();
             ret.insert(self.id, snap);
@@ -524,14 +1220,119 @@ impl Debugger for GdbMi {
         Err(anyhow!("Program is not running"))
     }
 
+    /// Full state of this process with the args and locals of every frame
+    fn process_snapshot(&mut self, id: u64) -> Result<Option<ProgramSnapshot>> {
+        if id != self.id {
+            return Ok(None);
+        }
+
+        if self.id_is_running(self.id)? {
+            return Err(anyhow!("Process {} has to be stopped first", self.id));
+        }
+
+        let exited = self.id_is_exited(self.id)?;
+
+        if let Some(st) = &self.state {
+            if exited {
+                let stop_state: Option<StopReason> = GdbMiState::get_stop_state(st);
+                return Ok(Some(ProgramSnapshot::exited(stop_state)));
+            }
+
+            return Ok(Some(GdbMiState::snapshot(st.clone(), true)?));
+        }
+
+        Err(anyhow!("Program is not running"))
+    }
+
+    /// Detach from the program so that it is not left stopped and exit GDB
+    fn detach(&mut self) -> Result<()> {
+        if self.id_is_running(self.id)? {
+            self.stop()?;
+
+            /* The interrupt is asynchronous wait for the stop */
+            for _ in 0..10 {
+                if self.id_is_stopped(self.id)? {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+        }
+
+        let live: Vec<String> = match &self.state {
+            Some(st) => GdbMiState::inferiors(st)
+                .values()
+                .filter(|inf| inf.is_live())
+                .map(|inf| inf.group.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let ret = if live.len() > 1 {
+            /* Detach all the forked processes not only the current one */
+            live.iter()
+                .map(|g| self.cmd(&format!("-target-detach {}", g)))
+                .collect::<Result<Vec<String>>>()
+                .map(|v| v.join(""))
+        } else {
+            self.cmd("-target-detach")
+        };
+
+        if let Some(child) = &mut self.child_proc {
+            let _ = child.kill();
+        }
+
+        ret.map(|_| ())
+    }
+
+    /// Evaluate an expression in the currently selected frame
+    fn eval(&mut self, expr: &str) -> Result<HashMap<u64, String>> {
+        if self.id_is_running(self.id)? {
+            return Err(anyhow!(
+                "Expressions can only be evaluated on a stopped target"
+            ));
+        }
+
+        let cmd = format!(
+            "-data-evaluate-expression \"{}\"",
+            expr.replace('"', "\\\"")
+        );
+
+        let value = match self.cmd(&cmd) {
+            Ok(resp) => parse_gdb_equal_list(&resp)
+                .get("value")
+                .cloned()
+                .unwrap_or_default(),
+            Err(e) => format!("<{}>", e),
+        };
+
+        let mut ret = HashMap::new();
+        ret.insert(self.id, value);
+
+        Ok(ret)
+    }
+
+    /// Describe the process being debugged
+    fn processes(&mut self) -> Result<HashMap<u64, ProcessInfo>> {
+        let mut ret = HashMap::new();
+        ret.insert(self.id, ProcessInfo::default()?);
+        Ok(ret)
+    }
+
     /// Get the symbol table from the target split it per file
     fn symbols(&mut self) -> Result<SymbolTable> {
         if self.id_is_running(self.id)? {
             return Err(anyhow!("Symbols can only be retrieved on a stopped target"));
         }
 
-        if let Some(st) = &self.state {
-            return GdbMiState::symbols(st.clone());
+        if let Some(st) = self.state.clone() {
+            let path = GdbMiState::executable(st.clone())?;
+
+            return Ok(SymbolTable::from_binary(BinarySymbols {
+                build_id: self.build_id(&path),
+                ids: vec![self.id],
+                symbols_per_file: GdbMiState::symbols(st)?,
+                path,
+            }));
         }
 
         Err(anyhow!("No GDB state was available to retrieve symbols"))
@@ -540,19 +1341,114 @@ impl Debugger for GdbMi {
     fn count(&mut self) -> Result<u64> {
         Ok(1)
     }
+
+    /// Mutex wait-for analysis (only reported if some threads are blocked)
+    fn mutex_deadlocks(&mut self) -> Result<HashMap<u64, LockReport>> {
+        if self.id_is_running(self.id)? {
+            return Err(anyhow!("Locks can only be analyzed on a stopped target"));
+        }
+
+        let mut ret = HashMap::new();
+
+        if self.id_is_exited(self.id)? {
+            return Ok(ret);
+        }
+
+        if let Some(st) = &self.state {
+            let report = GdbMiState::lock_report(st.clone())?;
+
+            if !report.waits.is_empty() {
+                ret.insert(self.id, report);
+            }
+
+            return Ok(ret);
+        }
+
+        Err(anyhow!("No GDB state was available to analyze locks"))
+    }
+
+    /// Shared libraries of this process, build-ids are read once per path
+    fn libraries(&mut self) -> Result<LibraryReport> {
+        if self.id_is_running(self.id)? {
+            return Err(anyhow!("Libraries can only be listed on a stopped target"));
+        }
+
+        let mut ret = LibraryReport::default();
+
+        if self.id_is_exited(self.id)? {
+            return Ok(ret);
+        }
+
+        let st = self
+            .state
+            .clone()
+            .ok_or(anyhow!("No GDB state was available to list libraries"))?;
+
+        for path in GdbMiState::shared_libraries(st)? {
+            let build_id = self.build_id(&path);
+            ret.add(&path, build_id, self.id);
+        }
+
+        Ok(ret)
+    }
+
+    /// Blocking MPI calls of this process
+    fn mpi_waits(&mut self) -> Result<HashMap<u64, MpiRankState>> {
+        if self.id_is_running(self.id)? {
+            return Err(anyhow!(
+                "MPI calls can only be analyzed on a stopped target"
+            ));
+        }
+
+        let exited = self.id_is_exited(self.id)?;
+
+        let waits = match (&self.state, exited) {
+            (Some(st), false) => GdbMiState::mpi_waits(st.clone())?,
+            (Some(_), true) => Vec::new(),
+            (None, _) => return Err(anyhow!("No GDB state was available to analyze MPI calls")),
+        };
+
+        let mut ret = HashMap::new();
+        ret.insert(
+            self.id,
+            MpiRankState {
+                rank: ProcessInfo::mpirank_from_env(),
+                exited,
+                waits,
+            },
+        );
+
+        Ok(ret)
+    }
 }
 
 impl GdbMi {
+    /// Build-id of a binary, read once per path
+    fn build_id(&mut self, path: &str) -> Option<String> {
+        self.build_ids
+            .entry(path.to_string())
+            .or_insert_with(|| {
+                binary::build_id(path).unwrap_or_else(|e| {
+                    log::debug!("No build-id for {} : {}", path, e);
+                    None
+                })
+            })
+            .clone()
+    }
+
     fn _start_gdb(&mut self) -> Result<()> {
-        let gdbargs = self.target.gdbargs();
+        let gdbargs = self.target.gdbargs(&self.config);
 
-        log::debug!("{:?}", gdbargs);
+        log::debug!("{} {:?}", self.config.path, gdbargs);
 
-        let mut command = Command::new("gdb")
+        let mut command = Command::new(&self.config.path)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .args(&gdbargs)
-            .spawn()?;
+            /* The program inherits the environment of GDB, keep the secret of the tree out */
+            .env_remove(auth::TOKEN_ENV)
+            .spawn()
+            .map_err(|e| anyhow!("Failed to run {} : {}", self.config.path, e))?;
 
         let state = GdbMiState::new(command.stdin.take(), command.stdout.take())?;
 
@@ -599,9 +1495,11 @@ impl GdbMi {
         let cmd: Vec<String> = cmd.iter().map(|v| v.to_string()).collect();
         let mut ret = GdbMi {
             target: GdbMiRemote::Command(cmd),
+            config: GdbConfig::from_env(),
             state: None,
             id: 0,
             child_proc: None,
+            build_ids: HashMap::new(),
         };
 
         ret._start_gdb()?;
@@ -609,6 +1507,24 @@ impl GdbMi {
         ret.cmd("-gdb-set mi-async on")?;
         ret.cmd("-enable-pretty-printing")?;
 
+        if let Ok(mode) = std::env::var(FOLLOW_FORK_ENV) {
+            ret.cmd(&format!("-gdb-set follow-fork-mode {}", mode))?;
+        }
+
+        if std::env::var(DETACH_ON_FORK_ENV).is_ok_and(|v| v == "off") {
+            ret.cmd("-gdb-set detach-on-fork off")?;
+            /* Otherwise the forked processes stay stopped on continue */
+            ret.cmd("-gdb-set schedule-multiple on")?;
+        }
+
+        /* Last so that they can override the settings above */
+        if let Some(st) = ret.state.clone() {
+            for command in ret.config.commands.iter() {
+                GdbMiState::console(st.clone(), command)
+                    .map_err(|e| anyhow!("GDB setup command '{}' failed : {}", command, e))?;
+            }
+        }
+
         Ok(ret)
     }
 
@@ -645,3 +1561,88 @@ impl GdbMi {
         dbg
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// x86_64 registers of a thread stopped on `SIGSEGV` (vector registers shortened)
+    const X86_64_NAMES: &str = r#"register-names=["rax","rbx","rcx","rdx","rsi","rdi","rbp","rsp","r8","r9","r10","r11","r12","r13","r14","r15","rip","eflags","cs","ss","ds","es","fs","gs","fs_base","gs_base","st0","st1","fctrl","","","","xmm0","mxcsr"]"#;
+    const X86_64_VALUES: &str = r#"register-values=[{number="0",value="0x0"},{number="1",value="0x7fffffffe1c8"},{number="2",value="0x555555557dd8"},{number="3",value="0x7fffffffe1d8"},{number="4",value="0x1"},{number="5",value="0x0"},{number="6",value="0x7fffffffe0b0"},{number="7",value="0x7fffffffe0b0"},{number="8",value="0x0"},{number="9",value="0x0"},{number="10",value="0x0"},{number="11",value="0x0"},{number="12",value="0x0"},{number="13",value="0x0"},{number="14",value="0x0"},{number="15",value="0x0"},{number="16",value="0x555555555131"},{number="17",value="0x10246"},{number="18",value="0x33"},{number="19",value="0x2b"},{number="20",value="0x0"},{number="21",value="0x0"},{number="22",value="0x0"},{number="23",value="0x0"},{number="24",value="0x7ffff7d8a740"},{number="25",value="0x0"},{number="26",value="0x0"},{number="27",value="0x0"},{number="28",value="0x37f"},{number="32",value="{v8_bfloat16 = {0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0}, uint128 = 0x0}"},{number="33",value="0x1fa0"}]"#;
+
+    /// aarch64 registers of a thread stopped on `SIGSEGV` (vector registers shortened)
+    const AARCH64_NAMES: &str = r#"register-names=["x0","x1","x2","x3","x4","x5","x6","x7","x8","x9","x10","x11","x12","x13","x14","x15","x16","x17","x18","x19","x20","x21","x22","x23","x24","x25","x26","x27","x28","x29","x30","sp","pc","cpsr","v0","fpsr","fpcr"]"#;
+    const AARCH64_VALUES: &str = r#"register-values=[{number="0",value="0x0"},{number="1",value="0x10"},{number="2",value="0x20"},{number="3",value="0x30"},{number="4",value="0x40"},{number="5",value="0x50"},{number="6",value="0x60"},{number="7",value="0x70"},{number="8",value="0x80"},{number="9",value="0x90"},{number="10",value="0xa0"},{number="11",value="0xb0"},{number="12",value="0xc0"},{number="13",value="0xd0"},{number="14",value="0xe0"},{number="15",value="0xf0"},{number="16",value="0x100"},{number="17",value="0x110"},{number="18",value="0x120"},{number="19",value="0x130"},{number="20",value="0x140"},{number="21",value="0x150"},{number="22",value="0x160"},{number="23",value="0x170"},{number="24",value="0x180"},{number="25",value="0x190"},{number="26",value="0x1a0"},{number="27",value="0x1b0"},{number="28",value="0x1c0"},{number="29",value="0x1d0"},{number="30",value="0x1e0"},{number="31",value="0xfffffffff0a0"},{number="32",value="0xaaaaaaaa0754"},{number="33",value="0x60001000"},{number="34",value="{d = {f = {0x0, 0x0}, u = {0x0, 0x0}, s = {0x0, 0x0}}, s = {f = {0x0, 0x0, 0x0, 0x0}}}"},{number="35",value="0x0"},{number="36",value="0x0"}]"#;
+
+    fn names(registers: &[(String, String)]) -> Vec<&str> {
+        registers.iter().map(|(n, _)| n.as_str()).collect()
+    }
+
+    #[test]
+    fn x86_64_registers() {
+        let regs = parse_registers(X86_64_NAMES, X86_64_VALUES).unwrap();
+
+        /* Cut after the status register, before the segment and vector registers */
+        assert_eq!(regs.len(), 18);
+        assert_eq!(names(&regs)[..3], ["rax", "rbx", "rcx"]);
+        assert_eq!(regs[16], ("rip".to_string(), "0x555555555131".to_string()));
+        assert_eq!(regs[17], ("eflags".to_string(), "0x10246".to_string()));
+    }
+
+    #[test]
+    fn aarch64_registers() {
+        let regs = parse_registers(AARCH64_NAMES, AARCH64_VALUES).unwrap();
+
+        /* x0 to x30, sp, pc and cpsr fill the limit */
+        assert_eq!(regs.len(), 34);
+        assert_eq!(names(&regs)[..2], ["x0", "x1"]);
+        assert_eq!(regs[31], ("sp".to_string(), "0xfffffffff0a0".to_string()));
+        assert_eq!(regs[32], ("pc".to_string(), "0xaaaaaaaa0754".to_string()));
+        assert_eq!(regs[33].0, "cpsr");
+
+        /* Registers without a value are skipped */
+        let values = AARCH64_VALUES.replace(r#"{number="1",value="0x10"},"#, "");
+        let regs = parse_registers(AARCH64_NAMES, &values).unwrap();
+        assert_eq!(names(&regs)[..2], ["x0", "x2"]);
+        assert_eq!(regs.last().unwrap().0, "cpsr");
+    }
+
+    /// `-stack-list-variables --all-values` in a C++ frame
+    const VARIABLES: &str = r#"variables=[{name="grid",arg="1",value="0x7fffffffd9c0"},{name="msg",arg="1",value="0x555555556004 \"step {%d}\\n\""},{name="it",value="0"},{name="p",value="{x = 1.5, y = -2, tags = {0, 0}}"},{name="v",value="std::vector of length 2, capacity 2 = {1, 2}"},{name="empty",value=""}]"#;
+
+    #[test]
+    fn stack_variables() {
+        let vars = parse_locals(VARIABLES);
+        let var = |name: &str| vars.iter().find(|(n, _, _)| n == name).cloned();
+
+        assert_eq!(vars.len(), 6);
+        assert_eq!(
+            var("grid"),
+            Some(("grid".to_string(), true, "0x7fffffffd9c0".to_string()))
+        );
+        /* Quotes and escapes of strings are decoded */
+        assert_eq!(var("msg").unwrap().2, r#"0x555555556004 "step {%d}\n""#);
+        assert!(!var("it").unwrap().1);
+        /* Structures are kept whole */
+        assert_eq!(var("p").unwrap().2, "{x = 1.5, y = -2, tags = {0, 0}}");
+        assert_eq!(var("empty").unwrap().2, "");
+
+        let mut frame = DebugFrame::exited();
+        frame.attach_locals(vars);
+        assert_eq!(frame.args.as_ref().unwrap().len(), 2);
+        assert_eq!(frame.locals.as_ref().unwrap().len(), 4);
+
+        assert!(parse_locals("variables=[]").is_empty());
+    }
+
+    #[test]
+    fn fault_addresses() {
+        /* `-data-evaluate-expression $_siginfo._sifields._sigfault.si_addr` */
+        let resp = r#"value="(void *) 0x0""#;
+        let value = parse_gdb_equal_list(resp)["value"].clone();
+        assert_eq!(pointer_value(&value), "0x0");
+
+        assert_eq!(pointer_value("(void *) 0x7ffff7ff3000"), "0x7ffff7ff3000");
+        assert_eq!(pointer_value("0x10"), "0x10");
+    }
+}
@@ -0,0 +1,239 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::GdbClient;
+
+/// Delay between two heartbeats sent to each child
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Heartbeats missed in a row after which a child is considered dead
+pub const DEAD_AFTER_MISSES: u32 = 3;
+
+/// Threads sending the heartbeats of a node
+///
+/// They have their own pool: the commands block the threads of the global
+/// one on their children for up to the child timeout, which would delay the
+/// heartbeats until the children believe their parent is gone.
+const HEARTBEAT_THREADS: usize = 16;
+
+/// Time after which a node without heartbeats from its parent considers it dead
+pub fn parent_timeout() -> Duration {
+    HEARTBEAT_INTERVAL * (DEAD_AFTER_MISSES + 1)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Liveness {
+    /// Answered the last heartbeat
+    Live,
+    /// Missed some heartbeats
+    Suspect,
+    /// Missed too many heartbeats or the link broke
+    Dead,
+}
+
+impl std::fmt::Display for Liveness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Liveness::Live => write!(f, "live"),
+            Liveness::Suspect => write!(f, "suspect"),
+            Liveness::Dead => write!(f, "dead"),
+        }
+    }
+}
+
+/// Health of a subtree as seen from its parent
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubtreeHealth {
+    /// Id of the node at the top of the subtree
+    pub id: u64,
+    pub liveness: Liveness,
+    /// Heartbeats missed in a row
+    pub missed: u32,
+    /// Health of the subtrees below as last reported by the node
+    pub children: Vec<SubtreeHealth>,
+}
+
+impl SubtreeHealth {
+    fn new(id: u64) -> SubtreeHealth {
+        SubtreeHealth {
+            id,
+            liveness: Liveness::Live,
+            missed: 0,
+            children: Vec::new(),
+        }
+    }
+
+    /// Number of (live, suspect, dead) nodes in the subtree
+    ///
+    /// The children of a dead node are not known anymore and are not counted.
+    pub fn count(&self) -> (u64, u64, u64) {
+        let mut ret = match self.liveness {
+            Liveness::Live => (1, 0, 0),
+            Liveness::Suspect => (0, 1, 0),
+            Liveness::Dead => return (0, 0, 1),
+        };
+
+        for c in &self.children {
+            let (l, s, d) = c.count();
+            ret.0 += l;
+            ret.1 += s;
+            ret.2 += d;
+        }
+
+        ret
+    }
+
+    /// Is every node of the subtree live
+    pub fn healthy(&self) -> bool {
+        self.liveness == Liveness::Live && self.children.iter().all(|c| c.healthy())
+    }
+}
+
+/// Error of a start, stop or continue which did not reach some subtrees
+///
+/// The rest of the tree did run the command, callers which can carry on
+/// with a partial tree check for it with `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unreachable {
+    /// Ids of the nodes at the top of the subtrees
+    pub subtrees: Vec<u64>,
+}
+
+impl std::fmt::Display for Unreachable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ids: Vec<String> = self.subtrees.iter().map(|v| v.to_string()).collect();
+        write!(f, "Subtrees {} are unreachable", ids.join(","))
+    }
+}
+
+impl std::error::Error for Unreachable {}
+
+struct Link {
+    client: Arc<Mutex<GdbClient>>,
+    health: SubtreeHealth,
+}
+
+/// Heartbeats the children of a node over dedicated connections
+///
+/// Commands can take a long time to complete (e.g. a snapshot with locals),
+/// using a connection per child for heartbeats keeps liveness monitoring
+/// independent from the command traffic.
+pub struct HealthMonitor {
+    links: Vec<Link>,
+}
+
+impl HealthMonitor {
+    pub fn new() -> HealthMonitor {
+        HealthMonitor { links: Vec::new() }
+    }
+
+    /// Start monitoring the child `id` over `client`
+    pub fn add(&mut self, id: u64, client: GdbClient) {
+        self.links.push(Link {
+            client: Arc::new(Mutex::new(client)),
+            health: SubtreeHealth::new(id),
+        });
+    }
+
+    pub fn is_dead(&self, id: u64) -> bool {
+        self.links
+            .iter()
+            .any(|l| l.health.id == id && l.health.liveness == Liveness::Dead)
+    }
+
+    /// Suspect a child which did not answer a command in time
+    ///
+    /// The next heartbeats tell whether it is only slow or dead.
+    pub fn mark_suspect(&mut self, id: u64) {
+        for l in self.links.iter_mut().filter(|l| l.health.id == id) {
+            if l.health.liveness == Liveness::Live {
+                l.health.liveness = Liveness::Suspect;
+            }
+        }
+    }
+
+    /// Mark a child as dead (e.g. it did not answer a command in time)
+    pub fn mark_dead(&mut self, id: u64) {
+        for l in self.links.iter_mut().filter(|l| l.health.id == id) {
+            l.health.liveness = Liveness::Dead;
+            l.health.children.clear();
+        }
+    }
+
+    /// Health of all the subtrees below this node
+    pub fn report(&self) -> Vec<SubtreeHealth> {
+        self.links.iter().map(|l| l.health.clone()).collect()
+    }
+
+    fn beat(monitor: &Arc<Mutex<HealthMonitor>>, pool: &ThreadPool) {
+        /* Do not hold the monitor while waiting for the children */
+        let links: Vec<(usize, Arc<Mutex<GdbClient>>)> = monitor
+            .lock()
+            .unwrap()
+            .links
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.health.liveness != Liveness::Dead)
+            .map(|(i, l)| (i, l.client.clone()))
+            .collect();
+
+        let results: Vec<_> = pool.install(|| {
+            links
+                .into_par_iter()
+                .map(|(i, c)| (i, c.lock().unwrap().heartbeat()))
+                .collect()
+        });
+
+        let mut monitor = monitor.lock().unwrap();
+
+        for (i, res) in results {
+            let health = &mut monitor.links[i].health;
+
+            match res {
+                Ok(children) => {
+                    health.liveness = Liveness::Live;
+                    health.missed = 0;
+                    health.children = children;
+                }
+                Err(e) => {
+                    health.missed += 1;
+
+                    if health.missed >= DEAD_AFTER_MISSES {
+                        log::error!("Subtree {} is dead : {}", health.id, e);
+                        health.liveness = Liveness::Dead;
+                        health.children.clear();
+                    } else {
+                        log::warn!("Subtree {} missed a heartbeat : {}", health.id, e);
+                        health.liveness = Liveness::Suspect;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Heartbeat the children every [HEARTBEAT_INTERVAL] in the background
+    pub fn start(monitor: Arc<Mutex<HealthMonitor>>) -> anyhow::Result<()> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(HEARTBEAT_THREADS)
+            .thread_name(|i| format!("heartbeat-{}", i))
+            .build()?;
+
+        thread::spawn(move || loop {
+            thread::sleep(HEARTBEAT_INTERVAL);
+            HealthMonitor::beat(&monitor, &pool);
+        });
+
+        Ok(())
+    }
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        HealthMonitor::new()
+    }
+}
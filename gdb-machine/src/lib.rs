@@ -37,88 +37,289 @@
 //! ```
 //!
 
+pub mod auth;
+pub mod backend;
+pub mod binary;
 pub mod debugger;
 pub mod gdbmi;
+pub mod health;
+pub mod lldbdap;
+pub mod locality;
 pub mod metadata;
 mod protocol;
+pub mod pyframes;
 mod tools;
+pub mod topology;
+pub mod transport;
+pub mod waitgraph;
+pub mod wire;
 
 use anyhow::anyhow;
-use anyhow::Context;
 use anyhow::Result;
+use backend::Backend;
 use debugger::Debugger;
 use debugger::DummyDebugger;
-use gdbmi::GdbMi;
+use debugger::Targets;
+use health::HealthMonitor;
+use health::SubtreeHealth;
+use health::Unreachable;
+use health::HEARTBEAT_INTERVAL;
 use metadata::BacktraceState;
+use metadata::LibraryReport;
+use metadata::LockReport;
+use metadata::MpiRankState;
 use metadata::ProcessInfo;
 use metadata::ProgramSnapshot;
 use metadata::RunState;
+use metadata::SnapshotPart;
 use metadata::SymbolTable;
-use metadata::TreeIdFactory;
 use protocol::GdbMachineResponse;
-use rayon::prelude::*;
-use rayon::scope;
 use std::any::Any;
 use std::collections::HashMap;
-use std::io::Write;
 use std::net::SocketAddr;
 use std::net::TcpListener;
-use std::net::TcpStream;
+use std::os::unix::net::UnixListener;
 use std::process::Child;
 use std::str::FromStr;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::Sender;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::TryLockError;
 use std::thread;
 use std::thread::sleep;
 use std::time::Duration;
+use std::time::Instant;
 use std::u64;
-use tools::read_until_null;
-use tools::strdistance;
+use topology::Placement;
+use topology::Topology;
+use topology::TreeLayout;
+use topology::TreePlan;
+use transport::Endpoint;
+use transport::Stream;
+use wire::Capabilities;
+use wire::Hello;
+use wire::WireFormat;
 
 use crate::protocol::GdbMachineCommand;
 
+/// Time (in seconds) given to the children of the root to answer
+pub const CHILD_TIMEOUT_ENV: &str = "GDBW_CHILD_TIMEOUT";
+
+/// Time (in seconds) given to the children of the root to start their programs
+pub const START_TIMEOUT_ENV: &str = "GDBW_START_TIMEOUT";
+
+const DEFAULT_CHILD_TIMEOUT: u64 = 60;
+
+const DEFAULT_START_TIMEOUT: u64 = 600;
+
+/// Delays between two attempts when accepting connections keeps failing
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(10);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// Time given to a running command to release the debugger once the parent is lost
+const PARENT_LOST_DETACH_WAIT: Duration = Duration::from_secs(10);
+
+/// Snapshot parts arriving within this interval are merged before going up the tree
+const STREAM_FLUSH_INTERVAL: Duration = Duration::from_millis(200);
+
 pub struct GdbClient {
-    client_sock: TcpStream,
+    /* URL of the remote, to open the link again after a stall */
+    url: String,
+    client_sock: Stream,
+    /* Given to the commands, kept for a new link */
+    timeout: Option<Duration>,
+    /* The link was closed after a stall and is opened again by the next command */
+    stale: bool,
+    /* Authenticated halves of the connection, set up by the first command */
+    channel: Option<auth::Channel>,
+    /* Encoding of the commands sent to the remote */
+    format: WireFormat,
+    /* Ids hosted by the remote subtree (half-open, see TreeIdFactory::span) */
+    range: (u64, u64),
+    /* Ids the remote subtree reported so far */
+    ids: Vec<u64>,
+    /* The remote stopped answering and is not contacted anymore */
+    dead: bool,
 }
 
 impl GdbClient {
     pub fn new(addr: &str) -> Result<GdbClient> {
-        let client_sock = TcpStream::connect(addr)?;
+        Ok(GdbClient::with_stream(addr, Stream::connect(addr)?))
+    }
+
+    /// A client of the server at `endpoint` running on this host or another
+    pub fn reach(endpoint: &Endpoint) -> Result<GdbClient> {
+        let (client_sock, url) = endpoint.connect(&transport::local_host()?)?;
+        Ok(GdbClient::with_stream(url, client_sock))
+    }
+
+    fn with_stream(addr: &str, client_sock: Stream) -> GdbClient {
+        GdbClient {
+            url: addr.to_string(),
+            client_sock,
+            timeout: None,
+            stale: false,
+            channel: None,
+            format: WireFormat::from_env(),
+            range: (0, u64::MAX),
+            ids: Vec::new(),
+            dead: false,
+        }
+    }
+
+    /// Give up on commands which are not answered within `timeout`
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.timeout = Some(timeout);
+        self.client_sock.set_read_timeout(Some(timeout))?;
+        self.client_sock.set_write_timeout(Some(timeout))?;
+        Ok(())
+    }
 
-        Ok(GdbClient { client_sock })
+    /// Close the link to a remote which did not answer in time
+    ///
+    /// Its late answer would otherwise be read as the answer to the next
+    /// command, the next command opens a new link instead.
+    fn close(&mut self) {
+        self.client_sock.shutdown();
+        self.channel = None;
+        self.stale = true;
+    }
+
+    /// Talk to the remote in the given format
+    pub fn set_format(&mut self, format: WireFormat) {
+        self.format = format;
+    }
+
+    /// Record the ids a response comes from
+    fn learn_ids(&mut self, resp: &GdbMachineResponse) {
+        let ids: Vec<u64> = match resp {
+            GdbMachineResponse::State(st) => st.keys().cloned().collect(),
+            GdbMachineResponse::Processes(p) => p.keys().cloned().collect(),
+            _ => return,
+        };
+
+        self.ids.extend(ids);
+        self.ids.sort();
+        self.ids.dedup();
+    }
+
+    /// Does the remote subtree host the given id
+    fn hosts(&self, id: u64) -> bool {
+        self.range.0 <= id && id < self.range.1
     }
 
     fn do_command(&mut self, cmd: &GdbMachineCommand) -> Result<GdbMachineResponse> {
-        let cmd_in_json = serde_json::to_string(&cmd)?;
+        if self.stale {
+            self.client_sock = Stream::connect(&self.url)?;
+            self.stale = false;
 
-        /* Write JSON */
-        self.client_sock.write_all(cmd_in_json.as_bytes())?;
-        /* Write Separator */
-        self.client_sock.write_all("\0".as_bytes())?;
-        self.client_sock.flush()?;
+            if let Some(timeout) = self.timeout {
+                self.set_timeout(timeout)?;
+            }
+        }
 
-        /* Get Response */
-        let resp = read_until_null(&mut self.client_sock)?;
-        /* Parse Response */
-        let ret: GdbMachineResponse = serde_json::from_str(&resp)?;
+        /* The remote may only start serving once we answered (e.g. a joining leaf) */
+        if self.channel.is_none() {
+            self.channel = Some(auth::connect(&self.client_sock)?);
+        }
 
-        Ok(ret)
+        let (_, writer) = self.channel.as_mut().unwrap();
+
+        self.format.send(writer, cmd)?;
+
+        self.next_response()
+    }
+
+    /// Read one more response to the last command (streamed answers)
+    fn next_response(&mut self) -> Result<GdbMachineResponse> {
+        let (reader, _) = self
+            .channel
+            .as_mut()
+            .ok_or(anyhow!("No command was sent to the remote"))?;
+
+        match wire::recv(reader)? {
+            Some((resp, _)) => Ok(resp),
+            None => Err(anyhow!("Connection closed by the remote")),
+        }
     }
 
-    pub fn join(&mut self, targ: String) -> Result<()> {
-        self.do_command(&GdbMachineCommand::Join(targ))?.ok()
+    /// Snapshot the remote subtree, handing out the parts as they arrive
+    ///
+    /// Fails when the remote did not answer, the final answer of the remote
+    /// (which may be an error) is returned otherwise.
+    pub fn stream_snapshot(
+        &mut self,
+        mut on_part: impl FnMut(SnapshotPart) -> Result<()>,
+    ) -> Result<GdbMachineResponse> {
+        let mut resp = self.do_command(&GdbMachineCommand::StreamSnapshot)?;
+
+        /* Parts keep coming until the final answer */
+        while let GdbMachineResponse::SnapshotPart(part) = resp {
+            on_part(part)?;
+            resp = self.next_response()?;
+        }
+
+        Ok(resp)
     }
 
-    pub fn pivot(&mut self, local_url: String) -> Result<(u64, String)> {
-        let process_info = ProcessInfo::default()?;
+    /// Exchange versions and capabilities, fails if the remote speaks another version
+    pub fn hello(&mut self) -> Result<Capabilities> {
+        let format = self.format;
 
-        let ret = self.do_command(&GdbMachineCommand::Pivot(process_info, local_url))?;
+        /* Readable by any version, see GdbMachineCommand::Hello */
+        self.format = WireFormat::Json;
+        let resp = self.do_command(&GdbMachineCommand::Hello(Hello::local()));
+        self.format = format;
+
+        match resp? {
+            GdbMachineResponse::Hello(h) => {
+                h.check()?;
+                Ok(h.capabilities)
+            }
+            GdbMachineResponse::Error(e) => Err(anyhow!("Error: {}", e)),
+            _ => Err(anyhow!("Bad response for hello")),
+        }
+    }
 
-        if let GdbMachineResponse::Pivot(id, targ) = ret {
-            return Ok((id, targ));
+    /// Check that the remote is alive and get the health of its subtree
+    pub fn heartbeat(&mut self) -> Result<Vec<SubtreeHealth>> {
+        match self.do_command(&GdbMachineCommand::Heartbeat)? {
+            GdbMachineResponse::Health(h) => Ok(h),
+            GdbMachineResponse::Error(e) => Err(anyhow!("Error: {}", e)),
+            _ => Err(anyhow!("Bad response for heartbeat")),
         }
+    }
+
+    /// Ask the parent to connect to `targ` and to talk to it in our format
+    pub fn join(&mut self, targ: Endpoint, id: u64, span: u64) -> Result<()> {
+        self.do_command(&GdbMachineCommand::Join(
+            targ,
+            id,
+            span,
+            self.format,
+            Capabilities::local(),
+        ))?
+        .ok()
+    }
 
-        Err(anyhow!("Bad response for pivot"))
+    pub fn pivot(
+        &mut self,
+        process_info: ProcessInfo,
+        local: Endpoint,
+    ) -> Result<(Placement, bool)> {
+        let ret = self.do_command(&GdbMachineCommand::Pivot(
+            process_info,
+            local,
+            Capabilities::local(),
+        ))?;
+
+        match ret {
+            GdbMachineResponse::Pivot(placement, late) => Ok((placement, late)),
+            GdbMachineResponse::Error(e) => Err(anyhow!("Error: {}", e)),
+            _ => Err(anyhow!("Bad response for pivot")),
+        }
     }
 }
 
@@ -159,117 +360,366 @@ impl Debugger for GdbClient {
         self.do_command(&GdbMachineCommand::GetSnapshot)?.snapshot()
     }
 
+    fn snapshot_stream(&mut self, parts: &Sender<SnapshotPart>) -> Result<()> {
+        self.stream_snapshot(|part| {
+            parts
+                .send(part)
+                .map_err(|_| anyhow!("Snapshot receiver is gone"))
+        })?
+        .ok()
+    }
+
+    /// Full state of a single process
+    fn process_snapshot(&mut self, id: u64) -> Result<Option<ProgramSnapshot>> {
+        self.do_command(&GdbMachineCommand::GetProcessSnapshot(id))?
+            .process_snapshot()
+    }
+
+    /// Evaluate an expression
+    fn eval(&mut self, expr: &str) -> Result<HashMap<u64, String>> {
+        self.do_command(&GdbMachineCommand::Eval(expr.to_string()))?
+            .values()
+    }
+
+    /// Describe processes
+    fn processes(&mut self) -> Result<HashMap<u64, ProcessInfo>> {
+        self.do_command(&GdbMachineCommand::GetProcesses)?
+            .processes()
+    }
+
     /// Get Symbol table
     fn symbols(&mut self) -> Result<SymbolTable> {
         self.do_command(&GdbMachineCommand::GetSymbols)?.symbols()
     }
 
+    /// Analyze mutex deadlocks
+    fn mutex_deadlocks(&mut self) -> Result<HashMap<u64, LockReport>> {
+        self.do_command(&GdbMachineCommand::GetLocks)?.locks()
+    }
+
+    /// Get blocking MPI calls
+    fn mpi_waits(&mut self) -> Result<HashMap<u64, MpiRankState>> {
+        self.do_command(&GdbMachineCommand::GetMpiWaits)?
+            .mpi_waits()
+    }
+
+    fn libraries(&mut self) -> Result<LibraryReport> {
+        self.do_command(&GdbMachineCommand::GetLibraries)?
+            .libraries()
+    }
+
     fn count(&mut self) -> Result<u64> {
         Ok(1)
     }
 }
 
+/// Whether new processes can pivot into the tree
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Joining {
+    /// The tree is being built
+    Open,
+    /// The session started, new processes join late
+    Late,
+    /// The session started without the processes which did not join
+    Closed,
+}
+
 pub struct TreeState {
     id: Option<u64>,
-    seen_children: HashMap<String, (String, TreeIdFactory)>,
+    /* Depth of this node in the tree (root is 0) */
+    depth: u32,
+    /* Placement of the processes (root only) */
+    plan: Option<TreePlan>,
+    /* Features all the processes which pivoted support (root only) */
+    capabilities: Capabilities,
     children: Vec<GdbClient>,
+    /* Sorted ids commands are restricted to (None is all) */
+    targets: Option<Vec<u64>>,
+    /* Cached rank to id mapping to resolve rank targets */
+    rank_ids: Option<HashMap<u32, u64>>,
+    health: Arc<Mutex<HealthMonitor>>,
+    joining: Joining,
+    /* Base timeout of the children overriding CHILD_TIMEOUT_ENV */
+    timeout: Option<Duration>,
 }
 
 impl TreeState {
-    fn default() -> TreeState {
+    fn new(health: Arc<Mutex<HealthMonitor>>) -> TreeState {
         TreeState {
-            seen_children: HashMap::new(),
+            health,
+            joining: Joining::Open,
+            plan: None,
+            capabilities: Capabilities::local(),
             children: Vec::new(),
             id: None,
+            depth: 0,
+            targets: None,
+            rank_ids: None,
+            timeout: None,
         }
     }
 
-    fn set_root(&mut self, root_url: String) {
-        self.seen_children
-            .insert("ROOT".to_string(), (root_url, TreeIdFactory::default()));
+    fn set_root(&mut self, root: Endpoint, topology: Topology) {
+        log::info!("Building a {} tree", topology);
+        self.plan = Some(TreePlan::new(topology, root));
     }
 
-    fn _pivot_get_closest_id_match(&mut self, locator: &String) -> Result<(String, TreeIdFactory)> {
-        /* Exact match */
-        if let Some(_) = self.seen_children.get(locator) {
-            return Err(anyhow!("Process {:?} is already registered", locator));
+    fn pivot(
+        &mut self,
+        process_info: &ProcessInfo,
+        from: Endpoint,
+        caps: &Capabilities,
+    ) -> Result<(Placement, bool)> {
+        if self.joining == Joining::Closed {
+            return Err(anyhow!(
+                "Process {} arrived after the join timeout",
+                process_info.locality_descriptor
+            ));
         }
 
-        /* If root is not full use it */
-        let (url, root) = self
-            .seen_children
-            .get_mut("ROOT")
-            .expect("It is only possible to pivot on root process");
+        let plan = self
+            .plan
+            .as_mut()
+            .ok_or(anyhow!("It is only possible to pivot on root process"))?;
 
-        if !root.full() {
-            log::info!("PIVOT {} is Joining ROOT", locator);
-            return Ok((url.clone(), root.inherit()?));
+        let placement = plan.place(process_info, from)?;
+
+        if self.capabilities.common(caps) != self.capabilities {
+            log::warn!(
+                "PIVOT {} only supports {}",
+                process_info.locality_descriptor,
+                caps
+            );
+            self.capabilities = self.capabilities.common(caps);
         }
 
-        /* Now attempt closest match */
-        let mut mtch = None;
-        let mut distance: u64 = u64::MAX;
+        if self.joining == Joining::Late {
+            log::info!(
+                "PIVOT {} is a late joiner",
+                process_info.locality_descriptor
+            );
+        }
 
-        self.seen_children
-            .iter()
-            .filter(|(_, v)| !v.1.full())
-            .for_each(|(k, _)| {
-                let dist = strdistance(k, &locator);
+        Ok((placement, self.joining == Joining::Late))
+    }
 
-                if dist < distance {
-                    distance = dist;
-                    mtch = Some(k.clone());
-                }
-            });
+    /// Time given to each child to answer `cmd`
+    ///
+    /// Deeper nodes give up first so that a parent learns about a missing
+    /// grandchild before timing out on the child itself. Starting the
+    /// programs (e.g. loading the symbols of a large binary) is given its
+    /// own, longer, timeout not to take slow starts for dead subtrees.
+    fn child_timeout(&self, cmd: &GdbMachineCommand) -> Duration {
+        let (var, default) = match cmd {
+            GdbMachineCommand::Start => (START_TIMEOUT_ENV, DEFAULT_START_TIMEOUT),
+            GdbMachineCommand::Targeted(_, cmd) => return self.child_timeout(cmd),
+            _ => (CHILD_TIMEOUT_ENV, DEFAULT_CHILD_TIMEOUT),
+        };
 
-        match mtch {
-            Some(m) => {
-                if m == "ROOT" {
-                    unimplemented!("Distance match should not capture root");
-                }
-                /* We are sure the key exists as k is from keys */
-                if let Some((url, val)) = self.seen_children.get_mut(&m) {
-                    log::info!("PIVOT {} is Joining {}", locator, m);
-                    return Ok((url.clone(), val.inherit()?));
+        let base = match self.timeout {
+            Some(timeout) if var == CHILD_TIMEOUT_ENV => timeout,
+            _ => Duration::from_secs(
+                std::env::var(var)
+                    .ok()
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(default),
+            ),
+        };
+
+        base / (self.depth + 1)
+    }
+
+    fn join(
+        &mut self,
+        targ: Endpoint,
+        id: u64,
+        span: u64,
+        format: WireFormat,
+        caps: &Capabilities,
+    ) -> Result<()> {
+        /* Only use what both ends understand */
+        let format = format.restrict(&Capabilities::local().common(caps));
+        /* Children on this host are reached over their Unix socket */
+        let mut client = GdbClient::reach(&targ)?;
+        client.set_format(format);
+        client.set_timeout(self.child_timeout(&GdbMachineCommand::GetState))?;
+        client.range = (id, id.saturating_add(span));
+        client.ids.push(id);
+        let url = client.url.clone();
+        self.children.push(client);
+
+        /* Heartbeats use their own connection not to wait behind commands */
+        let mut heartbeat = GdbClient::new(&url)?;
+        heartbeat.set_format(format);
+        heartbeat.set_timeout(HEARTBEAT_INTERVAL)?;
+        self.health.lock().unwrap().add(id, heartbeat);
+
+        /* Ranks have to be fetched again */
+        self.rank_ids = None;
+        Ok(())
+    }
+
+    fn resolve_ranks(&mut self, ranks: &[u32]) -> Result<Vec<u64>> {
+        if self.rank_ids.is_none() {
+            let procs = self.processes()?;
+            self.rank_ids = Some(
+                procs
+                    .into_iter()
+                    .filter_map(|(id, p)| p.mpirank.map(|r| (r, id)))
+                    .collect(),
+            );
+        }
+
+        let rank_ids = self.rank_ids.as_ref().unwrap();
+
+        ranks
+            .iter()
+            .map(|r| {
+                rank_ids
+                    .get(r)
+                    .cloned()
+                    .ok_or(anyhow!("No process with rank {}", r))
+            })
+            .collect()
+    }
+
+    /// Command to send to a given child, None if it hosts none of the targets
+    fn child_command(
+        &self,
+        child: &GdbClient,
+        cmd: &GdbMachineCommand,
+    ) -> Option<GdbMachineCommand> {
+        match &self.targets {
+            None => Some(cmd.clone()),
+            Some(targets) => {
+                let ids: Vec<u64> = targets
+                    .iter()
+                    .filter(|v| child.hosts(**v))
+                    .cloned()
+                    .collect();
+
+                if ids.is_empty() {
+                    None
                 } else {
-                    unreachable!("Key must exist");
+                    Some(GdbMachineCommand::Targeted(ids, Box::new(cmd.clone())))
                 }
             }
-            None => unreachable!("Root should have had at least another child"),
         }
     }
 
-    fn pivot(&mut self, process_info: &ProcessInfo, from: String) -> Result<(u64, String)> {
-        /* Generate range for new entry */
-        let (url, new_range) =
-            self._pivot_get_closest_id_match(&process_info.locality_descriptor)?;
-
-        /* Let new id */
-        let id = new_range.id();
-
-        /* Insert range to locator */
-        self.seen_children
-            .insert(process_info.locality_descriptor.clone(), (from, new_range));
+    /// Stop talking to the children the health monitor found dead
+    fn refresh_dead(&mut self) {
+        let health = self.health.lock().unwrap();
+        for c in self.children.iter_mut() {
+            if health.is_dead(c.range.0) {
+                c.dead = true;
+            }
+        }
+    }
 
-        Ok((id, url))
+    /// Ids hosted by the subtree of `child`
+    ///
+    /// The root knows every placement, other nodes only know the ids their
+    /// child reported so far (at least the child itself).
+    fn subtree_ids(&self, child: &GdbClient) -> Vec<u64> {
+        match &self.plan {
+            Some(plan) => plan.ids_in(child.range),
+            None => child.ids.clone(),
+        }
     }
 
-    fn join(&mut self, targ: String) -> Result<()> {
-        let client = GdbClient::new(targ.as_str())?;
-        self.children.push(client);
-        Ok(())
+    /// Ids of `child` concerned by the current targets
+    fn targeted_ids(&self, child: &GdbClient) -> Vec<u64> {
+        match &self.targets {
+            None => self.subtree_ids(child),
+            Some(targets) => targets
+                .iter()
+                .filter(|v| child.hosts(**v))
+                .cloned()
+                .collect(),
+        }
     }
 
+    /// Run a command on the children hosting the targets
+    ///
+    /// A child which does not answer in time is suspected, the heartbeats
+    /// tell whether it is dead or only stalled. Children failing to answer
+    /// are replaced by a response standing for their processes as
+    /// unreachable (when the command has one) so that the others still
+    /// produce a partial result.
     fn run_on_children(&mut self, cmd: GdbMachineCommand) -> Result<Vec<GdbMachineResponse>> {
-        let ret = self
+        self.refresh_dead();
+
+        let cmds: Vec<Option<(GdbMachineCommand, Vec<u64>)>> = self
             .children
-            .par_iter_mut()
-            .map(|c| c.do_command(&cmd))
-            .collect::<Vec<_>>();
+            .iter()
+            .map(|c| {
+                self.child_command(c, &cmd)
+                    .map(|cmd| (cmd, self.targeted_ids(c)))
+            })
+            .collect();
 
-        let ret: Result<Vec<GdbMachineResponse>> = ret.into_iter().collect();
+        let timeout = self.child_timeout(&cmd);
+
+        let health = &self.health;
+        let cmd = &cmd;
+
+        /* A thread per child: they block on their socket for up to the
+        timeout, the global pool would serialize them when a node has more
+        children than cores */
+        let ret = thread::scope(|s| {
+            let handles: Vec<_> = self
+                .children
+                .iter_mut()
+                .zip(cmds)
+                .filter_map(|(c, routed)| {
+                    let (child_cmd, ids) = routed?;
+
+                    Some(s.spawn(move || {
+                        /* Only the children which could not answer are replaced,
+                        errors of the debuggers are forwarded */
+                        let failure = if c.dead {
+                            format!("Subtree {} is unreachable", c.range.0)
+                        } else {
+                            match c
+                                .set_timeout(timeout)
+                                .and_then(|_| c.do_command(&child_cmd))
+                            {
+                                Ok(resp) => {
+                                    c.learn_ids(&resp);
+                                    return resp;
+                                }
+                                Err(e) => {
+                                    log::error!("Subtree {} stopped answering : {}", c.range.0, e);
+                                    c.close();
+                                    health.lock().unwrap().mark_suspect(c.range.0);
+                                    format!("Subtree {} is unreachable : {}", c.range.0, e)
+                                }
+                            }
+                        };
+
+                        match GdbMachineResponse::unreachable(cmd, c.range.0, &ids) {
+                            Some(missing) => {
+                                log::warn!("{} processes missing from the result", ids.len());
+                                missing
+                            }
+                            None => GdbMachineResponse::Error(failure),
+                        }
+                    }))
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|h| {
+                    h.join().unwrap_or(GdbMachineResponse::Error(
+                        "Child command panicked".to_string(),
+                    ))
+                })
+                .collect::<Vec<_>>()
+        });
 
-        ret
+        Ok(ret)
     }
 
     fn all_resp_ok(resps: &Vec<GdbMachineResponse>) -> Result<()> {
@@ -285,6 +735,19 @@ impl TreeState {
             return Err(anyhow!("{}", errs.join(",")));
         }
 
+        let subtrees: Vec<u64> = resps
+            .iter()
+            .filter_map(|v| match v {
+                GdbMachineResponse::Unreachable(s) => Some(s.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+
+        if !subtrees.is_empty() {
+            return Err(Unreachable { subtrees }.into());
+        }
+
         Ok(())
     }
 
@@ -318,10 +781,22 @@ impl TreeState {
                 GdbMachineResponse::Ok => match r2 {
                     GdbMachineResponse::Error(e) => Some(GdbMachineResponse::Error(e.to_string())),
                     GdbMachineResponse::Ok => Some(GdbMachineResponse::Ok),
+                    GdbMachineResponse::Unreachable(s) => Some(GdbMachineResponse::Unreachable(s)),
                     _ => Some(GdbMachineResponse::Error(
                         "Incompatible type to be merged Ok".to_string(),
                     )),
                 },
+                GdbMachineResponse::Unreachable(mut s1) => match r2 {
+                    GdbMachineResponse::Error(e) => Some(GdbMachineResponse::Error(e.to_string())),
+                    GdbMachineResponse::Ok => Some(GdbMachineResponse::Unreachable(s1)),
+                    GdbMachineResponse::Unreachable(s2) => {
+                        s1.extend(s2);
+                        Some(GdbMachineResponse::Unreachable(s1))
+                    }
+                    _ => Some(GdbMachineResponse::Error(
+                        "Incompatible type to be merged Unreachable".to_string(),
+                    )),
+                },
                 GdbMachineResponse::State(mut st1) => match r2 {
                     GdbMachineResponse::State(st2) => {
                         st1.extend(st2.into_iter());
@@ -341,6 +816,25 @@ impl TreeState {
                         "Incompatible type to be merged snapshot".to_string(),
                     )),
                 },
+                GdbMachineResponse::SnapshotPart(mut p1) => match r2 {
+                    GdbMachineResponse::SnapshotPart(p2) => {
+                        p1.merge(p2);
+                        Some(GdbMachineResponse::SnapshotPart(p1))
+                    }
+                    GdbMachineResponse::Error(e) => Some(GdbMachineResponse::Error(e.to_string())),
+                    _ => Some(GdbMachineResponse::Error(
+                        "Incompatible type to be merged SnapshotPart".to_string(),
+                    )),
+                },
+                GdbMachineResponse::ProcessSnapshot(ps1) => match r2 {
+                    GdbMachineResponse::ProcessSnapshot(ps2) => {
+                        Some(GdbMachineResponse::ProcessSnapshot(ps1.or(ps2)))
+                    }
+                    GdbMachineResponse::Error(e) => Some(GdbMachineResponse::Error(e.to_string())),
+                    _ => Some(GdbMachineResponse::Error(
+                        "Incompatible type to be merged ProcessSnapshot".to_string(),
+                    )),
+                },
                 GdbMachineResponse::Count(c1) => match r2 {
                     GdbMachineResponse::Count(c2) => Some(GdbMachineResponse::Count(c1 + c2)),
                     GdbMachineResponse::Error(e) => Some(GdbMachineResponse::Error(e.to_string())),
@@ -349,9 +843,74 @@ impl TreeState {
                         "Incompatible type to be merged Count".to_string(),
                     )),
                 },
-                GdbMachineResponse::Symbols(_) => todo!(),
+                GdbMachineResponse::Locks(mut lk1) => match r2 {
+                    GdbMachineResponse::Locks(lk2) => {
+                        lk1.extend(lk2);
+                        Some(GdbMachineResponse::Locks(lk1))
+                    }
+                    GdbMachineResponse::Error(e) => Some(GdbMachineResponse::Error(e.to_string())),
+                    _ => Some(GdbMachineResponse::Error(
+                        "Incompatible type to be merged Locks".to_string(),
+                    )),
+                },
+                GdbMachineResponse::MpiWaits(mut mw1) => match r2 {
+                    GdbMachineResponse::MpiWaits(mw2) => {
+                        mw1.extend(mw2);
+                        Some(GdbMachineResponse::MpiWaits(mw1))
+                    }
+                    GdbMachineResponse::Error(e) => Some(GdbMachineResponse::Error(e.to_string())),
+                    _ => Some(GdbMachineResponse::Error(
+                        "Incompatible type to be merged MpiWaits".to_string(),
+                    )),
+                },
+                GdbMachineResponse::Libraries(mut l1) => match r2 {
+                    GdbMachineResponse::Libraries(l2) => {
+                        l1.merge(l2);
+                        Some(GdbMachineResponse::Libraries(l1))
+                    }
+                    GdbMachineResponse::Error(e) => Some(GdbMachineResponse::Error(e.to_string())),
+                    _ => Some(GdbMachineResponse::Error(
+                        "Incompatible type to be merged Libraries".to_string(),
+                    )),
+                },
+                GdbMachineResponse::Values(mut v1) => match r2 {
+                    GdbMachineResponse::Values(v2) => {
+                        v1.extend(v2);
+                        Some(GdbMachineResponse::Values(v1))
+                    }
+                    GdbMachineResponse::Error(e) => Some(GdbMachineResponse::Error(e.to_string())),
+                    _ => Some(GdbMachineResponse::Error(
+                        "Incompatible type to be merged Values".to_string(),
+                    )),
+                },
+                GdbMachineResponse::Processes(mut p1) => match r2 {
+                    GdbMachineResponse::Processes(p2) => {
+                        p1.extend(p2);
+                        Some(GdbMachineResponse::Processes(p1))
+                    }
+                    GdbMachineResponse::Error(e) => Some(GdbMachineResponse::Error(e.to_string())),
+                    _ => Some(GdbMachineResponse::Error(
+                        "Incompatible type to be merged Processes".to_string(),
+                    )),
+                },
+                GdbMachineResponse::Symbols(mut s1) => match r2 {
+                    GdbMachineResponse::Symbols(s2) => {
+                        s1.merge(s2);
+                        Some(GdbMachineResponse::Symbols(s1))
+                    }
+                    GdbMachineResponse::Error(e) => Some(GdbMachineResponse::Error(e.to_string())),
+                    _ => Some(GdbMachineResponse::Error(
+                        "Incompatible type to be merged Symbols".to_string(),
+                    )),
+                },
+                GdbMachineResponse::Health(_) => Some(GdbMachineResponse::Error(
+                    "Health reports are not merged".to_string(),
+                )),
+                GdbMachineResponse::Hello(_) => {
+                    Some(GdbMachineResponse::Error("Hello is not merged".to_string()))
+                }
                 GdbMachineResponse::Pivot(_, _) => {
-                    todo!()
+                    Some(GdbMachineResponse::Error("Pivot is not merged".to_string()))
                 }
             }
         }
@@ -381,6 +940,40 @@ impl Debugger for TreeState {
         0
     }
 
+    fn health(&mut self) -> Result<Vec<SubtreeHealth>> {
+        Ok(self.health.lock().unwrap().report())
+    }
+
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        Ok(self.capabilities)
+    }
+
+    fn layout(&mut self) -> Result<TreeLayout> {
+        match &self.plan {
+            Some(plan) => Ok(plan.layout()),
+            None => Err(anyhow!("Only the root knows the layout of the tree")),
+        }
+    }
+
+    fn set_targets(&mut self, targets: Option<Targets>) -> Result<()> {
+        /* Resolving ranks is done over the whole tree */
+        self.targets = None;
+
+        let ids = match targets {
+            None => return Ok(()),
+            Some(Targets::Ids(ids)) => ids,
+            Some(Targets::Ranks(ranks)) => self.resolve_ranks(&ranks)?,
+        };
+
+        let mut ids = ids;
+        ids.sort();
+        ids.dedup();
+
+        self.targets = Some(ids);
+
+        Ok(())
+    }
+
     fn start(&mut self) -> Result<()> {
         if self.children.is_empty() {
             return Ok(());
@@ -468,108 +1061,383 @@ impl Debugger for TreeState {
         Ok(ProgramSnapshot::components_merge(components))
     }
 
-    fn symbols(&mut self) -> Result<SymbolTable> {
-        todo!()
-    }
-}
-
-pub struct GdbMachine {
-    listening_sock: TcpListener,
-    host: String,
-    dbg: Arc<Mutex<Box<dyn Debugger>>>,
-    state: Arc<Mutex<Box<dyn Debugger>>>,
-}
-
-impl GdbMachine {
-    pub fn new(bindaddr: &str, dbg: Arc<Mutex<Box<dyn Debugger>>>) -> Result<GdbMachine> {
-        let address = SocketAddr::from_str(bindaddr)?;
-
-        let listening_sock = TcpListener::bind(address)?;
+    fn snapshot_stream(&mut self, parts: &Sender<SnapshotPart>) -> Result<()> {
+        if self.children.is_empty() {
+            return Ok(());
+        }
 
-        let host = gethostname::gethostname()
-            .to_str()
-            .context("Failed to convert hostname to string")?
-            .to_string();
+        /* Targeted snapshots are small, they are not worth streaming */
+        if self.targets.is_some() {
+            let part = SnapshotPart {
+                components: self.snapshot()?,
+                reported: self.count()?,
+            };
+            return parts
+                .send(part)
+                .map_err(|_| anyhow!("Snapshot receiver is gone"));
+        }
 
-        let ret = GdbMachine {
-            listening_sock,
-            host,
-            dbg,
-            state: Arc::new(Mutex::new(Box::new(TreeState::default()))),
-        };
+        self.refresh_dead();
 
-        Ok(ret)
-    }
+        let health = self.health.clone();
+        let timeout = self.child_timeout(&GdbMachineCommand::StreamSnapshot);
+        let expected: Vec<u64> = self
+            .children
+            .iter()
+            .map(|c| self.subtree_ids(c).len() as u64)
+            .collect();
 
-    pub fn local(command: &[String]) -> Result<RootDebugger> {
-        let v: Vec<&str> = command.iter().map(|x| &**x).collect();
-        let mut gdb = GdbMi::run(v.as_slice())?;
+        /* A thread per child as in run_on_children */
+        thread::scope(|s| {
+            for (c, expected) in self.children.iter_mut().zip(expected) {
+                let health = &health;
+
+                s.spawn(move || {
+                    let mut reported = 0;
+
+                    if !c.dead {
+                        let ret = c.set_timeout(timeout).and_then(|_| {
+                            c.stream_snapshot(|part| {
+                                reported += part.reported;
+                                parts
+                                    .send(part)
+                                    .map_err(|_| anyhow!("Snapshot receiver is gone"))
+                            })
+                        });
+
+                        match ret {
+                            Ok(GdbMachineResponse::Error(e)) => {
+                                log::error!("Subtree {} failed to snapshot : {}", c.range.0, e);
+                            }
+                            Ok(_) => return,
+                            Err(e) => {
+                                log::error!("Subtree {} stopped answering : {}", c.range.0, e);
+                                c.close();
+                                health.lock().unwrap().mark_suspect(c.range.0);
+                            }
+                        }
+                    }
 
-        let child_proc = gdb.take_child();
+                    /* Stand for the processes which did not report */
+                    let missing = expected.saturating_sub(reported);
 
-        return Ok(RootDebugger {
-            state: Arc::new(Mutex::new(Box::new(gdb))),
-            child_proc,
+                    if missing > 0 {
+                        log::warn!("{} processes missing from the result", missing);
+                        let _ = parts.send(SnapshotPart::unreachable(missing));
+                    }
+                });
+            }
         });
+
+        Ok(())
     }
 
-    pub fn run_as_leaf(root: String, command: &[String]) -> Result<()> {
-        let v: Vec<&str> = command.iter().map(|x| &**x).collect();
-        let gdb = GdbMi::run(v.as_slice())?;
+    fn process_snapshot(&mut self, id: u64) -> Result<Option<ProgramSnapshot>> {
+        if self.children.is_empty() {
+            return Ok(None);
+        }
 
-        let server = GdbMachine::new("0.0.0.0:0", gdb.instance())?;
+        /* Only walk down the subtree hosting the process */
+        let targets = self.targets.replace(vec![id]);
+        let resps = self.run_on_children(GdbMachineCommand::GetProcessSnapshot(id));
+        self.targets = targets;
 
-        let mut client = GdbClient::new(&root)?;
+        let resps = resps?;
 
-        let (id, targ) = client.pivot(server.url()?)?;
+        TreeState::all_resp_ok(&resps)?;
 
-        server.set_id(id);
+        Ok(resps.into_iter().find_map(|v| {
+            if let GdbMachineResponse::ProcessSnapshot(ps) = v {
+                return ps.map(|v| *v);
+            }
+            None
+        }))
+    }
 
-        // At this point the server should be backconnected
-        // We can drop our current client to the root
-        drop(client);
+    fn eval(&mut self, expr: &str) -> Result<HashMap<u64, String>> {
+        let mut ret = HashMap::new();
 
-        // Now we notify the new client we want him to join us
-        let mut client = GdbClient::new(&targ)?;
-        client.join(server.url()?)?;
-        //We are done the targ is conncted to our local server
-        drop(client);
+        if self.children.is_empty() {
+            return Ok(ret);
+        }
 
-        server.run()?;
+        let resps = self.run_on_children(GdbMachineCommand::Eval(expr.to_string()))?;
 
-        Ok(())
-    }
+        TreeState::all_resp_ok(&resps)?;
 
-    pub fn wait_for_child(&self, child_count: usize) -> Result<()> {
-        /* Wait for all clients to join in */
-        loop {
-            if let Some(cnt) = self.tree_count() {
-                if cnt == child_count {
-                    let tree_count = self.state.lock().unwrap().count()?;
-                    log::trace!("Tree is currently hosting {} processes", tree_count);
-                    if cnt == tree_count as usize {
-                        break;
-                    }
-                }
+        for resp in resps {
+            if let GdbMachineResponse::Values(v) = resp {
+                ret.extend(v);
             }
-            sleep(Duration::from_millis(500));
         }
 
-        Ok(())
+        Ok(ret)
     }
 
-    pub fn run_as_root() -> Result<(Arc<GdbMachine>, RootDebugger)> {
-        let srv = GdbMachine::new("0.0.0.0:0", DummyDebugger::instance())?;
-        srv.set_master();
+    fn processes(&mut self) -> Result<HashMap<u64, ProcessInfo>> {
+        let mut ret = HashMap::new();
 
-        let srv = Arc::new(srv);
+        if self.children.is_empty() {
+            return Ok(ret);
+        }
 
-        let psrv = srv.clone();
-        thread::spawn(move || {
-            psrv.run().unwrap();
-        });
+        let resps = self.run_on_children(GdbMachineCommand::GetProcesses)?;
 
-        let rdbg = RootDebugger {
+        TreeState::all_resp_ok(&resps)?;
+
+        for resp in resps {
+            if let GdbMachineResponse::Processes(p) = resp {
+                ret.extend(p);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    fn symbols(&mut self) -> Result<SymbolTable> {
+        let mut ret = SymbolTable::default();
+
+        if self.children.is_empty() {
+            return Ok(ret);
+        }
+
+        let resps = self.run_on_children(GdbMachineCommand::GetSymbols)?;
+
+        TreeState::all_resp_ok(&resps)?;
+
+        for resp in resps {
+            if let GdbMachineResponse::Symbols(table) = resp {
+                ret.merge(table);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    fn mutex_deadlocks(&mut self) -> Result<HashMap<u64, LockReport>> {
+        let mut ret = HashMap::new();
+
+        if self.children.is_empty() {
+            return Ok(ret);
+        }
+
+        let resps = self.run_on_children(GdbMachineCommand::GetLocks)?;
+
+        TreeState::all_resp_ok(&resps)?;
+
+        for resp in resps {
+            if let GdbMachineResponse::Locks(lk) = resp {
+                ret.extend(lk);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    fn mpi_waits(&mut self) -> Result<HashMap<u64, MpiRankState>> {
+        let mut ret = HashMap::new();
+
+        if self.children.is_empty() {
+            return Ok(ret);
+        }
+
+        let resps = self.run_on_children(GdbMachineCommand::GetMpiWaits)?;
+
+        TreeState::all_resp_ok(&resps)?;
+
+        for resp in resps {
+            if let GdbMachineResponse::MpiWaits(mw) = resp {
+                ret.extend(mw);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    fn libraries(&mut self) -> Result<LibraryReport> {
+        let mut ret = LibraryReport::default();
+
+        if self.children.is_empty() {
+            return Ok(ret);
+        }
+
+        let resps = self.run_on_children(GdbMachineCommand::GetLibraries)?;
+
+        TreeState::all_resp_ok(&resps)?;
+
+        for resp in resps {
+            if let GdbMachineResponse::Libraries(libs) = resp {
+                ret.merge(libs);
+            }
+        }
+
+        Ok(ret)
+    }
+}
+
+pub struct GdbMachine {
+    listening_sock: TcpListener,
+    /* Serves the children running on the same host */
+    unix_sock: UnixListener,
+    unix_url: String,
+    host: String,
+    dbg: Arc<Mutex<Box<dyn Debugger>>>,
+    state: Arc<Mutex<Box<dyn Debugger>>>,
+    health: Arc<Mutex<HealthMonitor>>,
+}
+
+impl GdbMachine {
+    pub fn new(bindaddr: &str, dbg: Arc<Mutex<Box<dyn Debugger>>>) -> Result<GdbMachine> {
+        let address = SocketAddr::from_str(bindaddr)?;
+
+        let listening_sock = TcpListener::bind(address)?;
+        let (unix_sock, unix_url) = transport::unix_listener(listening_sock.local_addr()?.port())?;
+
+        let host = transport::advertised_host(&address)?;
+
+        let health = Arc::new(Mutex::new(HealthMonitor::new()));
+
+        let ret = GdbMachine {
+            listening_sock,
+            unix_sock,
+            unix_url,
+            host,
+            dbg,
+            state: Arc::new(Mutex::new(Box::new(TreeState::new(health.clone())))),
+            health,
+        };
+
+        Ok(ret)
+    }
+
+    pub fn local(command: &[String]) -> Result<RootDebugger> {
+        let v: Vec<&str> = command.iter().map(|x| &**x).collect();
+        let (dbg, child_proc) = Backend::from_env()?.run_local(v.as_slice())?;
+
+        return Ok(RootDebugger {
+            state: Arc::new(Mutex::new(dbg)),
+            child_proc,
+        });
+    }
+
+    pub fn run_as_leaf(root: String, command: &[String]) -> Result<()> {
+        let v: Vec<&str> = command.iter().map(|x| &**x).collect();
+        let dbg = Backend::from_env()?.run(v.as_slice())?;
+
+        let server = GdbMachine::new(
+            &transport::bind_address()?.to_string(),
+            Arc::new(Mutex::new(dbg)),
+        )?;
+
+        let late = server.attach(&root, ProcessInfo::default()?)?;
+
+        /* The root already started the others, start on our own */
+        if late {
+            server.dbg.lock().unwrap().start()?;
+        }
+
+        server.run()?;
+
+        Ok(())
+    }
+
+    /// Get placed by the root at `root` and join the parent it picked
+    ///
+    /// Returns whether the session already started.
+    fn attach(&self, root: &str, process_info: ProcessInfo) -> Result<bool> {
+        let mut client = GdbClient::new(root)?;
+        client.hello()?;
+
+        let (placement, late) = client.pivot(process_info, self.endpoint()?)?;
+
+        self.set_id(placement.id);
+        self.set_depth(placement.depth);
+
+        // At this point the server should be backconnected
+        // We can drop our current client to the root
+        drop(client);
+
+        // Now we notify the new client we want him to join us
+        let mut client = GdbClient::reach(&placement.parent)?;
+        client.hello()?;
+        client.join(self.endpoint()?, placement.id, placement.span)?;
+        //We are done the targ is conncted to our local server
+        drop(client);
+
+        Ok(late)
+    }
+
+    /// Wait for `child_count` processes to join the tree
+    ///
+    /// When `timeout` elapses first, the processes which joined so far are
+    /// kept. Returns the number of processes in the tree.
+    pub fn wait_for_child(&self, child_count: usize, timeout: Option<Duration>) -> Result<usize> {
+        let start = Instant::now();
+
+        /* Wait for all clients to join in */
+        loop {
+            if let Some(cnt) = self.tree_count() {
+                if cnt == child_count {
+                    let tree_count = self.state.lock().unwrap().count()?;
+                    log::trace!("Tree is currently hosting {} processes", tree_count);
+                    if cnt == tree_count as usize {
+                        return Ok(cnt);
+                    }
+                }
+            }
+
+            if let Some(timeout) = timeout {
+                if start.elapsed() >= timeout {
+                    let tree_count = self.state.lock().unwrap().count()?;
+                    log::warn!(
+                        "Join timeout reached with {} of {} processes",
+                        tree_count,
+                        child_count
+                    );
+                    return Ok(tree_count as usize);
+                }
+            }
+
+            sleep(Duration::from_millis(500));
+        }
+    }
+
+    /// Stop building the tree, `allow_late` lets processes join the running session
+    pub fn close_joins(&self, allow_late: bool) {
+        if let Ok(state) = self.state.lock().as_mut() {
+            if let Some(st) = state.as_treestate() {
+                st.joining = if allow_late {
+                    Joining::Late
+                } else {
+                    Joining::Closed
+                };
+            }
+        }
+    }
+
+    pub fn run_as_root(topology: Topology) -> Result<(Arc<GdbMachine>, RootDebugger)> {
+        /* Inherited by the leaves along with the root server address */
+        auth::ensure_token()?;
+
+        let srv = GdbMachine::new(
+            &transport::bind_address()?.to_string(),
+            DummyDebugger::instance(),
+        )?;
+        /* Only meant for the root, the leaves run on other hosts */
+        std::env::remove_var(transport::ADVERTISE_ADDR_ENV);
+        if transport::bind_is_address() {
+            std::env::remove_var(transport::BIND_INTERFACE_ENV);
+        }
+        srv.set_master(topology);
+
+        let srv = Arc::new(srv);
+
+        let psrv = srv.clone();
+        thread::spawn(move || {
+            psrv.run().unwrap();
+        });
+
+        let rdbg = RootDebugger {
             state: srv.state.clone(),
             child_proc: None,
         };
@@ -584,6 +1452,14 @@ impl GdbMachine {
     ) -> Option<GdbMachineResponse> {
         let mut dbg = dbg.lock().unwrap();
 
+        GdbMachine::do_locked_cmd(&mut dbg, state, cmd)
+    }
+
+    fn do_locked_cmd(
+        dbg: &mut Box<dyn Debugger>,
+        state: Option<Arc<Mutex<Box<dyn Debugger>>>>,
+        cmd: &GdbMachineCommand,
+    ) -> Option<GdbMachineResponse> {
         match cmd {
             GdbMachineCommand::Start => Some(GdbMachineResponse::from_result(dbg.start())),
             GdbMachineCommand::Stop => Some(GdbMachineResponse::from_result(dbg.stop())),
@@ -592,18 +1468,36 @@ impl GdbMachine {
             GdbMachineCommand::GetSnapshot => {
                 Some(GdbMachineResponse::snapshot_from_result(dbg.snapshot()))
             }
+            GdbMachineCommand::GetProcessSnapshot(id) => Some(
+                GdbMachineResponse::process_snapshot_from_result(dbg.process_snapshot(*id)),
+            ),
+            GdbMachineCommand::Eval(expr) => {
+                Some(GdbMachineResponse::values_from_result(dbg.eval(expr)))
+            }
+            GdbMachineCommand::GetProcesses => {
+                Some(GdbMachineResponse::processes_from_result(dbg.processes()))
+            }
             GdbMachineCommand::GetSymbols => {
                 Some(GdbMachineResponse::symbols_from_result(dbg.symbols()))
             }
+            GdbMachineCommand::GetLocks => {
+                Some(GdbMachineResponse::locks_from_result(dbg.mutex_deadlocks()))
+            }
+            GdbMachineCommand::GetMpiWaits => {
+                Some(GdbMachineResponse::mpi_waits_from_result(dbg.mpi_waits()))
+            }
+            GdbMachineCommand::GetLibraries => {
+                Some(GdbMachineResponse::libraries_from_result(dbg.libraries()))
+            }
             GdbMachineCommand::Count => Some(GdbMachineResponse::Count(dbg.count().unwrap_or(0))),
-            GdbMachineCommand::Pivot(process_info, from) => {
+            GdbMachineCommand::Pivot(process_info, from, caps) => {
                 let ret = if let Some(state) = state {
                     let mut state = state.lock().unwrap();
 
                     let tree_state = state.as_mut().as_treestate().unwrap();
 
-                    match tree_state.pivot(process_info, from.clone()) {
-                        Ok((id, targ)) => Some(GdbMachineResponse::Pivot(id, targ)),
+                    match tree_state.pivot(process_info, from.clone(), caps) {
+                        Ok((placement, late)) => Some(GdbMachineResponse::Pivot(placement, late)),
                         Err(e) => Some(GdbMachineResponse::Error(e.to_string())),
                     }
                 } else {
@@ -611,12 +1505,12 @@ impl GdbMachine {
                 };
                 ret
             }
-            GdbMachineCommand::Join(target) => {
+            GdbMachineCommand::Join(target, id, span, format, caps) => {
                 let ret = if let Some(state) = state {
                     let mut state = state.lock().unwrap();
                     let tree_state = state.as_mut().as_treestate().unwrap();
 
-                    match tree_state.join(target.clone()) {
+                    match tree_state.join(target.clone(), *id, *span, *format, caps) {
                         Ok(()) => Some(GdbMachineResponse::Ok),
                         Err(e) => Some(GdbMachineResponse::Error(e.to_string())),
                     }
@@ -625,6 +1519,28 @@ impl GdbMachine {
                 };
                 ret
             }
+            /* Answered by the client loop */
+            GdbMachineCommand::Heartbeat
+            | GdbMachineCommand::Hello(_)
+            | GdbMachineCommand::StreamSnapshot => None,
+            GdbMachineCommand::Targeted(ids, cmd) => {
+                if state.is_some() {
+                    /* Local debugger only runs if targeted */
+                    if ids.contains(&dbg.get_id()) {
+                        GdbMachine::do_locked_cmd(dbg, state, cmd)
+                    } else {
+                        None
+                    }
+                } else {
+                    /* Tree state forwards to the subtrees hosting the ids */
+                    if let Err(e) = dbg.set_targets(Some(Targets::Ids(ids.clone()))) {
+                        return Some(GdbMachineResponse::Error(e.to_string()));
+                    }
+                    let ret = GdbMachine::do_locked_cmd(dbg, None, cmd);
+                    let _ = dbg.set_targets(None);
+                    ret
+                }
+            }
         }
     }
 
@@ -639,71 +1555,240 @@ impl GdbMachine {
         let st1 = state.clone();
         let st2 = state.clone();
 
-        // Use rayon's scope to execute the tasks in parallel
-        scope(|s| {
-            s.spawn(|_| {
+        /* Not on the global pool, the subtree may block for the child timeout */
+        thread::scope(|s| {
+            s.spawn(|| {
                 // Execute the first command in a separate thread
                 remote_result = GdbMachine::do_cmd(st1, None, &cmd);
             });
 
-            s.spawn(|_| {
+            s.spawn(|| {
                 // Execute the second command in a separate thread
                 local_result = GdbMachine::do_cmd(dbg, Some(st2), &cmd);
             });
         });
 
-        if let Some(local) = local_result {
-            return TreeState::merge_results(local, remote_result);
+        match (local_result, remote_result) {
+            (Some(local), remote) => TreeState::merge_results(local, remote),
+            /* Local debugger was not targeted */
+            (None, Some(remote)) => remote,
+            (None, None) => {
+                GdbMachineResponse::Error("Local command did not return a response".to_string())
+            }
         }
+    }
+
+    /// Snapshot the local debugger and the subtree, sending the parts as they come
+    ///
+    /// Parts arriving close together are merged so that a wide subtree does
+    /// not flood its parent with small messages.
+    fn _stream_snapshot(
+        dbg: Arc<Mutex<Box<dyn Debugger>>>,
+        state: Arc<Mutex<Box<dyn Debugger>>>,
+        mut send: impl FnMut(SnapshotPart) -> Result<()>,
+    ) -> GdbMachineResponse {
+        let (tx, rx) = mpsc::channel();
+        let local_tx = tx.clone();
+
+        let ret = thread::scope(|s| {
+            s.spawn(move || {
+                let mut dbg = dbg.lock().unwrap();
+                let local_id = dbg.get_id();
+
+                /* A failing local debugger should not hide the rest of the subtree */
+                if let Err(e) = dbg.snapshot_stream(&local_tx) {
+                    log::warn!("Local debugger {} failed : {}", local_id, e);
+                    let _ = local_tx.send(SnapshotPart::unreachable(1));
+                }
+            });
+
+            let remote = s.spawn(move || state.lock().unwrap().snapshot_stream(&tx));
+
+            let mut pending = SnapshotPart::default();
+            let mut last = Instant::now();
 
-        GdbMachineResponse::Error("Local command did not return a response".to_string())
+            loop {
+                match rx.recv_timeout(STREAM_FLUSH_INTERVAL) {
+                    Ok(part) => pending.merge(part),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                if !pending.is_empty() && last.elapsed() >= STREAM_FLUSH_INTERVAL {
+                    send(std::mem::take(&mut pending))?;
+                    last = Instant::now();
+                }
+            }
+
+            if !pending.is_empty() {
+                send(pending)?;
+            }
+
+            remote
+                .join()
+                .map_err(|_| anyhow!("Subtree snapshot panicked"))?
+        });
+
+        GdbMachineResponse::from_result(ret)
+    }
+
+    /// The parent stopped sending heartbeats, release the program and leave
+    fn parent_lost(dbg: &Arc<Mutex<Box<dyn Debugger>>>, reason: &str) -> ! {
+        log::error!("Lost the link to the parent node : {}", reason);
+
+        /* A command may hold the debugger until its own timeout */
+        let start = Instant::now();
+
+        loop {
+            match dbg.try_lock() {
+                Ok(mut dbg) => {
+                    if let Err(e) = dbg.detach() {
+                        log::error!("Failed to detach from the program : {}", e);
+                    }
+                    break;
+                }
+                Err(TryLockError::WouldBlock) if start.elapsed() < PARENT_LOST_DETACH_WAIT => {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Err(_) => {
+                    log::error!("The debugger is busy, leaving without detaching");
+                    break;
+                }
+            }
+        }
+
+        std::process::exit(1);
     }
 
     fn _client_loop(
-        mut sock: TcpStream,
+        sock: Stream,
         dbg: Arc<Mutex<Box<dyn Debugger>>>,
         state: Arc<Mutex<Box<dyn Debugger>>>,
+        health: Arc<Mutex<HealthMonitor>>,
     ) -> Result<()> {
-        loop {
-            let resp = read_until_null(&mut sock)?;
-
-            if resp.is_empty() {
-                break;
+        /* The connection heartbeating us comes from the parent */
+        let mut parent_link = false;
+        let (mut reader, mut writer) = match auth::accept(&sock) {
+            Ok(channel) => channel,
+            Err(e) => {
+                log::warn!("Refused connection from {} : {}", sock.peer(), e);
+                return Ok(());
             }
+        };
 
-            log::debug!("INBOUND: {:?}", resp);
+        loop {
+            let (msg, format) = match wire::recv_message(&mut reader) {
+                Ok(Some(v)) => v,
+                Ok(None) => {
+                    if parent_link {
+                        GdbMachine::parent_lost(&dbg, "connection closed");
+                    }
+                    break;
+                }
+                Err(e) if parent_link => GdbMachine::parent_lost(&dbg, &e.to_string()),
+                Err(e) => return Err(e),
+            };
 
-            let cmd: GdbMachineCommand = serde_json::from_str(&resp)?;
+            let cmd: GdbMachineCommand = match format.decode(&msg) {
+                Ok(cmd) => cmd,
+                Err(e) => {
+                    /* Most likely a peer built from another version */
+                    let err = format!(
+                        "Cannot decode the command ({}), protocol version {} expected",
+                        e,
+                        wire::PROTOCOL_VERSION
+                    );
+                    log::error!("{}", err);
+                    format.send(&mut writer, &GdbMachineResponse::Error(err))?;
+                    continue;
+                }
+            };
 
-            let resp = GdbMachine::_run_command(dbg.clone(), state.clone(), cmd);
+            log::debug!("INBOUND ({}): {:?}", format, cmd);
 
-            log::debug!("OUTBOUND: {:?}", resp);
+            let resp = match cmd {
+                GdbMachineCommand::Heartbeat => {
+                    if !parent_link {
+                        parent_link = true;
+                        sock.set_read_timeout(Some(health::parent_timeout()))?;
+                    }
+                    GdbMachineResponse::Health(health.lock().unwrap().report())
+                }
+                GdbMachineCommand::Hello(peer) => match peer.check() {
+                    Ok(()) => GdbMachineResponse::Hello(Hello::local()),
+                    Err(e) => {
+                        log::error!("Refusing peer : {}", e);
+                        GdbMachineResponse::Error(e.to_string())
+                    }
+                },
+                GdbMachineCommand::StreamSnapshot => {
+                    GdbMachine::_stream_snapshot(dbg.clone(), state.clone(), |part| {
+                        format.send(&mut writer, &GdbMachineResponse::SnapshotPart(part))
+                    })
+                }
+                cmd => GdbMachine::_run_command(dbg.clone(), state.clone(), cmd),
+            };
 
-            let resp_json = serde_json::to_string(&resp)?;
+            log::debug!("OUTBOUND: {:?}", resp);
 
-            /* Write JSON */
-            sock.write_all(resp_json.as_bytes())?;
-            /* Write Separator */
-            sock.write_all("\0".as_bytes())?;
-            sock.flush()?;
+            /* Answer in the format we were asked in */
+            format.send(&mut writer, &resp)?;
         }
 
         Ok(())
     }
 
-    pub fn run(&self) -> Result<()> {
-        loop {
-            let (stream, _) = self.listening_sock.accept()?;
-
-            let dbg = self.dbg.clone();
-            let state = self.state.clone();
-            thread::spawn(move || match GdbMachine::_client_loop(stream, dbg, state) {
+    fn serve(&self, stream: Stream) {
+        let dbg = self.dbg.clone();
+        let state = self.state.clone();
+        let health = self.health.clone();
+        thread::spawn(
+            move || match GdbMachine::_client_loop(stream, dbg, state, health) {
                 Ok(_) => {}
                 Err(e) => {
                     println!("Error processing client request : {}", e);
                 }
+            },
+        );
+    }
+
+    pub fn run(&self) -> Result<()> {
+        HealthMonitor::start(self.health.clone())?;
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                let mut backoff = ACCEPT_BACKOFF_MIN;
+
+                loop {
+                    match self.unix_sock.accept() {
+                        Ok((stream, _)) => {
+                            backoff = ACCEPT_BACKOFF_MIN;
+                            self.serve(Stream::Unix(stream))
+                        }
+                        Err(e) => {
+                            /* e.g. out of file descriptors, give the others time to close */
+                            log::error!("Failed to accept on the Unix socket : {}", e);
+                            sleep(backoff);
+                            backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                        }
+                    }
+                }
             });
-        }
+
+            loop {
+                let (stream, _) = self.listening_sock.accept()?;
+                self.serve(Stream::Tcp(stream));
+            }
+        })
+    }
+
+    /// How to reach this server from the same host and from the others
+    pub fn endpoint(&self) -> Result<Endpoint> {
+        Ok(Endpoint {
+            host: transport::local_host()?,
+            tcp: self.url()?,
+            unix: Some(self.unix_url.clone()),
+        })
     }
 
     pub fn url(&self) -> Result<String> {
@@ -714,9 +1799,21 @@ impl GdbMachine {
         ))
     }
 
-    pub fn set_master(&self) {
+    pub fn set_master(&self, topology: Topology) {
+        if let Ok(state) = self.state.lock().as_mut() {
+            state
+                .as_treestate()
+                .unwrap()
+                .set_root(self.endpoint().unwrap(), topology);
+        }
+    }
+
+    /// Time given to the children to answer, instead of [CHILD_TIMEOUT_ENV]
+    pub fn set_child_timeout(&self, timeout: Duration) {
         if let Ok(state) = self.state.lock().as_mut() {
-            state.as_treestate().unwrap().set_root(self.url().unwrap());
+            if let Some(tree) = state.as_treestate() {
+                tree.timeout = Some(timeout);
+            }
         }
     }
 
@@ -730,6 +1827,14 @@ impl GdbMachine {
         }
     }
 
+    pub fn set_depth(&self, depth: u32) {
+        if let Ok(state) = self.state.lock().as_mut() {
+            if let Some(st) = state.as_treestate() {
+                st.depth = depth;
+            }
+        }
+    }
+
     pub fn tree_count(&self) -> Option<usize> {
         let mut state = self.state.lock().unwrap();
 
@@ -743,8 +1848,7 @@ impl GdbMachine {
             return None;
         }
 
-        /* We remove 1 as the root is self-pushed in this vec */
-        Some(state.as_treestate().unwrap().seen_children.len() - 1)
+        state.as_treestate().unwrap().plan.as_ref().map(|p| p.len())
     }
 }
 
@@ -825,10 +1929,343 @@ impl Debugger for RootDebugger {
         }
     }
 
+    fn snapshot_stream(&mut self, parts: &Sender<SnapshotPart>) -> Result<()> {
+        match self.state.lock().as_mut() {
+            Ok(st) => st.snapshot_stream(parts),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    fn health(&mut self) -> Result<Vec<SubtreeHealth>> {
+        match self.state.lock().as_mut() {
+            Ok(st) => st.health(),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    fn capabilities(&mut self) -> Result<Capabilities> {
+        match self.state.lock().as_mut() {
+            Ok(st) => st.capabilities(),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    fn layout(&mut self) -> Result<TreeLayout> {
+        match self.state.lock().as_mut() {
+            Ok(st) => st.layout(),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    fn set_targets(&mut self, targets: Option<Targets>) -> Result<()> {
+        match self.state.lock().as_mut() {
+            Ok(st) => st.set_targets(targets),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    fn process_snapshot(&mut self, id: u64) -> Result<Option<ProgramSnapshot>> {
+        match self.state.lock().as_mut() {
+            Ok(st) => st.process_snapshot(id),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    fn eval(&mut self, expr: &str) -> Result<HashMap<u64, String>> {
+        match self.state.lock().as_mut() {
+            Ok(st) => st.eval(expr),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    fn processes(&mut self) -> Result<HashMap<u64, ProcessInfo>> {
+        match self.state.lock().as_mut() {
+            Ok(st) => st.processes(),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
     fn symbols(&mut self) -> Result<SymbolTable> {
         match self.state.lock().as_mut() {
             Ok(st) => st.symbols(),
             Err(e) => Err(anyhow!(e.to_string())),
         }
     }
+
+    fn mutex_deadlocks(&mut self) -> Result<HashMap<u64, LockReport>> {
+        match self.state.lock().as_mut() {
+            Ok(st) => st.mutex_deadlocks(),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    fn mpi_waits(&mut self) -> Result<HashMap<u64, MpiRankState>> {
+        match self.state.lock().as_mut() {
+            Ok(st) => st.mpi_waits(),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    fn libraries(&mut self) -> Result<LibraryReport> {
+        match self.state.lock().as_mut() {
+            Ok(st) => st.libraries(),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::StopReason;
+
+    const LEAVES: u64 = 7;
+
+    /// Process whose stack only depends on its id
+    struct FakeDebugger {
+        id: u64,
+        info: ProcessInfo,
+        /* Id of the process which stops answering snapshots */
+        slow: Arc<Mutex<Option<u64>>>,
+    }
+
+    impl FakeDebugger {
+        fn stack(&self) -> Vec<BacktraceState> {
+            vec![BacktraceState::from(&StopReason {
+                reason: format!("stack-{}", self.id % 3),
+                ..StopReason::unreachable()
+            })]
+        }
+    }
+
+    impl Debugger for FakeDebugger {
+        fn count(&mut self) -> Result<u64> {
+            Ok(1)
+        }
+
+        fn set_id(&mut self, id: u64) {
+            self.id = id;
+        }
+
+        fn get_id(&self) -> u64 {
+            self.id
+        }
+
+        fn start(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn cont(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn state(&mut self) -> Result<HashMap<u64, RunState>> {
+            Ok(HashMap::from([(
+                self.id,
+                RunState::Running("running".to_string()),
+            )]))
+        }
+
+        fn snapshot(&mut self) -> Result<HashMap<u64, (u64, Vec<BacktraceState>)>> {
+            if *self.slow.lock().unwrap() == Some(self.id) {
+                sleep(Duration::from_secs(3));
+            }
+
+            Ok(HashMap::from([(self.id % 3, (1, self.stack()))]))
+        }
+
+        fn process_snapshot(&mut self, _id: u64) -> Result<Option<ProgramSnapshot>> {
+            Ok(None)
+        }
+
+        fn eval(&mut self, _expr: &str) -> Result<HashMap<u64, String>> {
+            Ok(HashMap::new())
+        }
+
+        fn processes(&mut self) -> Result<HashMap<u64, ProcessInfo>> {
+            Ok(HashMap::from([(self.id, self.info.clone())]))
+        }
+
+        fn symbols(&mut self) -> Result<SymbolTable> {
+            Ok(SymbolTable::default())
+        }
+
+        fn mutex_deadlocks(&mut self) -> Result<HashMap<u64, LockReport>> {
+            Ok(HashMap::new())
+        }
+
+        fn mpi_waits(&mut self) -> Result<HashMap<u64, MpiRankState>> {
+            Ok(HashMap::new())
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+
+    fn fake_root() -> Arc<GdbMachine> {
+        let root = Arc::new(GdbMachine::new("127.0.0.1:0", DummyDebugger::instance()).unwrap());
+        root.set_master(Topology::Kary(2));
+        root.set_child_timeout(Duration::from_secs(1));
+
+        let srv = root.clone();
+        thread::spawn(move || srv.run());
+
+        root
+    }
+
+    /// Join a fake process of rank `pid` to the tree, returns its id and if it was late
+    fn fake_leaf(
+        root: &GdbMachine,
+        pid: u64,
+        slow: &Arc<Mutex<Option<u64>>>,
+    ) -> Result<(u64, bool)> {
+        let host = transport::local_host()?;
+        let info = ProcessInfo {
+            mpirank: Some(pid as u32),
+            pid,
+            hostname: host.clone(),
+            locality_descriptor: format!("{}-0-{}", host, pid),
+        };
+
+        let dbg: Box<dyn Debugger> = Box::new(FakeDebugger {
+            id: 0,
+            info: info.clone(),
+            slow: slow.clone(),
+        });
+        let leaf = Arc::new(GdbMachine::new("127.0.0.1:0", Arc::new(Mutex::new(dbg)))?);
+        leaf.set_child_timeout(Duration::from_secs(1));
+
+        let late = leaf.attach(&root.url()?, info)?;
+        let id = leaf.dbg.lock().unwrap().get_id();
+
+        thread::spawn(move || leaf.run());
+
+        Ok((id, late))
+    }
+
+    /// A k-ary tree of fake processes, returns the root and the leaf ids
+    fn fake_tree(slow: &Arc<Mutex<Option<u64>>>) -> (Arc<GdbMachine>, Vec<u64>) {
+        let root = fake_root();
+
+        let ids = (0..LEAVES)
+            .map(|pid| fake_leaf(&root, pid, slow).unwrap().0)
+            .collect();
+
+        (root, ids)
+    }
+
+    /// Processes per component
+    fn counts(components: &HashMap<u64, (u64, Vec<BacktraceState>)>) -> HashMap<u64, u64> {
+        components.iter().map(|(k, (c, _))| (*k, *c)).collect()
+    }
+
+    fn streamed(root: &GdbMachine) -> SnapshotPart {
+        let (tx, rx) = mpsc::channel();
+        root.state.lock().unwrap().snapshot_stream(&tx).unwrap();
+        drop(tx);
+
+        let mut snap = SnapshotPart::default();
+        for part in rx {
+            snap.merge(part);
+        }
+        snap
+    }
+
+    /// Ranks of the processes in the tree
+    fn ranks(root: &GdbMachine) -> Vec<u32> {
+        let procs = root.state.lock().unwrap().processes().unwrap();
+        let mut ret: Vec<u32> = procs.values().filter_map(|p| p.mpirank).collect();
+        ret.sort();
+        ret
+    }
+
+    #[test]
+    fn join_timeout_without_a_launcher() {
+        let slow = Arc::new(Mutex::new(None));
+        let root = fake_root();
+
+        /* The launcher of rank 3 never connects */
+        for pid in (0..LEAVES).filter(|p| *p != 3) {
+            assert!(!fake_leaf(&root, pid, &slow).unwrap().1);
+        }
+
+        let start = Instant::now();
+        let joined = root
+            .wait_for_child(LEAVES as usize, Some(Duration::from_secs(1)))
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(joined, LEAVES as usize - 1);
+
+        root.close_joins(false);
+        assert_eq!(ranks(&root), [0, 1, 2, 4, 5, 6]);
+        assert_eq!(root.state.lock().unwrap().count().unwrap(), LEAVES - 1);
+
+        /* It finally starts after the session started without it */
+        let err = fake_leaf(&root, 3, &slow).unwrap_err();
+        assert!(err.to_string().contains("after the join timeout"));
+        assert_eq!(ranks(&root).len(), LEAVES as usize - 1);
+    }
+
+    #[test]
+    fn late_launcher_joins() {
+        let slow = Arc::new(Mutex::new(None));
+        let root = fake_root();
+
+        for pid in 0..LEAVES - 1 {
+            fake_leaf(&root, pid, &slow).unwrap();
+        }
+
+        let joined = root
+            .wait_for_child(LEAVES as usize, Some(Duration::from_secs(1)))
+            .unwrap();
+        assert_eq!(joined, LEAVES as usize - 1);
+        root.close_joins(true);
+
+        let (_, late) = fake_leaf(&root, LEAVES - 1, &slow).unwrap();
+        assert!(late);
+        assert_eq!(ranks(&root), (0..LEAVES as u32).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn streamed_snapshot_matches_one_shot() {
+        let slow = Arc::new(Mutex::new(None));
+        let (root, ids) = fake_tree(&slow);
+
+        assert_eq!(root.state.lock().unwrap().count().unwrap(), LEAVES);
+
+        let once = root.state.lock().unwrap().snapshot().unwrap();
+        let snap = streamed(&root);
+
+        assert_eq!(snap.reported, LEAVES);
+        assert_eq!(counts(&snap.components), counts(&once));
+        assert_eq!(counts(&once).values().sum::<u64>(), LEAVES);
+
+        /* The first child of the root, which has children, stops answering */
+        *slow.lock().unwrap() = Some(ids[0]);
+
+        let snap = streamed(&root);
+        let missing = ProgramSnapshot::unreachable_components(1);
+        let missing = missing.keys().next().unwrap();
+
+        assert_eq!(snap.reported, LEAVES);
+        assert!(snap.components.contains_key(missing));
+
+        /* Still busy, the whole subtree is known from the placements */
+        let once = root.state.lock().unwrap().snapshot().unwrap();
+        assert_eq!(counts(&once).values().sum::<u64>(), LEAVES);
+        assert!(counts(&once)[missing] > 1);
+
+        /* A stall is not a death, the subtree answers on a new link */
+        *slow.lock().unwrap() = None;
+        sleep(Duration::from_secs(4));
+
+        let once = root.state.lock().unwrap().snapshot().unwrap();
+        assert!(!once.contains_key(missing));
+        assert_eq!(counts(&once).values().sum::<u64>(), LEAVES);
+    }
 }
@@ -0,0 +1,1274 @@
+//! LLDB backend speaking the Debug Adapter Protocol (DAP) to `lldb-dap`
+//!
+//! Codes built with clang and recent DWARF may not be readable by the GDB of
+//! the cluster. This backend launches the program under `lldb-dap` and maps
+//! the DAP threads, stack frames, scopes and stop events to the same
+//! [ProgramSnapshot] as the GDB-MI backend so that both can be mixed in a tree.
+//!
+//! Analyses relying on GDB scripting (Python frames, disassembly, forks) are
+//! not available with this backend.
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde_json::{json, Value};
+use std::any::Any;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use crate::auth;
+use crate::binary;
+use crate::debugger::Debugger;
+use crate::metadata::*;
+
+/// Path of the `lldb-dap` executable (`lldb-vscode` in LLVM before 18)
+pub const LLDB_DAP_ENV: &str = "GDBW_LLDB_DAP";
+
+const DEFAULT_LLDB_DAP: &str = "lldb-dap";
+
+/// Frames requested per thread (same bound as the GDB backend)
+const MAX_FRAMES: u32 = 1000;
+
+/// Time given to `lldb-dap` to load the program before configuring it
+const LAUNCH_TIMEOUT_MS: u128 = 30000;
+
+/// Find `program` in the `PATH` as `lldb-dap` only takes paths
+fn resolve_program(program: &str) -> String {
+    if program.contains('/') {
+        return program.to_string();
+    }
+
+    std::env::var_os("PATH")
+        .and_then(|paths| {
+            std::env::split_paths(&paths)
+                .map(|p| p.join(program))
+                .find(|p| p.is_file())
+        })
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or(program.to_string())
+}
+
+/// Split the frame names of LLDB (`libc.so.6`__poll + 12`) in module, function and offset
+fn split_frame_name(name: &str) -> (Option<String>, String, Option<u64>) {
+    let (module, func) = match name.split_once('`') {
+        Some((m, f)) => (Some(m.to_string()), f),
+        None => (None, name),
+    };
+
+    match func.rsplit_once(" + ") {
+        Some((f, off)) if off.parse::<u64>().is_ok() => {
+            (module, f.to_string(), off.parse::<u64>().ok())
+        }
+        _ => (module, func.to_string(), None),
+    }
+}
+
+/// Frame of a backtrace from a DAP stack frame
+fn debug_frame(level: u32, f: &Value, modules: &HashMap<String, String>) -> DebugFrame {
+    let (module, func, offset) = split_frame_name(f["name"].as_str().unwrap_or("??"));
+    let fullname = f["source"]["path"].as_str().map(|v| v.to_string());
+    let line = f["line"].as_u64().filter(|l| *l != 0).map(|l| l as u32);
+
+    let from = match (&line, &f["moduleId"]) {
+        (Some(_), _) => None,
+        (None, Value::String(m)) => modules.get(m).cloned().or(module),
+        (None, _) => module,
+    };
+
+    DebugFrame {
+        level,
+        addr: f["instructionPointerReference"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        func,
+        file: f["source"]["name"].as_str().map(|v| v.to_string()),
+        fullname,
+        line,
+        from,
+        offset: offset.filter(|_| line.is_none()),
+        disassembly: None,
+        inlined: false,
+        arch: None,
+        args: None,
+        locals: None,
+    }
+}
+
+/// `si_code` of the faults by the descriptions of LLDB, see [CrashInfo::cause]
+const FAULT_CODES: [(&str, &str, i32); 13] = [
+    ("SIGSEGV", "address not mapped", 1),
+    ("SIGSEGV", "invalid permissions", 2),
+    ("SIGBUS", "illegal alignment", 1),
+    ("SIGBUS", "illegal address", 2),
+    ("SIGBUS", "hardware error", 3),
+    ("SIGFPE", "integer divide by zero", 1),
+    ("SIGFPE", "integer overflow", 2),
+    ("SIGFPE", "floating point divide by zero", 3),
+    ("SIGFPE", "floating point overflow", 4),
+    ("SIGFPE", "floating point underflow", 5),
+    ("SIGFPE", "inexact result", 6),
+    ("SIGFPE", "invalid operation", 7),
+    ("SIGFPE", "subscript out of range", 8),
+];
+
+/// Fault address and `si_code` from the description of a stop
+///
+/// LLDB describes faults as `signal SIGSEGV: address not mapped to object
+/// (fault address: 0x0)`, signals sent by other processes have no details.
+fn fault_from_description(signal: &str, description: &str) -> (Option<String>, Option<i32>) {
+    let fault_addr = Regex::new("fault address: (0x[0-9a-fA-F]+)")
+        .ok()
+        .and_then(|re| re.captures(description))
+        .and_then(|c| c.get(1))
+        .map(|v| v.as_str().to_string());
+
+    let code = FAULT_CODES
+        .iter()
+        .find(|(sig, text, _)| *sig == signal && description.contains(text))
+        .map(|(_, _, code)| *code);
+
+    (fault_addr, code)
+}
+
+/// Stop reason in the terms of GDB-MI from a DAP `stopped` event
+///
+/// Signals are reported as exceptions described by `signal SIGSEGV: ...`
+/// and interrupts as pauses, which GDB reports as a SIGINT.
+fn stop_reason_from_dap(body: &Value) -> StopReason {
+    let reason = body["reason"].as_str().unwrap_or_default();
+    let description = body["description"]
+        .as_str()
+        .or(body["text"].as_str())
+        .unwrap_or_default();
+
+    let signal = Regex::new("signal (SIG[A-Z0-9]+)")
+        .ok()
+        .and_then(|re| re.captures(description))
+        .and_then(|c| c.get(1))
+        .map(|v| v.as_str().to_string());
+
+    let (reason, signal_name) = match (reason, signal) {
+        (_, Some(sig)) => ("signal-received", Some(sig)),
+        ("pause", None) => ("signal-received", Some("SIGINT".to_string())),
+        ("breakpoint" | "function breakpoint" | "data breakpoint", None) => {
+            ("breakpoint-hit", None)
+        }
+        ("step", None) => ("end-stepping-range", None),
+        (other, None) => (other, None),
+    };
+
+    StopReason {
+        reason: reason.to_string(),
+        signal_name,
+        meaning: Some(description.to_string()).filter(|v| !v.is_empty()),
+        thread_id: body["threadId"].as_u64().map(|v| v as u32),
+        ..StopReason::unreachable()
+    }
+}
+
+/// Stop reason of a process which exited with `code`
+fn exit_reason(code: Option<i32>) -> StopReason {
+    let reason = match code {
+        Some(0) => "exited-normally",
+        _ => "exited",
+    };
+
+    StopReason {
+        reason: reason.to_string(),
+        exit_code: code.filter(|v| *v != 0),
+        ..StopReason::unreachable()
+    }
+}
+
+pub struct DapState {
+    seq: u64,
+    thread_stdout: Option<JoinHandle<Result<()>>>,
+    dap_in: ChildStdin,
+    running: bool,
+    /// Responses by the `seq` of their request
+    resps: HashMap<u64, Value>,
+    /// The adapter accepts the configuration requests
+    initialized: bool,
+    runstate: Option<RunState>,
+    /// Process id of the program once launched
+    pid: Option<u64>,
+    /// DAP frame ids of the last backtraces by thread and level
+    frame_ids: HashMap<(u32, u32), u64>,
+}
+
+impl DapState {
+    fn isrunning(state: &Arc<Mutex<DapState>>) -> Result<bool> {
+        if let Ok(st) = state.lock() {
+            return Ok(st.running);
+        }
+
+        Err(anyhow!("Failed to lock"))
+    }
+
+    fn setrunstate(state: &Arc<Mutex<DapState>>, runstate: RunState) -> Result<()> {
+        if let Ok(st) = state.lock().as_mut() {
+            st.runstate = Some(runstate);
+            /* Frame ids are only valid while the process is stopped */
+            st.frame_ids.clear();
+            return Ok(());
+        }
+
+        Err(anyhow!("Failed to lock"))
+    }
+
+    fn get_stop_state(state: &Arc<Mutex<DapState>>) -> Option<StopReason> {
+        if let Ok(st) = state.lock() {
+            if let Some(RunState::Stopped(st)) = st.runstate.clone() {
+                return Some(*st);
+            }
+        }
+
+        None
+    }
+
+    fn pid(state: &Arc<Mutex<DapState>>) -> Option<u64> {
+        state.lock().ok().and_then(|st| st.pid)
+    }
+
+    /// Read a `Content-Length` framed message
+    fn read_message<T: BufRead>(output: &mut T) -> Result<Value> {
+        let mut len = None;
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+
+            if output.read_line(&mut line)? == 0 {
+                return Err(anyhow!("lldb-dap closed its output"));
+            }
+
+            let header = line.trim_end();
+
+            if header.is_empty() {
+                break;
+            }
+
+            if let Some(v) = header.strip_prefix("Content-Length:") {
+                len = Some(v.trim().parse::<usize>()?);
+            }
+        }
+
+        let len = len.ok_or(anyhow!("DAP message without Content-Length"))?;
+        let mut body = vec![0; len];
+        output.read_exact(&mut body)?;
+
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Write a `Content-Length` framed message
+    fn write_message<T: Write>(input: &mut T, msg: &Value) -> Result<()> {
+        let msg = msg.to_string();
+
+        write!(input, "Content-Length: {}\r\n\r\n{}", msg.len(), msg)?;
+        input.flush()?;
+
+        Ok(())
+    }
+
+    /// Body of a successful response or its error
+    fn response_body(resp: &Value) -> Result<Value> {
+        if resp["success"].as_bool() == Some(true) {
+            return Ok(resp["body"].clone());
+        }
+
+        let error = resp["body"]["error"]["format"]
+            .as_str()
+            .or(resp["message"].as_str())
+            .unwrap_or("unknown error");
+
+        Err(anyhow!(
+            "Command {} returned an error : {}",
+            resp["command"].as_str().unwrap_or_default(),
+            error
+        ))
+    }
+
+    fn handle_event(state: &Arc<Mutex<DapState>>, msg: &Value) -> Result<()> {
+        let body = &msg["body"];
+
+        match msg["event"].as_str().unwrap_or_default() {
+            "initialized" => {
+                if let Ok(st) = state.lock().as_mut() {
+                    st.initialized = true;
+                }
+            }
+            "process" => {
+                if let Ok(st) = state.lock().as_mut() {
+                    st.pid = body["systemProcessId"].as_u64();
+                }
+            }
+            "stopped" => {
+                let stop = stop_reason_from_dap(body);
+                DapState::setrunstate(state, RunState::Stopped(Box::new(stop)))?;
+            }
+            "continued" => {
+                DapState::setrunstate(state, RunState::Running("running".to_string()))?;
+            }
+            "exited" => {
+                let code = body["exitCode"].as_i64().map(|v| v as i32);
+                DapState::setrunstate(state, RunState::Stopped(Box::new(exit_reason(code))))?;
+            }
+            "terminated" => {
+                /* Only sent after `exited` unless the process vanished */
+                let exited = DapState::get_stop_state(state).is_some_and(|s| s.exited());
+
+                if !exited {
+                    DapState::setrunstate(state, RunState::Stopped(Box::new(exit_reason(None))))?;
+                }
+            }
+            "output" => {
+                let text = body["output"].as_str().unwrap_or_default();
+
+                match body["category"].as_str() {
+                    Some("stdout") => print!("{}", text),
+                    Some("stderr") => eprint!("{}", text),
+                    _ => log::debug!("lldb-dap: {}", text.trim_end()),
+                }
+            }
+            other => log::trace!("Skipped DAP event {}", other),
+        }
+
+        Ok(())
+    }
+
+    fn thread_loop(state: Arc<Mutex<DapState>>, dap_out: ChildStdout) -> Result<()> {
+        let mut output = BufReader::new(dap_out);
+
+        while DapState::isrunning(&state)? {
+            let msg = DapState::read_message(&mut output)?;
+
+            log::trace!("OUTPUT {}", msg);
+
+            match msg["type"].as_str() {
+                Some("response") => {
+                    if let (Some(seq), Ok(st)) =
+                        (msg["request_seq"].as_u64(), state.lock().as_mut())
+                    {
+                        st.resps.insert(seq, msg);
+                    }
+                }
+                Some("event") => DapState::handle_event(&state, &msg)?,
+                /* Reverse requests (e.g. runInTerminal) are not advertised */
+                _ => log::debug!("Unexpected DAP message {}", msg),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send(state: &Arc<Mutex<DapState>>, command: &str, arguments: Value) -> Result<u64> {
+        if let Ok(st) = state.lock().as_mut() {
+            st.seq += 1;
+
+            let msg = json!({
+                "seq": st.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+
+            DapState::write_message(&mut st.dap_in, &msg)?;
+
+            return Ok(st.seq);
+        }
+
+        Err(anyhow!("Failed to lock"))
+    }
+
+    /// Body of the response to request `seq`
+    fn await_response(state: &Arc<Mutex<DapState>>, seq: u64, timeout_ms: u128) -> Result<Value> {
+        let start_time = Instant::now();
+
+        loop {
+            if let Ok(ls) = state.lock().as_mut() {
+                if let Some(resp) = ls.resps.remove(&seq) {
+                    return DapState::response_body(&resp);
+                }
+            }
+
+            if timeout_ms != 0 && start_time.elapsed().as_millis() > timeout_ms {
+                return Err(anyhow!("Timeout waiting for response"));
+            }
+        }
+    }
+
+    fn request(state: &Arc<Mutex<DapState>>, command: &str, arguments: Value) -> Result<Value> {
+        let seq = DapState::send(state, command, arguments)?;
+        DapState::await_response(state, seq, 0)
+    }
+
+    fn await_initialized(state: &Arc<Mutex<DapState>>, timeout_ms: u128) -> Result<()> {
+        let start_time = Instant::now();
+
+        while !state.lock().is_ok_and(|st| st.initialized) {
+            if start_time.elapsed().as_millis() > timeout_ms {
+                return Err(anyhow!("lldb-dap did not load the program"));
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        Ok(())
+    }
+
+    /// Threads of the process with the names given by the kernel
+    fn thread_info(state: &Arc<Mutex<DapState>>) -> Result<HashMap<u32, ThreadInfo>> {
+        let body = DapState::request(state, "threads", json!({}))?;
+        let pid = DapState::pid(state);
+
+        let ret = body["threads"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|t| t["id"].as_u64())
+            .map(|id| {
+                /* DAP thread ids are the LWPs on Linux */
+                let name = pid.and_then(|pid| {
+                    std::fs::read_to_string(format!("/proc/{}/task/{}/comm", pid, id)).ok()
+                });
+
+                let th = ThreadInfo {
+                    id: id as u32,
+                    target_id: format!("LWP {}", id),
+                    name: name.map(|v| v.trim_end().to_string()),
+                    core: None,
+                };
+
+                (th.id, th)
+            })
+            .collect();
+
+        Ok(ret)
+    }
+
+    /// Paths of the modules loaded by the process by DAP module id
+    fn modules(state: &Arc<Mutex<DapState>>) -> Result<HashMap<String, String>> {
+        let body = DapState::request(state, "modules", json!({}))?;
+
+        Ok(body["modules"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|m| {
+                let id = match &m["id"] {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+
+                m["path"].as_str().map(|p| (id, p.to_string()))
+            })
+            .collect())
+    }
+
+    fn backtrace(
+        state: &Arc<Mutex<DapState>>,
+        threadid: u32,
+        modules: &HashMap<String, String>,
+    ) -> Result<Vec<DebugFrame>> {
+        let body = DapState::request(
+            state,
+            "stackTrace",
+            json!({"threadId": threadid, "startFrame": 0, "levels": MAX_FRAMES}),
+        )?;
+
+        let mut ret = Vec::new();
+
+        for (level, f) in body["stackFrames"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+        {
+            let level = level as u32;
+
+            if let (Some(id), Ok(st)) = (f["id"].as_u64(), state.lock().as_mut()) {
+                st.frame_ids.insert((threadid, level), id);
+            }
+
+            ret.push(debug_frame(level, f, modules));
+        }
+
+        DebugFrame::mark_inlined(&mut ret);
+
+        Ok(ret)
+    }
+
+    fn frame_id(state: &Arc<Mutex<DapState>>, threadid: u32, level: u32) -> Result<u64> {
+        state
+            .lock()
+            .ok()
+            .and_then(|st| st.frame_ids.get(&(threadid, level)).cloned())
+            .ok_or(anyhow!(
+                "No frame {} in the backtrace of thread {}",
+                level,
+                threadid
+            ))
+    }
+
+    /// Arguments and locals of a frame (LLDB may report both as locals)
+    fn locals(
+        state: &Arc<Mutex<DapState>>,
+        threadid: u32,
+        level: u32,
+    ) -> Result<Vec<(String, bool, String)>> {
+        let frame = DapState::frame_id(state, threadid, level)?;
+        let body = DapState::request(state, "scopes", json!({"frameId": frame}))?;
+
+        let mut ret = Vec::new();
+
+        for scope in body["scopes"].as_array().cloned().unwrap_or_default() {
+            let is_arg = match scope["name"].as_str() {
+                Some("Arguments") => true,
+                Some("Locals") => false,
+                _ => continue,
+            };
+
+            let vars = DapState::request(
+                state,
+                "variables",
+                json!({"variablesReference": scope["variablesReference"]}),
+            )?;
+
+            for v in vars["variables"].as_array().cloned().unwrap_or_default() {
+                if let (Some(name), Some(value)) = (v["name"].as_str(), v["value"].as_str()) {
+                    ret.push((name.to_string(), is_arg, value.to_string()));
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+
+    fn evaluate(
+        state: &Arc<Mutex<DapState>>,
+        threadid: u32,
+        level: u32,
+        expr: &str,
+    ) -> Result<String> {
+        let frame = DapState::frame_id(state, threadid, level)?;
+
+        let body = DapState::request(
+            state,
+            "evaluate",
+            json!({"expression": expr, "frameId": frame, "context": "watch"}),
+        )?;
+
+        body["result"]
+            .as_str()
+            .map(|v| v.to_string())
+            .ok_or(anyhow!("No value returned when evaluating {}", expr))
+    }
+
+    /// Backtraces of all the threads
+    fn backtraces(
+        state: &Arc<Mutex<DapState>>,
+        threads: &HashMap<u32, ThreadInfo>,
+    ) -> Result<HashMap<u32, Vec<DebugFrame>>> {
+        /* Older adapters have no modules request, frames then keep LLDB's module */
+        let modules = DapState::modules(state).unwrap_or_default();
+
+        threads
+            .keys()
+            .map(|th| Ok((*th, DapState::backtrace(state, *th, &modules)?)))
+            .collect()
+    }
+
+    /// Instruction at the PC of the innermost frame of a thread
+    fn current_instruction(state: &Arc<Mutex<DapState>>, bt: &[DebugFrame]) -> Result<Instruction> {
+        let frame = bt.first().ok_or(anyhow!("Empty backtrace"))?;
+
+        let body = DapState::request(
+            state,
+            "disassemble",
+            json!({"memoryReference": frame.addr, "instructionCount": 1}),
+        )?;
+
+        let ins = &body["instructions"][0];
+
+        Ok(Instruction {
+            address: ins["address"].as_str().unwrap_or(&frame.addr).to_string(),
+            func: Some(frame.func.clone()),
+            offset: None,
+            text: ins["instruction"]
+                .as_str()
+                .ok_or(anyhow!("Failed to disassemble the program counter"))?
+                .to_string(),
+        })
+    }
+
+    /// General purpose registers of the innermost frame of a thread
+    ///
+    /// They are the first group of the `Registers` scope of lldb-dap.
+    fn registers(state: &Arc<Mutex<DapState>>, threadid: u32) -> Result<Vec<(String, String)>> {
+        let frame = DapState::frame_id(state, threadid, 0)?;
+        let body = DapState::request(state, "scopes", json!({"frameId": frame}))?;
+
+        let scope = body["scopes"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .find(|s| s["name"].as_str() == Some("Registers"))
+            .ok_or(anyhow!("No registers for thread {}", threadid))?;
+
+        let variables = |reference: &Value| -> Result<Vec<Value>> {
+            let body =
+                DapState::request(state, "variables", json!({"variablesReference": reference}))?;
+
+            Ok(body["variables"].as_array().cloned().unwrap_or_default())
+        };
+
+        let groups = variables(&scope["variablesReference"])?;
+        let group = groups
+            .first()
+            .ok_or(anyhow!("No registers for thread {}", threadid))?;
+
+        let registers = variables(&group["variablesReference"])?
+            .into_iter()
+            .flat_map(|v| {
+                Some((
+                    v["name"].as_str()?.to_string(),
+                    v["value"].as_str()?.to_string(),
+                ))
+            });
+
+        Ok(CrashInfo::general_registers(registers))
+    }
+
+    /// Fault details LLDB puts in the description of the stop, with the
+    /// instruction and registers of the faulting thread
+    fn crash_info(
+        state: &Arc<Mutex<DapState>>,
+        stop: &StopReason,
+        backtraces: &HashMap<u32, Vec<DebugFrame>>,
+    ) -> CrashInfo {
+        let signal = stop.signal_name.clone().unwrap_or_default();
+        let (fault_addr, code) =
+            fault_from_description(&signal, stop.meaning.as_deref().unwrap_or_default());
+
+        let th = stop.thread_id.or_else(|| backtraces.keys().min().cloned());
+        let bt = th.and_then(|th| backtraces.get(&th));
+
+        CrashInfo {
+            signal,
+            fault_addr,
+            code,
+            instruction: bt.and_then(|bt| DapState::current_instruction(state, bt).ok()),
+            registers: th
+                .and_then(|th| DapState::registers(state, th).ok())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn snapshot(state: &Arc<Mutex<DapState>>, with_locals: bool) -> Result<ProgramSnapshot> {
+        let threads = DapState::thread_info(state)?;
+        let mut ret = DapState::backtraces(state, &threads)?;
+
+        if with_locals {
+            for (th, bt) in ret.iter_mut() {
+                for frame in bt.iter_mut() {
+                    if let Ok(vars) = DapState::locals(state, *th, frame.level) {
+                        frame.attach_locals(vars);
+                    }
+                }
+            }
+        }
+
+        let stop_state = DapState::get_stop_state(state);
+
+        let crash = match &stop_state {
+            Some(stop) if stop.is_fault() => Some(DapState::crash_info(state, stop, &ret)),
+            _ => None,
+        };
+
+        Ok(ProgramSnapshot {
+            state: ret,
+            stop_state,
+            threads,
+            crash,
+            inferiors: HashMap::new(),
+        })
+    }
+
+    /// Same analysis as the GDB backend, see `GdbMiState::lock_wait`
+    fn lock_report(state: &Arc<Mutex<DapState>>) -> Result<LockReport> {
+        let threads = DapState::thread_info(state)?;
+        let backtraces = DapState::backtraces(state, &threads)?;
+        let mut waits = Vec::new();
+
+        for (th, bt) in backtraces.iter() {
+            let wait = MUTEX_LOCK_FUNCTIONS.iter().find_map(|func| {
+                let frame = bt.iter().find(|f| f.func == *func)?;
+
+                let mutex = if *func == "__lll_lock_wait" {
+                    "((pthread_mutex_t *)futex)"
+                } else {
+                    "mutex"
+                };
+
+                let eval = |expr: &str| DapState::evaluate(state, *th, frame.level, expr);
+
+                let addr = eval(&format!("(unsigned long){}", mutex)).ok()?;
+                let owner = eval(&format!("{}->__data.__owner", mutex))
+                    .ok()
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .filter(|v| *v != 0);
+
+                let addr = addr
+                    .parse::<u64>()
+                    .map(|v| format!("{:#x}", v))
+                    .unwrap_or(addr);
+
+                Some((addr, owner))
+            });
+
+            if let Some((mutex, owner_lwp)) = wait {
+                /* Thread ids are the LWPs */
+                let owner = owner_lwp
+                    .map(|l| l as u32)
+                    .filter(|l| threads.contains_key(l));
+
+                waits.push(LockWait {
+                    thread: *th,
+                    mutex,
+                    owner_lwp,
+                    owner,
+                });
+            }
+        }
+
+        Ok(LockReport::new(waits))
+    }
+
+    /// Same analysis as the GDB backend, see `GdbMiState::mpi_waits`
+    fn mpi_waits(state: &Arc<Mutex<DapState>>) -> Result<Vec<MpiWait>> {
+        let threads = DapState::thread_info(state)?;
+        let mut ret = Vec::new();
+
+        for (th, bt) in DapState::backtraces(state, &threads)? {
+            if let Some((frame, peer)) = MpiWait::find_call(&bt) {
+                let eval = |expr: &str| DapState::evaluate(state, th, frame.level, expr).ok();
+                ret.push(MpiWait::new(th, frame, peer, eval));
+            }
+        }
+
+        Ok(ret)
+    }
+
+    fn new(
+        dap_in: Option<ChildStdin>,
+        dap_out: Option<ChildStdout>,
+    ) -> Result<Arc<Mutex<DapState>>> {
+        if let (Some(dap_in), Some(dap_out)) = (dap_in, dap_out) {
+            let ret = Arc::new(Mutex::new(DapState {
+                seq: 0,
+                thread_stdout: None,
+                dap_in,
+                running: true,
+                resps: HashMap::new(),
+                initialized: false,
+                runstate: None,
+                pid: None,
+                frame_ids: HashMap::new(),
+            }));
+
+            let pstate = ret.clone();
+            let thout = std::thread::spawn(move || DapState::thread_loop(pstate, dap_out));
+
+            if let Ok(st) = ret.lock().as_mut() {
+                st.thread_stdout = Some(thout);
+            }
+
+            Ok(ret)
+        } else {
+            Err(anyhow!(
+                "Failed to capture lldb-dap child process stdin/stdout"
+            ))
+        }
+    }
+}
+
+pub struct LldbDap {
+    id: u64,
+    command: Vec<String>,
+    state: Arc<Mutex<DapState>>,
+    child_proc: Option<Child>,
+}
+
+impl Debugger for LldbDap {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn set_id(&mut self, id: u64) {
+        self.id = id;
+    }
+
+    fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    /// Launch the program, the configuration is done once `lldb-dap` loaded it
+    fn start(&mut self) -> Result<()> {
+        if !self.state()?.is_empty() {
+            /* Already started (e.g. late joiner starting on its own) */
+            return Ok(());
+        }
+
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or(anyhow!("No command to launch"))?;
+
+        let launch = DapState::send(
+            &self.state,
+            "launch",
+            json!({
+                "program": resolve_program(program),
+                "args": args,
+                "cwd": std::env::current_dir()?,
+                "stopOnEntry": false,
+            }),
+        )?;
+
+        DapState::await_initialized(&self.state, LAUNCH_TIMEOUT_MS)?;
+        DapState::request(&self.state, "configurationDone", json!({}))?;
+        DapState::await_response(&self.state, launch, LAUNCH_TIMEOUT_MS)?;
+
+        if let Ok(st) = self.state.lock().as_mut() {
+            /* A stop may already have been reported */
+            if st.runstate.is_none() {
+                st.runstate = Some(RunState::Running("running".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pause the process (the thread id is required but all threads stop)
+    fn stop(&mut self) -> Result<()> {
+        if self.id_is_stopped(self.id)? {
+            /* Already stopped */
+            return Ok(());
+        }
+
+        let thread = DapState::pid(&self.state).unwrap_or_default();
+        DapState::request(&self.state, "pause", json!({"threadId": thread}))?;
+        Ok(())
+    }
+
+    fn cont(&mut self) -> Result<()> {
+        if self.id_is_running(self.id)? {
+            /* Already running */
+            return Ok(());
+        }
+
+        let thread = DapState::pid(&self.state).unwrap_or_default();
+        DapState::request(&self.state, "continue", json!({"threadId": thread}))?;
+        DapState::setrunstate(&self.state, RunState::Running("running".to_string()))?;
+        Ok(())
+    }
+
+    fn state(&mut self) -> Result<HashMap<u64, RunState>> {
+        let mut ret = HashMap::new();
+
+        if let Ok(ls) = self.state.lock() {
+            if let Some(rs) = &ls.runstate {
+                ret.insert(self.id, rs.clone());
+            }
+        }
+
+        Ok(ret)
+    }
+
+    fn snapshot(&mut self) -> Result<HashMap<u64, (u64, Vec<BacktraceState>)>> {
+        if self.id_is_running(self.id)? {
+            self.stop()?;
+        }
+
+        let snap = if self.id_is_exited(self.id)? {
+            ProgramSnapshot::exited(DapState::get_stop_state(&self.state))
+        } else {
+            DapState::snapshot(&self.state, false)?
+        };
+
+        let mut ret = HashMap::new();
+        ret.insert(self.id, snap);
+
+        Ok(ProgramSnapshot::generate_components(ret))
+    }
+
+    fn process_snapshot(&mut self, id: u64) -> Result<Option<ProgramSnapshot>> {
+        if id != self.id {
+            return Ok(None);
+        }
+
+        if self.id_is_running(self.id)? {
+            return Err(anyhow!("Process {} has to be stopped first", self.id));
+        }
+
+        if self.id_is_exited(self.id)? {
+            return Ok(Some(ProgramSnapshot::exited(DapState::get_stop_state(
+                &self.state,
+            ))));
+        }
+
+        Ok(Some(DapState::snapshot(&self.state, true)?))
+    }
+
+    /// Disconnect leaving the program running and terminate `lldb-dap`
+    fn detach(&mut self) -> Result<()> {
+        let ret = DapState::request(
+            &self.state,
+            "disconnect",
+            json!({"terminateDebuggee": false}),
+        );
+
+        if let Ok(st) = self.state.lock().as_mut() {
+            st.running = false;
+        }
+
+        if let Some(child) = &mut self.child_proc {
+            let _ = child.kill();
+        }
+
+        ret.map(|_| ())
+    }
+
+    /// Evaluate an expression in the innermost frame of the stopping thread
+    fn eval(&mut self, expr: &str) -> Result<HashMap<u64, String>> {
+        if self.id_is_running(self.id)? {
+            return Err(anyhow!(
+                "Expressions can only be evaluated on a stopped target"
+            ));
+        }
+
+        let thread = DapState::get_stop_state(&self.state)
+            .and_then(|s| s.thread_id)
+            .or(DapState::pid(&self.state).map(|v| v as u32))
+            .unwrap_or_default();
+
+        let value = DapState::backtrace(&self.state, thread, &HashMap::new())
+            .and_then(|_| DapState::evaluate(&self.state, thread, 0, expr))
+            .unwrap_or_else(|e| format!("<{}>", e));
+
+        let mut ret = HashMap::new();
+        ret.insert(self.id, value);
+
+        Ok(ret)
+    }
+
+    fn processes(&mut self) -> Result<HashMap<u64, ProcessInfo>> {
+        let mut ret = HashMap::new();
+        ret.insert(self.id, ProcessInfo::default()?);
+        Ok(ret)
+    }
+
+    /// Symbols are only listed by the GDB backend
+    ///
+    /// The empty table keeps the listing of a mixed tree going, the
+    /// capabilities of the process tell it does not list them.
+    fn symbols(&mut self) -> Result<SymbolTable> {
+        Ok(SymbolTable::default())
+    }
+
+    fn count(&mut self) -> Result<u64> {
+        Ok(1)
+    }
+
+    fn mutex_deadlocks(&mut self) -> Result<HashMap<u64, LockReport>> {
+        if self.id_is_running(self.id)? {
+            return Err(anyhow!("Locks can only be analyzed on a stopped target"));
+        }
+
+        let mut ret = HashMap::new();
+
+        if self.id_is_exited(self.id)? {
+            return Ok(ret);
+        }
+
+        let report = DapState::lock_report(&self.state)?;
+
+        if !report.waits.is_empty() {
+            ret.insert(self.id, report);
+        }
+
+        Ok(ret)
+    }
+
+    /// Shared libraries from the modules of the process
+    fn libraries(&mut self) -> Result<LibraryReport> {
+        if self.id_is_running(self.id)? {
+            return Err(anyhow!("Libraries can only be listed on a stopped target"));
+        }
+
+        let mut ret = LibraryReport::default();
+
+        if self.id_is_exited(self.id)? {
+            return Ok(ret);
+        }
+
+        let program = self.command.first().map(|p| resolve_program(p));
+
+        for path in DapState::modules(&self.state)?.values() {
+            /* GDB does not count the executable as a shared library */
+            if Some(path) == program.as_ref() || path.starts_with('[') {
+                continue;
+            }
+
+            let build_id = binary::build_id(path).unwrap_or_else(|e| {
+                log::debug!("No build-id for {} : {}", path, e);
+                None
+            });
+
+            ret.add(path, build_id, self.id);
+        }
+
+        Ok(ret)
+    }
+
+    fn mpi_waits(&mut self) -> Result<HashMap<u64, MpiRankState>> {
+        if self.id_is_running(self.id)? {
+            return Err(anyhow!(
+                "MPI calls can only be analyzed on a stopped target"
+            ));
+        }
+
+        let exited = self.id_is_exited(self.id)?;
+
+        let waits = if exited {
+            Vec::new()
+        } else {
+            DapState::mpi_waits(&self.state)?
+        };
+
+        let mut ret = HashMap::new();
+        ret.insert(
+            self.id,
+            MpiRankState {
+                rank: ProcessInfo::mpirank_from_env(),
+                exited,
+                waits,
+            },
+        );
+
+        Ok(ret)
+    }
+}
+
+impl LldbDap {
+    pub fn take_child(&mut self) -> Option<Child> {
+        self.child_proc.take()
+    }
+
+    /// Launch a command wrapped in `lldb-dap`
+    ///
+    /// As with [crate::gdbmi::GdbMi::run] the program is only started by `start`.
+    pub fn run(cmd: &[&str]) -> Result<LldbDap> {
+        let adapter = std::env::var(LLDB_DAP_ENV).unwrap_or(DEFAULT_LLDB_DAP.to_string());
+
+        log::debug!("{} {:?}", adapter, cmd);
+
+        let mut child = Command::new(&adapter)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            /* The program inherits the environment of the adapter */
+            .env_remove(auth::TOKEN_ENV)
+            .spawn()
+            .map_err(|e| anyhow!("Failed to run {} : {}", adapter, e))?;
+
+        let state = DapState::new(child.stdin.take(), child.stdout.take())?;
+
+        DapState::request(
+            &state,
+            "initialize",
+            json!({
+                "clientID": "snapped",
+                "adapterID": "lldb",
+                "linesStartAt1": true,
+                "columnsStartAt1": true,
+                "pathFormat": "path",
+            }),
+        )?;
+
+        Ok(LldbDap {
+            id: 0,
+            command: cmd.iter().map(|v| v.to_string()).collect(),
+            state,
+            child_proc: Some(child),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn message_framing() {
+        let mut out = Vec::new();
+        let first = json!({"seq": 1, "type": "request", "command": "threads"});
+        let second =
+            json!({"seq": 2, "type": "event", "event": "output", "body": {"output": "é\n"}});
+
+        DapState::write_message(&mut out, &first).unwrap();
+        DapState::write_message(&mut out, &second).unwrap();
+
+        let mut input = Cursor::new(out);
+        assert_eq!(DapState::read_message(&mut input).unwrap(), first);
+        assert_eq!(DapState::read_message(&mut input).unwrap(), second);
+        assert!(DapState::read_message(&mut input).is_err());
+
+        /* Other headers are skipped */
+        let body = r#"{"seq":3}"#;
+        let framed = format!(
+            "Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut input = Cursor::new(framed.into_bytes());
+        assert_eq!(
+            DapState::read_message(&mut input).unwrap(),
+            json!({"seq": 3})
+        );
+
+        let mut input = Cursor::new(b"\r\n{}".to_vec());
+        assert!(DapState::read_message(&mut input).is_err());
+
+        /* Truncated body */
+        let mut input = Cursor::new(b"Content-Length: 10\r\n\r\n{}".to_vec());
+        assert!(DapState::read_message(&mut input).is_err());
+    }
+
+    #[test]
+    fn responses() {
+        let ok = json!({"success": true, "command": "threads", "body": {"threads": []}});
+        assert_eq!(
+            DapState::response_body(&ok).unwrap(),
+            json!({"threads": []})
+        );
+
+        let err = json!({
+            "success": false,
+            "command": "evaluate",
+            "message": "failed",
+            "body": {"error": {"format": "use of undeclared identifier 'x'"}}
+        });
+        let msg = DapState::response_body(&err).unwrap_err().to_string();
+        assert!(msg.contains("evaluate") && msg.contains("undeclared identifier"));
+
+        let err = json!({"success": false, "command": "modules", "message": "unknown request"});
+        let msg = DapState::response_body(&err).unwrap_err().to_string();
+        assert!(msg.contains("unknown request"));
+    }
+
+    #[test]
+    fn frames() {
+        assert_eq!(
+            split_frame_name("libc.so.6`__poll + 12"),
+            (
+                Some("libc.so.6".to_string()),
+                "__poll".to_string(),
+                Some(12)
+            )
+        );
+        assert_eq!(split_frame_name("a + b"), (None, "a + b".to_string(), None));
+
+        let modules = HashMap::from([("7".to_string(), "/lib/libc.so.6".to_string())]);
+
+        let f = json!({
+            "id": 1,
+            "name": "libc.so.6`__poll + 12",
+            "instructionPointerReference": "0x7ffff7d1c",
+            "moduleId": "7",
+            "line": 0
+        });
+        let frame = debug_frame(0, &f, &modules);
+        assert_eq!(frame.func, "__poll");
+        assert_eq!(frame.from.as_deref(), Some("/lib/libc.so.6"));
+        assert_eq!(frame.offset, Some(12));
+        assert_eq!(frame.line, None);
+
+        let f = json!({
+            "id": 2,
+            "name": "main",
+            "instructionPointerReference": "0x401136",
+            "source": {"name": "main.c", "path": "/src/main.c"},
+            "line": 12
+        });
+        let frame = debug_frame(1, &f, &modules);
+        assert_eq!(frame.level, 1);
+        assert_eq!(frame.addr, "0x401136");
+        assert_eq!(frame.fullname.as_deref(), Some("/src/main.c"));
+        assert_eq!(frame.line, Some(12));
+        assert_eq!(frame.from, None);
+    }
+
+    #[test]
+    fn stop_reasons() {
+        let stop = stop_reason_from_dap(&json!({
+            "reason": "exception",
+            "description": "signal SIGSEGV: address not mapped to object (fault address: 0x10)",
+            "threadId": 42
+        }));
+        assert_eq!(stop.reason, "signal-received");
+        assert_eq!(stop.signal_name.as_deref(), Some("SIGSEGV"));
+        assert_eq!(stop.thread_id, Some(42));
+
+        let stop = stop_reason_from_dap(&json!({"reason": "pause", "threadId": 1}));
+        assert_eq!(stop.signal_name.as_deref(), Some("SIGINT"));
+
+        let stop = stop_reason_from_dap(&json!({"reason": "breakpoint"}));
+        assert_eq!(stop.reason, "breakpoint-hit");
+    }
+
+    #[test]
+    fn fault_descriptions() {
+        let cases = [
+            (
+                "SIGSEGV",
+                "signal SIGSEGV: address not mapped to object (fault address: 0x10)",
+                Some("0x10"),
+                Some(1),
+            ),
+            (
+                "SIGSEGV",
+                "signal SIGSEGV: invalid permissions for mapped object (fault address: 0x7f00)",
+                Some("0x7f00"),
+                Some(2),
+            ),
+            (
+                "SIGFPE",
+                "signal SIGFPE: integer divide by zero",
+                None,
+                Some(1),
+            ),
+            (
+                "SIGBUS",
+                "signal SIGBUS: illegal alignment (fault address: 0x3)",
+                Some("0x3"),
+                Some(1),
+            ),
+            ("SIGSEGV", "signal SIGSEGV", None, None),
+        ];
+
+        for (signal, description, addr, code) in cases {
+            let (fault_addr, fault_code) = fault_from_description(signal, description);
+            assert_eq!(fault_addr.as_deref(), addr, "{}", description);
+            assert_eq!(fault_code, code, "{}", description);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::fs;
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+
+use crate::topology::expand_hostlist;
+
+/// Regex whose first capture (or whole match) on a host name is its rack/switch group
+pub const RACK_PATTERN_ENV: &str = "GDBW_RACK_PATTERN";
+
+/// File of `<group> <hostlist>` lines giving the rack/switch group of hosts
+pub const LOCALITY_FILE_ENV: &str = "GDBW_LOCALITY_FILE";
+
+/// Short host name (without the domain)
+pub(crate) fn short_host(host: &str) -> &str {
+    host.split('.').next().unwrap_or(host)
+}
+
+/// Where a process runs as described by its locality descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locality {
+    pub host: String,
+    pub numa: u64,
+    /// Rack or switch the host belongs to (if known)
+    pub group: Option<String>,
+}
+
+impl Locality {
+    /// Parse a `<host>-<numa>-<pid>` descriptor
+    ///
+    /// Host names may contain dashes, the descriptor is split from the end.
+    pub fn parse(descriptor: &str, groups: &LocalityGroups) -> Result<Locality> {
+        let mut it = descriptor.rsplitn(3, '-');
+
+        let (_pid, numa, host) = match (it.next(), it.next(), it.next()) {
+            (Some(pid), Some(numa), Some(host)) => (pid, numa, host),
+            _ => return Err(anyhow!("Bad locality descriptor '{}'", descriptor)),
+        };
+
+        let host = short_host(host).to_string();
+
+        Ok(Locality {
+            numa: numa.parse()?,
+            group: groups.group_of(&host),
+            host,
+        })
+    }
+
+    /// How far two processes are: 0 same NUMA, 1 same host, 2 same group, 3 elsewhere
+    pub fn distance(&self, other: &Locality) -> u32 {
+        if self.host == other.host {
+            if self.numa == other.numa {
+                0
+            } else {
+                1
+            }
+        } else if self.group.is_some() && self.group == other.group {
+            2
+        } else {
+            3
+        }
+    }
+}
+
+/// Rack/switch groups of the hosts
+#[derive(Default)]
+pub struct LocalityGroups {
+    pattern: Option<Regex>,
+    hosts: HashMap<String, String>,
+}
+
+impl LocalityGroups {
+    /// Load the groups from [RACK_PATTERN_ENV] and [LOCALITY_FILE_ENV]
+    pub fn from_env() -> Result<LocalityGroups> {
+        let mut ret = LocalityGroups::default();
+
+        if let Ok(pattern) = std::env::var(RACK_PATTERN_ENV) {
+            ret.pattern = Some(
+                Regex::new(&pattern)
+                    .map_err(|e| anyhow!("Bad rack pattern '{}' : {}", pattern, e))?,
+            );
+        }
+
+        if let Ok(path) = std::env::var(LOCALITY_FILE_ENV) {
+            let data = fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Failed to read locality file {} : {}", path, e))?;
+            ret.load(&data)?;
+        }
+
+        Ok(ret)
+    }
+
+    /// Read `<group> <hostlist>` lines, `#` starts a comment
+    fn load(&mut self, data: &str) -> Result<()> {
+        for line in data.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let (group, hosts) = line
+                .split_once(char::is_whitespace)
+                .ok_or(anyhow!("Expected '<group> <hostlist>' got '{}'", line))?;
+
+            for h in expand_hostlist(hosts.trim())? {
+                self.hosts
+                    .insert(short_host(&h).to_string(), group.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Group of a host, the locality file takes precedence over the pattern
+    pub fn group_of(&self, host: &str) -> Option<String> {
+        if let Some(g) = self.hosts.get(host) {
+            return Some(g.clone());
+        }
+
+        let cap = self.pattern.as_ref()?.captures(host)?;
+
+        cap.get(1).or(cap.get(0)).map(|m| m.as_str().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::ProcessInfo;
+    use crate::topology::{Topology, TreeLayout, TreePlan};
+    use crate::transport::Endpoint;
+
+    fn endpoint(host: &str) -> Endpoint {
+        Endpoint {
+            host: host.to_string(),
+            tcp: format!("{}:7000", host),
+            unix: None,
+        }
+    }
+
+    fn process(host: &str, numa: u64, pid: u64) -> ProcessInfo {
+        ProcessInfo {
+            mpirank: None,
+            pid,
+            hostname: host.to_string(),
+            locality_descriptor: format!("{}-{}-{}", host, numa, pid),
+        }
+    }
+
+    /// Smallest `d` with `k^d >= n`
+    fn log_ceil(k: u64, n: u64) -> u32 {
+        let mut d = 0;
+        let mut reach = 1;
+        while reach < n {
+            reach *= k;
+            d += 1;
+        }
+        d
+    }
+
+    /// Deepest node and number of processes placed under a node of another host
+    fn walk(node: &TreeLayout, depth: u32, parent: Option<&str>, remote: &mut usize) -> u32 {
+        if let Some(parent) = parent {
+            if parent != node.host {
+                *remote += 1;
+            }
+        }
+
+        let parent = if depth == 0 {
+            None
+        } else {
+            Some(node.host.as_str())
+        };
+
+        node.children
+            .iter()
+            .map(|c| walk(c, depth + 1, parent, remote))
+            .max()
+            .unwrap_or(depth)
+    }
+
+    /// Pivot the processes `pids` on each of `hosts`, hosts interleaved
+    fn pivot_all(plan: &mut TreePlan, hosts: std::ops::Range<u64>, pids: std::ops::Range<u64>) {
+        for pid in pids {
+            for h in hosts.clone() {
+                let host = format!("node{}", h);
+                plan.place(&process(&host, pid % 2, pid), endpoint(&host))
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn parse_descriptor() {
+        let groups = LocalityGroups::default();
+
+        let l = Locality::parse("login-1.cluster-1-4242", &groups).unwrap();
+        assert_eq!(l.host, "login-1");
+        assert_eq!(l.numa, 1);
+
+        let a = Locality::parse("node10-0-123", &groups).unwrap();
+        let b = Locality::parse("node19-0-123", &groups).unwrap();
+        let c = Locality::parse("node10-1-999", &groups).unwrap();
+        assert_eq!(a.distance(&b), 3);
+        assert_eq!(a.distance(&c), 1);
+
+        assert!(Locality::parse("node10", &groups).is_err());
+    }
+
+    #[test]
+    fn rack_groups() {
+        let mut groups = LocalityGroups {
+            pattern: Some(Regex::new(r"r(\d+)n").unwrap()),
+            ..Default::default()
+        };
+        groups.load("special r12n[01-02] # moved\n\n").unwrap();
+
+        assert_eq!(groups.group_of("r12n01").as_deref(), Some("special"));
+        assert_eq!(groups.group_of("r12n03").as_deref(), Some("12"));
+        assert_eq!(groups.group_of("login"), None);
+    }
+
+    #[test]
+    fn kary_depth_stays_bounded() {
+        const K: u64 = 8;
+        const HOSTS: u64 = 64;
+        const PER_HOST: u64 = 64;
+
+        let mut plan = TreePlan::new(Topology::Kary(K), endpoint("root"));
+
+        pivot_all(&mut plan, 0..HOSTS, 0..PER_HOST);
+        /* Late joins, on known hosts then on new ones */
+        pivot_all(&mut plan, 0..HOSTS / 2, PER_HOST..PER_HOST + 8);
+        pivot_all(&mut plan, HOSTS..HOSTS + 16, 0..PER_HOST);
+
+        let hosts = HOSTS + 16;
+        assert_eq!(plan.len() as u64, hosts * PER_HOST + HOSTS / 2 * 8);
+
+        let mut remote = 0;
+        let depth = walk(&plan.layout(), 0, None, &mut remote);
+
+        /* A k-ary tree of hosts, each host being a k-ary tree of its processes */
+        let bound = log_ceil(K, hosts) + log_ceil(K, PER_HOST + 8) + 2;
+        assert!(depth <= bound, "depth {} above {}", depth, bound);
+
+        /* Only the first process of each host hangs off another host */
+        assert!(
+            remote as u64 <= hosts,
+            "{} processes under remote hosts",
+            remote
+        );
+    }
+
+    #[test]
+    fn host_depth_stays_bounded() {
+        const K: u64 = 4;
+        const HOSTS: u64 = 300;
+        const PER_HOST: u64 = 16;
+
+        let mut plan = TreePlan::new(Topology::Host(K), endpoint("root"));
+
+        pivot_all(&mut plan, 0..HOSTS, 0..PER_HOST);
+        /* Late joins on new hosts */
+        pivot_all(&mut plan, HOSTS..HOSTS + 40, 0..PER_HOST);
+
+        let mut remote = 0;
+        let depth = walk(&plan.layout(), 0, None, &mut remote);
+
+        /* Aggregators form a k-ary tree, the other processes are one level below */
+        let bound = log_ceil(K, (HOSTS + 40) * (K - 1) + 1) + 1;
+        assert!(depth <= bound, "depth {} above {}", depth, bound);
+        /* Aggregators below other aggregators, all the others are local */
+        assert_eq!(remote as u64, HOSTS + 40 - K);
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use gethostname::gethostname;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -7,41 +7,62 @@ use std::{
     hash::{DefaultHasher, Hash, Hasher},
 };
 
-use crate::tools::{dominating_numa_id, parse_gdb_equal_list};
+use crate::tools::{dominating_numa_id, gdb_toplevel_fields, parse_gdb_equal_list};
+use crate::waitgraph::WaitForGraph;
 
+/// Partition of the id space over a subtree
+///
+/// Each node hands out `arity` equal slices of its id range to its children
+/// so that the ids of a subtree can be told from its root id and span.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TreeIdFactory {
     root_id: u64,
     dynamic: u64,
     stride: u64,
     offset: u64,
+    arity: u64,
 }
 
-const TREE_ARITY: u64 = 24;
-
 impl TreeIdFactory {
-    pub fn default() -> TreeIdFactory {
+    /// Id space of the root node with `arity` children slots
+    pub fn new(arity: u64) -> TreeIdFactory {
         TreeIdFactory {
             root_id: 0,
-            dynamic: std::u64::MAX,
-            stride: (std::u64::MAX - 1) / TREE_ARITY,
+            dynamic: u64::MAX,
+            stride: (u64::MAX - 1) / arity,
             offset: 0,
+            arity,
         }
     }
 
-    pub fn inherit(&mut self) -> Result<TreeIdFactory> {
+    /// Id space of the next child, which has `arity` children slots itself
+    pub fn inherit(&mut self, arity: u64) -> Result<TreeIdFactory> {
+        if self.full() {
+            return Err(anyhow!("No children slot left under {}", self.root_id));
+        }
+
+        /* Wide levels (e.g. host aggregators) exhaust the id space after a few levels */
+        let dynamic = match self.dynamic.checked_sub(1) {
+            Some(d) if self.stride > 0 => d / self.arity,
+            _ => {
+                return Err(anyhow!(
+                    "Id space exhausted under {}, the tree is too deep for its arity",
+                    self.root_id
+                ))
+            }
+        };
+
         let root_id = self.root_id + 1 + self.stride * self.offset;
         self.offset += 1;
 
-        let dynamic = (self.dynamic - 1) / TREE_ARITY;
-
-        let stride = dynamic / TREE_ARITY;
+        let stride = dynamic / arity;
 
         Ok(TreeIdFactory {
             root_id,
             dynamic,
             stride,
             offset: 0,
+            arity,
         })
     }
 
@@ -49,8 +70,16 @@ impl TreeIdFactory {
         self.root_id
     }
 
+    /// Width of the id range handed out to the subtree rooted at `id`
+    ///
+    /// All the descendants of a node have their id in `[id, id + span)`,
+    /// the next sibling may start at `id + span`.
+    pub fn span(&self) -> u64 {
+        self.dynamic
+    }
+
     pub fn full(&self) -> bool {
-        self.offset == TREE_ARITY
+        self.offset == self.arity
     }
 }
 
@@ -71,13 +100,24 @@ impl ProcessInfo {
         Ok(format!("{}-{}-{}", host, numa, pid))
     }
 
+    /// Get the MPI rank of the current process from the launcher's environment
+    pub fn mpirank_from_env() -> Option<u32> {
+        [
+            "PMI_RANK",
+            "PMIX_RANK",
+            "OMPI_COMM_WORLD_RANK",
+            "SLURM_PROCID",
+        ]
+        .iter()
+        .find_map(|v| std::env::var(v).ok())
+        .and_then(|v| v.parse::<u32>().ok())
+    }
+
     pub fn default() -> Result<ProcessInfo> {
         let locality_descriptor = ProcessInfo::locality_descriptor()?;
         let hostname = String::from(gethostname().as_os_str().to_str().unwrap());
         let pid = std::process::id() as u64;
-        let mpirank = std::env::var("PMI_RANK")
-            .ok()
-            .and_then(|v| v.parse::<u32>().ok());
+        let mpirank = ProcessInfo::mpirank_from_env();
 
         Ok(ProcessInfo {
             mpirank,
@@ -88,11 +128,154 @@ impl ProcessInfo {
     }
 }
 
-#[derive(Hash, Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DisplayState {
     pub reason: String,
     pub signal_name: Option<String>,
     pub exit_code: Option<i32>,
+    /// Details of the fault (faulting thread only)
+    pub crash: Option<CrashInfo>,
+}
+
+/* Faulting threads are merged whatever their fault address or registers */
+impl Hash for DisplayState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.reason.hash(state);
+        self.signal_name.hash(state);
+        self.exit_code.hash(state);
+        self.crash.is_some().hash(state);
+    }
+}
+
+/// Signals for which the fault is analyzed
+pub const FAULT_SIGNALS: [&str; 3] = ["SIGSEGV", "SIGBUS", "SIGFPE"];
+
+/// Register closing the general purpose ones (x86 in GDB and LLDB, arm, aarch64, powerpc)
+const STATUS_REGISTERS: [&str; 5] = ["eflags", "rflags", "cpsr", "pstate", "msr"];
+
+/// At most this many registers are kept for a crash
+const MAX_CRASH_REGISTERS: usize = 34;
+
+/// A machine instruction as disassembled by GDB
+#[derive(Hash, Serialize, Deserialize, Debug, Clone)]
+pub struct Instruction {
+    pub address: String,
+    /// Symbol the instruction belongs to
+    pub func: Option<String>,
+    /// Offset of the instruction in `func`
+    pub offset: Option<u64>,
+    pub text: String,
+}
+
+impl Instruction {
+    /// Symbol and offset of the instruction (e.g. `main+0x14`)
+    pub fn location(&self) -> Option<String> {
+        match (&self.func, self.offset) {
+            (Some(func), Some(off)) => Some(format!("{}+{:#x}", func, off)),
+            (Some(func), None) => Some(func.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.location() {
+            Some(loc) => write!(f, "{} <{}>: {}", self.address, loc, self.text),
+            None => write!(f, "{}: {}", self.address, self.text),
+        }
+    }
+}
+
+/// What a thread stopped by [FAULT_SIGNALS] was doing
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrashInfo {
+    pub signal: String,
+    /// Address which caused the fault (`si_addr` of `$_siginfo`)
+    pub fault_addr: Option<String>,
+    /// `si_code` of `$_siginfo`
+    pub code: Option<i32>,
+    /// Instruction the thread stopped on
+    pub instruction: Option<Instruction>,
+    /// General purpose registers of the faulting frame
+    pub registers: Vec<(String, String)>,
+}
+
+impl CrashInfo {
+    /// Keep the general purpose registers of those listed by a debugger
+    ///
+    /// Both GDB and LLDB list them first, the status register closes them
+    /// on all supported architectures.
+    pub fn general_registers(
+        registers: impl IntoIterator<Item = (String, String)>,
+    ) -> Vec<(String, String)> {
+        let mut ret = Vec::new();
+
+        for (name, value) in registers.into_iter().take(MAX_CRASH_REGISTERS) {
+            let last = STATUS_REGISTERS.contains(&name.as_str());
+            ret.push((name, value));
+
+            if last {
+                break;
+            }
+        }
+
+        ret
+    }
+
+    /// Meaning of `si_code` for the signal
+    pub fn cause(&self) -> Option<&'static str> {
+        let code = self.code?;
+
+        /* Codes below one are set by the sender of the signal */
+        if code <= 0 {
+            return Some("sent by another process");
+        }
+
+        let causes: &[&str] = match self.signal.as_str() {
+            "SIGSEGV" => &[
+                "address not mapped (SEGV_MAPERR)",
+                "invalid permissions (SEGV_ACCERR)",
+            ],
+            "SIGBUS" => &[
+                "invalid address alignment (BUS_ADRALN)",
+                "nonexistent physical address (BUS_ADRERR)",
+                "object specific hardware error (BUS_OBJERR)",
+            ],
+            "SIGFPE" => &[
+                "integer divide by zero (FPE_INTDIV)",
+                "integer overflow (FPE_INTOVF)",
+                "floating-point divide by zero (FPE_FLTDIV)",
+                "floating-point overflow (FPE_FLTOVF)",
+                "floating-point underflow (FPE_FLTUND)",
+                "floating-point inexact result (FPE_FLTRES)",
+                "floating-point invalid operation (FPE_FLTINV)",
+                "subscript out of range (FPE_FLTSUB)",
+            ],
+            _ => &[],
+        };
+
+        causes.get(code as usize - 1).cloned()
+    }
+
+    /// One line description of the fault
+    pub fn summary(&self) -> String {
+        let mut ret = Vec::new();
+
+        if let Some(cause) = self.cause() {
+            ret.push(cause.to_string());
+        }
+
+        if let Some(addr) = &self.fault_addr {
+            ret.push(format!("at address {}", addr));
+        }
+
+        if let Some(inst) = &self.instruction {
+            ret.push(format!("on {}", inst));
+        }
+
+        ret.join(" ")
+    }
 }
 
 #[derive(Hash, Serialize, Deserialize, Debug, Clone)]
@@ -100,12 +283,51 @@ pub struct DisplayFrame {
     pub func: String,
     pub file: Option<String>,
     pub line: Option<u32>,
+    /// Offset of the PC in `func`, only kept without line info so that
+    /// distinct call sites of a stripped function are not merged
+    pub offset: Option<u64>,
+    /// Shared object of frames without debug info
+    pub from: Option<String>,
+    /// The function was inlined in its caller
+    pub inlined: bool,
+}
+
+impl DisplayFrame {
+    /// Function and offset of the frame (e.g. `memcpy+0x1a`)
+    pub fn location(&self) -> String {
+        match self.offset {
+            Some(off) => format!("{}+{:#x}", self.func, off),
+            None => self.func.to_string(),
+        }
+    }
+}
+
+/// Role of a thread inside its process
+///
+/// This is used to tell apart the main thread from anonymous workers
+/// (e.g. OpenMP pools) and named helper threads (progress threads, ...).
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Debug, Clone)]
+pub enum ThreadRole {
+    Main,
+    Worker,
+    Named(String),
+}
+
+impl std::fmt::Display for ThreadRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThreadRole::Main => write!(f, "main"),
+            ThreadRole::Worker => write!(f, "worker"),
+            ThreadRole::Named(n) => write!(f, "{}", n),
+        }
+    }
 }
 
 #[derive(Hash, Serialize, Deserialize, Debug, Clone)]
 pub enum BacktraceState {
     Frame(DisplayFrame),
     State(DisplayState),
+    Thread(ThreadRole),
 }
 
 impl From<&DebugFrame> for BacktraceState {
@@ -114,6 +336,9 @@ impl From<&DebugFrame> for BacktraceState {
             func: value.func.clone(),
             file: value.fullname.clone(),
             line: value.line.clone(),
+            offset: value.line.map_or(value.offset, |_| None),
+            from: value.from.clone(),
+            inlined: value.inlined,
         })
     }
 }
@@ -124,27 +349,61 @@ impl From<&StopReason> for BacktraceState {
             reason: value.reason.clone(),
             signal_name: value.signal_name.clone(),
             exit_code: value.exit_code.clone(),
+            crash: None,
         })
     }
 }
 
 impl BacktraceState {
+    /// Is this frame a thread parked on a pthread mutex
+    pub fn is_mutex_wait(&self) -> bool {
+        if let BacktraceState::Frame(f) = self {
+            return MUTEX_LOCK_FUNCTIONS.contains(&f.func.as_str());
+        }
+
+        false
+    }
+
+    /// Is this frame a blocking MPI point-to-point call
+    pub fn is_mpi_wait(&self) -> bool {
+        if let BacktraceState::Frame(f) = self {
+            return MPI_BLOCKING_FUNCTIONS.iter().any(|(n, _)| *n == f.func);
+        }
+
+        false
+    }
+
+    /// Is this the state of processes the tree could not reach
+    pub fn is_unreachable(&self) -> bool {
+        if let BacktraceState::State(s) = self {
+            return s.reason == UNREACHABLE_REASON;
+        }
+
+        false
+    }
+
     pub fn root() -> BacktraceState {
         BacktraceState::Frame(DisplayFrame {
             func: ".".to_string(),
             file: None,
             line: None,
+            offset: None,
+            from: None,
+            inlined: false,
         })
     }
 
-    fn print(&self) -> String {
+    pub fn print(&self) -> String {
         match &self {
-            BacktraceState::Frame(b) => format!(
-                "{} {}:{}",
-                b.func,
-                b.file.clone().unwrap_or("Unknown".to_string()),
-                b.line.unwrap_or(0),
-            ),
+            BacktraceState::Frame(b) => match (&b.file, &b.from) {
+                (None, Some(from)) => format!("{} in {}", b.location(), from),
+                _ => format!(
+                    "{} {}:{}",
+                    b.location(),
+                    b.file.clone().unwrap_or("Unknown".to_string()),
+                    b.line.unwrap_or(0),
+                ),
+            },
             BacktraceState::State(s) => {
                 format!(
                     "{} {}",
@@ -152,6 +411,7 @@ impl BacktraceState {
                     s.signal_name.clone().unwrap_or("".to_string()),
                 )
             }
+            BacktraceState::Thread(r) => format!("[{} thread]", r),
         }
     }
 
@@ -187,9 +447,18 @@ pub struct DebugFrame {
     /// The line in the given file
     pub line: Option<u32>,
 
-    /// Information about the origin of this debug frame (e.g. where it was called from)
+    /// Shared object of the frame when it has no debug information
     pub from: Option<String>,
 
+    /// Offset of the PC in `func` (resolved for frames without line info)
+    pub offset: Option<u64>,
+
+    /// Instructions around the PC (frames without line info, when requested)
+    pub disassembly: Option<Vec<Instruction>>,
+
+    /// The function was inlined in the function of the next frame
+    pub inlined: bool,
+
     /// The architecture or platform that this debug frame is relevant to
     pub arch: Option<String>,
 
@@ -210,6 +479,9 @@ impl DebugFrame {
             fullname: None,
             line: None,
             from: None,
+            offset: None,
+            disassembly: None,
+            inlined: false,
             arch: None,
             args: None,
             locals: None,
@@ -232,6 +504,9 @@ impl DebugFrame {
             fullname: None,
             line: None,
             from: None,
+            offset: None,
+            disassembly: None,
+            inlined: false,
             arch: None,
             args: None,
             locals: None,
@@ -272,6 +547,18 @@ impl DebugFrame {
         Ok(ret)
     }
 
+    /// Flag the inlined frames of a backtrace (innermost first)
+    ///
+    /// Guess for the debuggers which do not tell inlined frames apart (GDB
+    /// without Python, LLDB): they run at the same PC as the frame they were
+    /// inlined in while a caller is at a return address.
+    pub fn mark_inlined(bt: &mut [DebugFrame]) {
+        for i in 1..bt.len() {
+            let inlined = !bt[i].addr.is_empty() && bt[i].addr == bt[i - 1].addr;
+            bt[i - 1].inlined = inlined;
+        }
+    }
+
     /// Attaches additional arguments and local variables from a variable list.
     ///
     /// The `vars` parameter should be in the shape `(name, is_argument, value)`, where `is_argument`
@@ -332,12 +619,145 @@ impl DebugFrame {
     }
 }
 
+/// Thread metadata as reported by `-thread-info`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadInfo {
+    /// The GDB thread id
+    pub id: u32,
+
+    /// The target description of the thread (e.g. "Thread 0x7ffff7d89740 (LWP 1234)")
+    pub target_id: String,
+
+    /// The name of the thread (defaults to the program name on Linux)
+    pub name: Option<String>,
+
+    /// The core the thread was last seen running on
+    pub core: Option<u32>,
+}
+
+impl ThreadInfo {
+    /// Creates a new `ThreadInfo` from a GDB-MI `-thread-info` thread tuple.
+    pub fn new(desc: &str) -> Result<ThreadInfo> {
+        let entries = parse_gdb_equal_list(&gdb_toplevel_fields(desc));
+
+        let id = entries
+            .get("id")
+            .and_then(|v| v.parse::<u32>().ok())
+            .ok_or(anyhow!("Thread descriptor has no id"))?;
+
+        Ok(ThreadInfo {
+            id,
+            target_id: entries.get("target-id").cloned().unwrap_or_default(),
+            name: entries.get("name").cloned(),
+            core: entries.get("core").and_then(|v| v.parse::<u32>().ok()),
+        })
+    }
+
+    /// The kernel thread id (LWP) backing this thread if known
+    pub fn lwp(&self) -> Option<u64> {
+        let re = Regex::new("(?:LWP|process) ([0-9]+)").ok()?;
+        let cap = re.captures(&self.target_id)?;
+        cap.get(1)?.as_str().parse::<u64>().ok()
+    }
+}
+
+/// A process debugged by GDB (inferior), forked or exec'd processes add inferiors
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Inferior {
+    /// GDB thread group of the inferior (e.g. `i1`)
+    pub group: String,
+    /// Process id once started
+    pub pid: Option<u64>,
+    /// GDB ids of the threads of the inferior
+    pub threads: Vec<u32>,
+    /// Exit code once the process exited
+    pub exit_code: Option<i32>,
+    pub exited: bool,
+}
+
+impl Inferior {
+    /// Inferior number of a GDB thread group (e.g. `i2` is 2)
+    pub fn number(group: &str) -> Option<u32> {
+        group.strip_prefix('i')?.parse::<u32>().ok()
+    }
+
+    /// Is the process of the inferior running or stopped (not exited)
+    pub fn is_live(&self) -> bool {
+        self.pid.is_some() && !self.exited
+    }
+
+    /// Process GDB was started on, the others are forked from it
+    pub fn primary(inferiors: &HashMap<u32, Inferior>) -> Option<&Inferior> {
+        inferiors
+            .iter()
+            .min_by_key(|(num, _)| **num)
+            .map(|(_, inf)| inf)
+    }
+
+    /// Track the inferiors from a GDB-MI `=` notification
+    pub fn track(inferiors: &mut HashMap<u32, Inferior>, record: &str) {
+        let (kind, fields) = record.split_once(',').unwrap_or((record, ""));
+        let fields = parse_gdb_equal_list(fields);
+
+        let group = match kind {
+            "thread-created" | "thread-exited" => fields.get("group-id"),
+            _ => fields.get("id"),
+        };
+
+        let num = match group.and_then(|g| Inferior::number(g)) {
+            Some(n) => n,
+            None => return,
+        };
+
+        let inf = inferiors.entry(num).or_insert_with(|| Inferior {
+            group: format!("i{}", num),
+            ..Default::default()
+        });
+
+        match kind {
+            "thread-group-started" => {
+                inf.pid = fields.get("pid").and_then(|v| v.parse::<u64>().ok());
+                inf.exit_code = None;
+                inf.exited = false;
+            }
+            "thread-group-exited" => {
+                /* GDB prints it in octal */
+                inf.exit_code = fields
+                    .get("exit-code")
+                    .and_then(|v| i32::from_str_radix(v, 8).ok());
+                inf.exited = true;
+                inf.threads.clear();
+            }
+            "thread-group-removed" => {
+                inferiors.remove(&num);
+            }
+            "thread-created" => {
+                if let Some(id) = fields.get("id").and_then(|v| v.parse::<u32>().ok()) {
+                    inf.threads.push(id);
+                }
+            }
+            "thread-exited" => {
+                if let Some(id) = fields.get("id").and_then(|v| v.parse::<u32>().ok()) {
+                    inf.threads.retain(|t| *t != id);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 /// Represents the full state of a program, including the list of stack frames for each individual thread.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProgramSnapshot {
     /// A map where the keys are thread IDs and the values are lists of `DebugFrame`s representing that thread's call stack.
     pub state: HashMap<u32, Vec<DebugFrame>>,
     pub stop_state: Option<StopReason>,
+    /// Metadata for each thread in `state` (may be empty if not available)
+    pub threads: HashMap<u32, ThreadInfo>,
+    /// Fault analysis when the program stopped on one of [FAULT_SIGNALS]
+    pub crash: Option<CrashInfo>,
+    /// Processes debugged by this GDB by inferior number (more than one with forks)
+    pub inferiors: HashMap<u32, Inferior>,
 }
 
 impl ProgramSnapshot {
@@ -345,7 +765,76 @@ impl ProgramSnapshot {
         let mut state = HashMap::new();
         state.insert(0, vec![DebugFrame::exited()]);
 
-        ProgramSnapshot { state, stop_state }
+        ProgramSnapshot {
+            state,
+            stop_state,
+            threads: HashMap::new(),
+            crash: None,
+            inferiors: HashMap::new(),
+        }
+    }
+
+    /// Identifier of the main thread
+    ///
+    /// This is the thread whose LWP matches the process id, if none can be
+    /// matched the lowest thread id is used (GDB numbers the main thread first).
+    fn main_thread(&self) -> Option<u32> {
+        let lwps: HashMap<u64, u32> = self
+            .threads
+            .values()
+            .filter_map(|t| t.lwp().map(|l| (l, t.id)))
+            .collect();
+
+        if let Some(min_lwp) = lwps.keys().min() {
+            /* The main thread has LWP == PID which is the lowest barring PID wrap-around */
+            return lwps.get(min_lwp).cloned();
+        }
+
+        self.threads.keys().min().cloned()
+    }
+
+    /// Classify a thread as main, worker or named thread
+    ///
+    /// Threads carrying the same name as the main thread are considered anonymous
+    /// workers as Linux threads inherit the name of their parent by default.
+    pub fn thread_role(&self, id: u32) -> Option<ThreadRole> {
+        let th = self.threads.get(&id)?;
+
+        /* Each forked process has its own main thread */
+        let main = match self.inferior_of(id) {
+            Some(inf) => self
+                .threads
+                .values()
+                .find(|t| t.lwp().is_some() && t.lwp() == inf.pid)
+                .map(|t| t.id)
+                .or_else(|| self.main_thread())?,
+            None => self.main_thread()?,
+        };
+
+        if th.id == main {
+            return Some(ThreadRole::Main);
+        }
+
+        let main_name = self.threads.get(&main).and_then(|t| t.name.clone());
+
+        match (&th.name, main_name) {
+            (Some(name), Some(main_name)) if *name != main_name => {
+                Some(ThreadRole::Named(name.clone()))
+            }
+            (Some(name), None) => Some(ThreadRole::Named(name.clone())),
+            _ => Some(ThreadRole::Worker),
+        }
+    }
+
+    /// Inferior running a thread, when GDB debugs several processes
+    pub fn inferior_of(&self, thread: u32) -> Option<&Inferior> {
+        if self.inferiors.len() < 2 {
+            return None;
+        }
+
+        self.inferiors
+            .values()
+            .find(|inf| inf.threads.contains(&thread))
     }
 
     pub fn json(&self) -> Result<String> {
@@ -359,7 +848,7 @@ impl ProgramSnapshot {
         let mut components: HashMap<u64, (u64, Vec<BacktraceState>)> = HashMap::new();
 
         for snap in dist_state.values() {
-            for thsnap in snap.state.values() {
+            for (thid, thsnap) in snap.state.iter() {
                 let mut comp = if let Some(stop_reason) = &snap.stop_state {
                     //println!("{:?}", stop_reason);
 
@@ -367,7 +856,18 @@ impl ProgramSnapshot {
                     case of manual interrupt  */
                     match stop_reason.is_sigint() {
                         true => Vec::new(),
-                        false => vec![BacktraceState::from(stop_reason)],
+                        false => {
+                            let mut state = BacktraceState::from(stop_reason);
+
+                            /* Only the thread which took the fault carries its details */
+                            if let BacktraceState::State(ds) = &mut state {
+                                if stop_reason.thread_id.is_none_or(|t| t == *thid) {
+                                    ds.crash = snap.crash.clone();
+                                }
+                            }
+
+                            vec![state]
+                        }
                     }
                 } else {
                     Vec::new()
@@ -375,6 +875,11 @@ impl ProgramSnapshot {
 
                 comp.append(&mut DebugFrame::to_component(thsnap));
 
+                /* Outermost entry is the role so that the tree can be split by it */
+                if let Some(role) = snap.thread_role(*thid) {
+                    comp.push(BacktraceState::Thread(role));
+                }
+
                 let hash = DebugFrame::hash_component(&comp);
 
                 if let Some((cnt, _)) = components.get_mut(&hash) {
@@ -388,6 +893,18 @@ impl ProgramSnapshot {
         components
     }
 
+    /// Component standing for `count` processes which did not answer
+    pub fn unreachable_components(count: u64) -> HashMap<u64, (u64, Vec<BacktraceState>)> {
+        let mut ret = HashMap::new();
+
+        if count > 0 {
+            let comp = vec![BacktraceState::from(&StopReason::unreachable())];
+            ret.insert(DebugFrame::hash_component(&comp), (count, comp));
+        }
+
+        ret
+    }
+
     pub fn components_vec(
         components: &HashMap<u64, (u64, Vec<DisplayFrame>)>,
     ) -> Vec<(u64, Vec<DisplayFrame>)> {
@@ -415,6 +932,457 @@ impl ProgramSnapshot {
     }
 }
 
+/// Stacks of some processes sent while a snapshot is still being collected
+///
+/// Parts cover disjoint sets of processes, merging all the parts of a
+/// snapshot gives the same components as [ProgramSnapshot::components_merge].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SnapshotPart {
+    pub components: HashMap<u64, (u64, Vec<BacktraceState>)>,
+    /// Number of processes whose stacks are in `components`
+    pub reported: u64,
+}
+
+impl SnapshotPart {
+    /// Part standing for `count` processes which could not be reached
+    pub fn unreachable(count: u64) -> SnapshotPart {
+        SnapshotPart {
+            components: ProgramSnapshot::unreachable_components(count),
+            reported: count,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reported == 0 && self.components.is_empty()
+    }
+
+    pub fn merge(&mut self, other: SnapshotPart) {
+        let components = std::mem::take(&mut self.components);
+        self.components = ProgramSnapshot::components_merge(vec![other.components, components]);
+        self.reported += other.reported;
+    }
+
+    /// The `count` components with the most threads
+    pub fn dominant(&self, count: usize) -> Vec<&(u64, Vec<BacktraceState>)> {
+        let mut ret: Vec<&(u64, Vec<BacktraceState>)> = self.components.values().collect();
+        ret.sort_by_key(|c| std::cmp::Reverse(c.0));
+        ret.truncate(count);
+        ret
+    }
+}
+
+/// Shared libraries loaded by a set of processes and their builds
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LibraryReport {
+    /// Library file name to build-id (empty when unknown) to the processes loading it
+    pub libraries: HashMap<String, HashMap<String, Vec<u64>>>,
+}
+
+/// A library loaded with several builds across the processes
+pub struct LibraryMismatch<'a> {
+    pub name: &'a String,
+    /// Builds sorted from the most to the least common
+    pub builds: Vec<(&'a String, &'a Vec<u64>)>,
+}
+
+impl LibraryReport {
+    /// Record that process `id` loaded the library at `path`
+    ///
+    /// Libraries are told apart by file name so that a copy installed at
+    /// another path on some nodes is still compared with the others.
+    pub fn add(&mut self, path: &str, build_id: Option<String>, id: u64) {
+        let name = path.rsplit('/').next().unwrap_or(path).to_string();
+
+        self.libraries
+            .entry(name)
+            .or_default()
+            .entry(build_id.unwrap_or_default())
+            .or_default()
+            .push(id);
+    }
+
+    pub fn merge(&mut self, other: LibraryReport) {
+        for (name, builds) in other.libraries {
+            let known = self.libraries.entry(name).or_default();
+
+            for (build, ids) in builds {
+                known.entry(build).or_default().extend(ids);
+            }
+        }
+    }
+
+    /// Libraries whose build differs between processes
+    pub fn mismatches(&self) -> Vec<LibraryMismatch<'_>> {
+        let mut ret: Vec<LibraryMismatch> = self
+            .libraries
+            .iter()
+            .filter(|(_, builds)| builds.len() > 1)
+            .map(|(name, builds)| {
+                let mut builds: Vec<(&String, &Vec<u64>)> = builds.iter().collect();
+                builds.sort_by_key(|(_, ids)| std::cmp::Reverse(ids.len()));
+                LibraryMismatch { name, builds }
+            })
+            .collect();
+
+        ret.sort_by(|a, b| a.name.cmp(b.name));
+        ret
+    }
+}
+
+/// Functions in which a thread is parked waiting for a pthread mutex
+///
+/// The entries are ordered from the public entry point to the inner futex wait
+/// as the mutex is best evaluated in the outermost frame.
+pub const MUTEX_LOCK_FUNCTIONS: [&str; 6] = [
+    "pthread_mutex_lock",
+    "__pthread_mutex_lock",
+    "___pthread_mutex_lock",
+    "__GI___pthread_mutex_lock",
+    "pthread_mutex_timedlock",
+    "__lll_lock_wait",
+];
+
+/// A thread blocked on a pthread mutex
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockWait {
+    /// The GDB thread id of the blocked thread
+    pub thread: u32,
+
+    /// The address of the mutex the thread is waiting on
+    pub mutex: String,
+
+    /// The LWP of the thread owning the mutex (`__data.__owner`)
+    pub owner_lwp: Option<u64>,
+
+    /// The GDB thread id of the owner if it belongs to the process
+    pub owner: Option<u32>,
+}
+
+/// Mutex wait-for analysis of a single process
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockReport {
+    /// Threads which are blocked on a mutex
+    pub waits: Vec<LockWait>,
+
+    /// Cycles of threads in the wait-for graph (each is a deadlock)
+    pub cycles: Vec<Vec<u32>>,
+}
+
+impl LockReport {
+    pub fn new(waits: Vec<LockWait>) -> LockReport {
+        let mut graph = WaitForGraph::new();
+
+        for w in waits.iter() {
+            if let Some(owner) = w.owner {
+                graph.add_edge(w.thread, owner);
+            }
+        }
+
+        let cycles = graph.cycles();
+
+        LockReport { waits, cycles }
+    }
+
+    fn wait_of(&self, thread: u32) -> Option<&LockWait> {
+        self.waits.iter().find(|w| w.thread == thread)
+    }
+
+    /// Human readable description of each deadlock cycle
+    ///
+    /// Each line is in the form "thread 3 holds M1 waiting on M2 held by thread 5"
+    pub fn describe_cycles(&self) -> Vec<String> {
+        let mut ret = Vec::new();
+
+        for cycle in self.cycles.iter() {
+            for (i, th) in cycle.iter().enumerate() {
+                /* Previous thread in the cycle is waiting on what we hold */
+                let prev = cycle[(i + cycle.len() - 1) % cycle.len()];
+                let next = cycle[(i + 1) % cycle.len()];
+
+                let held = self
+                    .wait_of(prev)
+                    .map(|w| w.mutex.clone())
+                    .unwrap_or("?".to_string());
+                let waiting = self
+                    .wait_of(*th)
+                    .map(|w| w.mutex.clone())
+                    .unwrap_or("?".to_string());
+
+                ret.push(format!(
+                    "thread {} holds {} waiting on {} held by thread {}",
+                    th, held, waiting, next
+                ));
+            }
+        }
+
+        ret
+    }
+}
+
+/// Where the peers of a blocking MPI call are found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MpiPeer {
+    /// Arguments holding the peer ranks (both sides of `MPI_Sendrecv`)
+    Args(&'static [&'static str]),
+    /// The pending `request` waited on, see [MPI_REQUEST_PEER]
+    Request,
+}
+
+/// Blocking MPI point-to-point calls and where to find their peers
+pub const MPI_BLOCKING_FUNCTIONS: [(&str, MpiPeer); 12] = [
+    ("MPI_Recv", MpiPeer::Args(&["source"])),
+    ("PMPI_Recv", MpiPeer::Args(&["source"])),
+    ("MPI_Send", MpiPeer::Args(&["dest"])),
+    ("PMPI_Send", MpiPeer::Args(&["dest"])),
+    ("MPI_Ssend", MpiPeer::Args(&["dest"])),
+    ("PMPI_Ssend", MpiPeer::Args(&["dest"])),
+    ("MPI_Sendrecv", MpiPeer::Args(&["source", "dest"])),
+    ("PMPI_Sendrecv", MpiPeer::Args(&["source", "dest"])),
+    ("MPI_Probe", MpiPeer::Args(&["source"])),
+    ("PMPI_Probe", MpiPeer::Args(&["source"])),
+    ("MPI_Wait", MpiPeer::Request),
+    ("PMPI_Wait", MpiPeer::Request),
+];
+
+/// Peer and tag of the request of `MPI_Wait`, the first which evaluates is used
+///
+/// Point-to-point requests of Open MPI carry their peer and tag. The status
+/// is not read: MPI only fills it once the wait returns. Requests of other
+/// implementations leave the peer undetermined.
+pub const MPI_REQUEST_PEER: [(&str, &str); 1] = [(
+    "((mca_pml_base_request_t *)*request)->req_peer",
+    "((mca_pml_base_request_t *)*request)->req_tag",
+)];
+
+/// Handle of `MPI_COMM_WORLD`, the first which evaluates is used
+///
+/// Open MPI points to a global object, MPICH and its derivatives use a
+/// constant handle.
+pub const MPI_COMM_WORLD_HANDLES: [&str; 2] = [
+    "(unsigned long)&ompi_mpi_comm_world",
+    "(unsigned long)0x44000000",
+];
+
+/// A thread blocked in a point-to-point MPI call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MpiWait {
+    /// The GDB thread id of the blocked thread
+    pub thread: u32,
+
+    /// The blocking MPI call (e.g. "MPI_Recv")
+    pub call: String,
+
+    /// The peer ranks in `comm` (negative values are wildcards or `MPI_PROC_NULL`)
+    pub peers: Vec<i32>,
+
+    /// The message tag
+    pub tag: Option<i32>,
+
+    /// The raw value of the communicator handle
+    pub comm: Option<String>,
+
+    /// Is `comm` `MPI_COMM_WORLD` (None when it could not be read)
+    pub world: Option<bool>,
+}
+
+impl MpiWait {
+    /// Outermost blocking MPI call of a backtrace (the user facing one)
+    pub fn find_call(bt: &[DebugFrame]) -> Option<(&DebugFrame, MpiPeer)> {
+        bt.iter().rev().find_map(|f| {
+            MPI_BLOCKING_FUNCTIONS
+                .iter()
+                .find(|(n, _)| *n == f.func)
+                .map(|(_, peer)| (f, *peer))
+        })
+    }
+
+    /// Read the call of `frame` with `eval` evaluating expressions in this frame
+    ///
+    /// Arguments are evaluated by their name in the MPI standard which requires
+    /// the MPI library (or its PMPI wrapper) to be built with debug information.
+    pub fn new(
+        thread: u32,
+        frame: &DebugFrame,
+        peer: MpiPeer,
+        eval: impl Fn(&str) -> Option<String>,
+    ) -> MpiWait {
+        let int =
+            |expr: &str| eval(&format!("(int){}", expr)).and_then(|v| v.trim().parse::<i32>().ok());
+
+        let (peers, tag) = match peer {
+            MpiPeer::Args(args) => (args.iter().filter_map(|a| int(a)).collect(), int("tag")),
+            MpiPeer::Request => MPI_REQUEST_PEER
+                .iter()
+                .find_map(|(peer, tag)| int(peer).map(|p| (vec![p], int(tag))))
+                .unwrap_or_default(),
+        };
+
+        let comm = eval("(unsigned long)comm");
+        let world = comm.as_ref().and_then(|comm| {
+            MPI_COMM_WORLD_HANDLES
+                .iter()
+                .find_map(|h| eval(h))
+                .map(|world| world.trim() == comm.trim())
+        });
+
+        MpiWait {
+            thread,
+            call: frame.func.clone(),
+            peers,
+            tag,
+            comm,
+            world,
+        }
+    }
+}
+
+/// MPI state of a process as seen from its blocking frames
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MpiRankState {
+    /// The MPI rank if it could be retrieved from the launcher
+    pub rank: Option<u32>,
+
+    /// Did the process already exit
+    pub exited: bool,
+
+    /// Blocking calls the threads of this process are in
+    pub waits: Vec<MpiWait>,
+}
+
+/// Cross-rank wait-for analysis of MPI point-to-point calls
+///
+/// Peers are interpreted as ranks of `MPI_COMM_WORLD` (also when the
+/// communicator could not be read), waits in other communicators are
+/// reported with their handle and left out of the graph as their peers are
+/// ranks of the communicator.
+#[derive(Debug)]
+pub struct MpiReport {
+    /// Cycles of ranks waiting on each other
+    pub cycles: Vec<Vec<u32>>,
+
+    /// (waiting rank, exited peer, call)
+    pub waits_on_exited: Vec<(u32, u32, String)>,
+
+    /// (waiting rank, unknown peer, call)
+    pub waits_on_unknown: Vec<(u32, u32, String)>,
+
+    /// (waiting rank, call, communicator handle) of waits outside of `MPI_COMM_WORLD`
+    pub waits_in_comm: Vec<(u32, String, String)>,
+
+    /// Number of ranks blocked in a MPI call
+    pub blocked: usize,
+
+    /// Number of ranks blocked in a MPI call whose peer is not known
+    pub undetermined: usize,
+}
+
+impl MpiReport {
+    pub fn new(states: &HashMap<u64, MpiRankState>) -> MpiReport {
+        let ranks: HashMap<u32, &MpiRankState> = states
+            .values()
+            .filter_map(|s| s.rank.map(|r| (r, s)))
+            .collect();
+
+        let mut graph = WaitForGraph::new();
+        let mut waits_on_exited = Vec::new();
+        let mut waits_on_unknown = Vec::new();
+        let mut waits_in_comm = Vec::new();
+        let mut blocked = 0;
+        let mut undetermined = 0;
+
+        for (rank, st) in ranks.iter() {
+            if st.waits.is_empty() {
+                continue;
+            }
+
+            blocked += 1;
+
+            let (world, other): (Vec<&MpiWait>, Vec<&MpiWait>) =
+                st.waits.iter().partition(|w| w.world != Some(false));
+
+            for w in other {
+                let comm = w.comm.clone().unwrap_or_default();
+                waits_in_comm.push((*rank, w.call.clone(), comm));
+            }
+
+            let peers: Vec<(u32, &String)> = world
+                .iter()
+                .flat_map(|w| w.peers.iter().map(move |p| (p, &w.call)))
+                .filter(|(p, _)| **p >= 0)
+                .map(|(p, call)| (*p as u32, call))
+                .collect();
+
+            if peers.is_empty() {
+                undetermined += 1;
+            }
+
+            for (peer, call) in peers {
+                match ranks.get(&peer) {
+                    Some(p) if p.exited => waits_on_exited.push((*rank, peer, call.clone())),
+                    Some(_) => graph.add_edge(*rank, peer),
+                    None => waits_on_unknown.push((*rank, peer, call.clone())),
+                }
+            }
+        }
+
+        waits_on_exited.sort();
+        waits_on_unknown.sort();
+        waits_in_comm.sort();
+
+        MpiReport {
+            cycles: graph.cycles(),
+            waits_on_exited,
+            waits_on_unknown,
+            waits_in_comm,
+            blocked,
+            undetermined,
+        }
+    }
+
+    /// Human readable description of the ranks which can never progress
+    pub fn describe(&self) -> Vec<String> {
+        let mut ret = Vec::new();
+
+        for cycle in self.cycles.iter() {
+            let ranks: Vec<String> = cycle
+                .iter()
+                .chain(cycle.first())
+                .map(|r| r.to_string())
+                .collect();
+            ret.push(format!("ranks wait on each other: {}", ranks.join(" -> ")));
+        }
+
+        for (rank, peer, call) in self.waits_on_exited.iter() {
+            ret.push(format!(
+                "rank {} waits in {} on rank {} which already exited",
+                rank, call, peer
+            ));
+        }
+
+        ret
+    }
+
+    /// Waits which could not be checked as their peer is not in the snapshot
+    /// or not given as a rank of `MPI_COMM_WORLD`
+    pub fn describe_unknown(&self) -> Vec<String> {
+        let unknown = self.waits_on_unknown.iter().map(|(rank, peer, call)| {
+            format!(
+                "rank {} waits in {} on rank {} which is not part of the snapshot",
+                rank, call, peer
+            )
+        });
+
+        let in_comm = self.waits_in_comm.iter().map(|(rank, call, comm)| {
+            format!(
+                "rank {} waits in {} on communicator {} which is not MPI_COMM_WORLD",
+                rank, call, comm
+            )
+        });
+
+        unknown.chain(in_comm).collect()
+    }
+}
+
 /// This describes the stop state of a program
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StopReason {
@@ -465,7 +1433,42 @@ pub struct StopReason {
     pub exit_code: Option<i32>,
 }
 
+/// Stop reason given to processes whose debugger could not be reached
+pub const UNREACHABLE_REASON: &str = "unreachable";
+
 impl StopReason {
+    /// Stop reason standing for a process whose debugger did not answer
+    pub fn unreachable() -> StopReason {
+        StopReason {
+            reason: UNREACHABLE_REASON.to_string(),
+            disp: None,
+            breakpoint_num: None,
+            addr: None,
+            function: None,
+            meaning: None,
+            signal_name: None,
+            file: None,
+            fullname: None,
+            line: None,
+            arch: None,
+            thread_id: None,
+            stopped_threads: None,
+            core: None,
+            exit_code: None,
+        }
+    }
+
+    pub fn is_unreachable(&self) -> bool {
+        self.reason == UNREACHABLE_REASON
+    }
+
+    /// Did the program stop on one of [FAULT_SIGNALS]
+    pub fn is_fault(&self) -> bool {
+        self.signal_name
+            .as_ref()
+            .is_some_and(|s| FAULT_SIGNALS.contains(&s.as_str()))
+    }
+
     pub fn is_sigint(&self) -> bool {
         if let Some(sig) = &self.signal_name {
             if sig == "SIGINT" {
@@ -496,8 +1499,8 @@ impl StopReason {
             fullname: map.get("fullname").cloned(),
             line: map.get("line").and_then(|s| s.parse::<u32>().ok()),
             arch: map.get("arch").cloned(),
-            thread_id: map.get("thread_id").and_then(|s| s.parse::<u32>().ok()),
-            stopped_threads: map.get("stopped_threads").cloned(),
+            thread_id: map.get("thread-id").and_then(|s| s.parse::<u32>().ok()),
+            stopped_threads: map.get("stopped-threads").cloned(),
             core: map.get("core").and_then(|s| s.parse::<u32>().ok()),
             exit_code: map.get("exit-code").and_then(|s| s.parse::<i32>().ok()),
         };
@@ -532,16 +1535,516 @@ pub struct Symbol {
     pub description: Option<String>,
 }
 
+/// Symbols of a binary and the processes running it
 #[derive(Serialize, Deserialize, Debug)]
-pub struct SymbolTable {
+pub struct BinarySymbols {
+    /// Path of the binary as seen by the first process reporting it
+    pub path: String,
+    /// GNU build-id of the binary (if it was linked with one)
+    pub build_id: Option<String>,
+    /// Debugger ids of the processes running this binary
+    pub ids: Vec<u64>,
     /// Mapping of file names to a list of symbols in that file
     pub symbols_per_file: HashMap<String, Vec<Symbol>>,
 }
 
+impl BinarySymbols {
+    /// Binaries are told apart by build-id, by path when they have none
+    pub fn key(&self) -> String {
+        match &self.build_id {
+            Some(id) => id.clone(),
+            None => format!("path:{}", self.path),
+        }
+    }
+
+    pub fn symbol_count(&self) -> usize {
+        self.symbols_per_file.values().map(|v| v.len()).sum()
+    }
+}
+
+/// Symbols of the binaries run by a set of processes
+///
+/// Each binary appears once whatever the number of processes running it so
+/// that aggregating the tables over the tree does not scale with the job.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SymbolTable {
+    /// Binaries keyed by [BinarySymbols::key]
+    pub binaries: HashMap<String, BinarySymbols>,
+}
+
 impl SymbolTable {
     pub fn default() -> SymbolTable {
         SymbolTable {
-            symbols_per_file: HashMap::new(),
+            binaries: HashMap::new(),
+        }
+    }
+
+    pub fn from_binary(binary: BinarySymbols) -> SymbolTable {
+        let mut ret = SymbolTable::default();
+        ret.binaries.insert(binary.key(), binary);
+        ret
+    }
+
+    /// Add the binaries of `other`, the symbols of known binaries are dropped
+    pub fn merge(&mut self, other: SymbolTable) {
+        for (key, bin) in other.binaries {
+            match self.binaries.get_mut(&key) {
+                Some(known) => known.ids.extend(bin.ids),
+                None => {
+                    self.binaries.insert(key, bin);
+                }
+            }
+        }
+    }
+
+    /// Symbols of all the binaries
+    pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+        self.binaries
+            .values()
+            .flat_map(|b| b.symbols_per_file.values().flatten())
+    }
+
+    /// Paths run with several build-ids
+    pub fn mismatches(&self) -> Vec<(&String, Vec<&BinarySymbols>)> {
+        let mut by_path: HashMap<&String, Vec<&BinarySymbols>> = HashMap::new();
+
+        for bin in self.binaries.values() {
+            by_path.entry(&bin.path).or_default().push(bin);
+        }
+
+        let mut ret: Vec<(&String, Vec<&BinarySymbols>)> =
+            by_path.into_iter().filter(|(_, v)| v.len() > 1).collect();
+        ret.sort_by(|a, b| a.0.cmp(b.0));
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a tree of `depth` levels below `ids` and return all the ids given out
+    fn subtree_ids(ids: &mut TreeIdFactory, depth: u32, arities: &[u64]) -> Vec<u64> {
+        let mut ret = vec![ids.id()];
+
+        if depth == 0 {
+            return ret;
+        }
+
+        let arity = arities[depth as usize % arities.len()];
+
+        while !ids.full() {
+            let mut child = ids.inherit(arity).unwrap();
+            let child_ids = subtree_ids(&mut child, depth - 1, arities);
+
+            for id in child_ids.iter() {
+                assert!(
+                    child.id() <= *id && *id - child.id() < child.span(),
+                    "{} is out of [{}, {} + {})",
+                    id,
+                    child.id(),
+                    child.id(),
+                    child.span()
+                );
+            }
+
+            ret.extend(child_ids);
+        }
+
+        ret
+    }
+
+    #[test]
+    fn tree_ids_stay_in_span() {
+        for arities in [&[2, 3][..], &[3][..], &[7, 1, 4][..], &[5, 2][..]] {
+            let mut root = TreeIdFactory::new(arities[0]);
+            let mut ids = subtree_ids(&mut root, 5, arities);
+
+            let count = ids.len();
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), count, "duplicate ids with arities {:?}", arities);
+        }
+    }
+
+    #[test]
+    fn tree_ids_exhausted() {
+        /* Chain of host aggregators, each with k + HOST_SLOTS slots */
+        let mut node = TreeIdFactory::new(24 + 1024);
+        let mut depth = 0;
+
+        let err = loop {
+            match node.inherit(24 + 1024) {
+                Ok(child) => node = child,
+                Err(e) => break e,
+            }
+            depth += 1;
+            assert!(depth < 64, "id space never exhausted");
+        };
+
+        assert!(depth >= 5, "exhausted after {} levels", depth);
+        assert!(err.to_string().contains("exhausted"));
+    }
+
+    fn frame(func: &str) -> DebugFrame {
+        DebugFrame {
+            func: func.to_string(),
+            ..DebugFrame::exited()
+        }
+    }
+
+    fn rank(rank: u32, exited: bool, waits: Vec<MpiWait>) -> MpiRankState {
+        MpiRankState {
+            rank: Some(rank),
+            exited,
+            waits,
         }
     }
+
+    fn wait(call: &str, values: &[(&str, &str)]) -> MpiWait {
+        let bt = vec![frame("nanosleep"), frame(call), frame("main")];
+        let (f, peer) = MpiWait::find_call(&bt).unwrap();
+
+        MpiWait::new(0, f, peer, |expr| {
+            values
+                .iter()
+                .find(|(e, _)| *e == expr)
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn mpi_wait_peers() {
+        let recv = wait("MPI_Recv", &[("(int)source", "2"), ("(int)tag", "42")]);
+        assert_eq!(recv.peers, vec![2]);
+        assert_eq!(recv.tag, Some(42));
+
+        let sendrecv = wait("PMPI_Sendrecv", &[("(int)source", "1"), ("(int)dest", "3")]);
+        assert_eq!(sendrecv.peers, vec![1, 3]);
+
+        let openmpi = wait(
+            "MPI_Wait",
+            &[
+                ("(int)((mca_pml_base_request_t *)*request)->req_peer", "5"),
+                ("(int)((mca_pml_base_request_t *)*request)->req_tag", "7"),
+                ("(int)status->MPI_SOURCE", "9"),
+            ],
+        );
+        assert_eq!(openmpi.peers, vec![5]);
+        assert_eq!(openmpi.tag, Some(7));
+
+        /* The status is only filled once the wait returns */
+        let status = wait("MPI_Wait", &[("(int)status->MPI_SOURCE", "9")]);
+        assert!(status.peers.is_empty());
+
+        let unknown = wait("MPI_Wait", &[]);
+        assert!(unknown.peers.is_empty());
+    }
+
+    #[test]
+    fn mpi_report_edges() {
+        let mut states = HashMap::new();
+        /* 0 waits on 1 through a request, 1 on 2 and 0 in a sendrecv, 2 on 0 */
+        states.insert(
+            0,
+            rank(
+                0,
+                false,
+                vec![wait(
+                    "MPI_Wait",
+                    &[("(int)((mca_pml_base_request_t *)*request)->req_peer", "1")],
+                )],
+            ),
+        );
+        states.insert(
+            1,
+            rank(
+                1,
+                false,
+                vec![wait(
+                    "MPI_Sendrecv",
+                    &[("(int)source", "2"), ("(int)dest", "7")],
+                )],
+            ),
+        );
+        states.insert(
+            2,
+            rank(2, false, vec![wait("MPI_Send", &[("(int)dest", "3")])]),
+        );
+        states.insert(3, rank(3, true, vec![]));
+        states.insert(4, rank(4, false, vec![wait("MPI_Wait", &[])]));
+
+        let report = MpiReport::new(&states);
+
+        assert!(report.cycles.is_empty());
+        assert_eq!(report.waits_on_exited, vec![(2, 3, "MPI_Send".to_string())]);
+        assert_eq!(
+            report.waits_on_unknown,
+            vec![(1, 7, "MPI_Sendrecv".to_string())]
+        );
+        assert_eq!(report.blocked, 4);
+        assert_eq!(report.undetermined, 1);
+        assert_eq!(report.describe().len(), 1);
+        assert_eq!(report.describe_unknown().len(), 1);
+
+        /* Closing the ring through the request of rank 2 */
+        states.insert(
+            2,
+            rank(
+                2,
+                false,
+                vec![wait(
+                    "MPI_Wait",
+                    &[("(int)((mca_pml_base_request_t *)*request)->req_peer", "0")],
+                )],
+            ),
+        );
+
+        let report = MpiReport::new(&states);
+        assert_eq!(report.cycles.len(), 1);
+        assert_eq!(report.cycles[0].len(), 3);
+    }
+
+    #[test]
+    fn mpi_report_communicators() {
+        const WORLD: (&str, &str) = ("(unsigned long)0x44000000", "1140850688");
+        let recv = |source: &'static str, comm: &'static str| {
+            wait(
+                "MPI_Recv",
+                &[
+                    ("(int)source", source),
+                    ("(unsigned long)comm", comm),
+                    WORLD,
+                ],
+            )
+        };
+
+        let world = recv("1", "1140850688");
+        assert_eq!(world.world, Some(true));
+        let split = recv("0", "2214592514");
+        assert_eq!(split.world, Some(false));
+
+        /* Rank 1 of the split communicator is not rank 1 of MPI_COMM_WORLD */
+        let mut states = HashMap::new();
+        states.insert(0, rank(0, false, vec![world]));
+        states.insert(1, rank(1, false, vec![split]));
+
+        let report = MpiReport::new(&states);
+        assert!(report.cycles.is_empty());
+        assert_eq!(
+            report.waits_in_comm,
+            vec![(1, "MPI_Recv".to_string(), "2214592514".to_string())]
+        );
+        assert_eq!(report.blocked, 2);
+        assert_eq!(report.undetermined, 1);
+        assert!(report.describe_unknown()[0].contains("not MPI_COMM_WORLD"));
+
+        /* Both in MPI_COMM_WORLD */
+        states.insert(1, rank(1, false, vec![recv("0", "1140850688")]));
+        let report = MpiReport::new(&states);
+        assert_eq!(report.cycles.len(), 1);
+        assert!(report.waits_in_comm.is_empty());
+    }
+
+    /// `-thread-info` of an MPI rank, the main thread is not listed first
+    const THREAD_INFO: &str = r#"threads=[{id="3",target-id="Thread 0x7ffff6dff640 (LWP 4250)",name="solver",frame={level="0",addr="0x00007ffff7e9b117",func="__futex_abstimed_wait_common",args=[],from="/lib/x86_64-linux-gnu/libc.so.6",arch="i386:x86-64"},state="stopped",core="7"},{id="2",target-id="Thread 0x7ffff75ff640 (LWP 4244)",name="async_progress",frame={level="0",addr="0x00007ffff7f1a0af",func="epoll_wait",args=[],from="/lib/x86_64-linux-gnu/libc.so.6",arch="i386:x86-64"},state="stopped",core="1"},{id="1",target-id="Thread 0x7ffff7d8a740 (LWP 4242)",name="solver",frame={level="0",addr="0x0000555555555189",func="main",args=[],file="solver.c",fullname="/src/solver.c",line="42",arch="i386:x86-64"},state="stopped",core="0"}],current-thread-id="1""#;
+
+    fn snapshot_of(threads: HashMap<u32, ThreadInfo>) -> ProgramSnapshot {
+        ProgramSnapshot {
+            threads,
+            ..ProgramSnapshot::exited(None)
+        }
+    }
+
+    fn thread_info(info: &str) -> HashMap<u32, ThreadInfo> {
+        crate::tools::split_gdb_tuples(info)
+            .iter()
+            .flat_map(|v| ThreadInfo::new(v))
+            .map(|v| (v.id, v))
+            .collect()
+    }
+
+    #[test]
+    fn thread_info_parsing() {
+        let threads = thread_info(THREAD_INFO);
+        assert_eq!(threads.len(), 3);
+
+        let main = &threads[&1];
+        assert_eq!(main.target_id, "Thread 0x7ffff7d8a740 (LWP 4242)");
+        assert_eq!(main.name.as_deref(), Some("solver"));
+        assert_eq!(main.core, Some(0));
+        assert_eq!(main.lwp(), Some(4242));
+
+        /* Processes without threads are named after their pid */
+        let single = ThreadInfo::new(r#"id="1",target-id="process 977",state="stopped""#).unwrap();
+        assert_eq!(single.lwp(), Some(977));
+        assert_eq!(single.name, None);
+
+        assert!(ThreadInfo::new(r#"target-id="process 977""#).is_err());
+    }
+
+    #[test]
+    fn thread_roles() {
+        let snap = snapshot_of(thread_info(THREAD_INFO));
+
+        assert_eq!(snap.main_thread(), Some(1));
+        assert_eq!(snap.thread_role(1), Some(ThreadRole::Main));
+        /* Same name as the main thread, inherited from it */
+        assert_eq!(snap.thread_role(3), Some(ThreadRole::Worker));
+        assert_eq!(
+            snap.thread_role(2),
+            Some(ThreadRole::Named("async_progress".to_string()))
+        );
+        assert_eq!(snap.thread_role(9), None);
+
+        /* The lowest LWP wins over the GDB numbering */
+        let mut threads = thread_info(THREAD_INFO);
+        threads.get_mut(&3).unwrap().target_id = "Thread 0x7ffff6dff640 (LWP 4100)".to_string();
+        assert_eq!(snapshot_of(threads).main_thread(), Some(3));
+
+        /* Without LWPs the first GDB thread is the main one */
+        let mut threads = thread_info(THREAD_INFO);
+        threads.values_mut().for_each(|t| t.target_id.clear());
+        assert_eq!(snapshot_of(threads).main_thread(), Some(1));
+    }
+
+    fn lock_wait(thread: u32, mutex: &str, owner: Option<u32>) -> LockWait {
+        LockWait {
+            thread,
+            mutex: mutex.to_string(),
+            owner_lwp: owner.map(|o| 4240 + o as u64),
+            owner,
+        }
+    }
+
+    #[test]
+    fn lock_report() {
+        /* 2 and 3 lock M1 and M2 in opposite orders, 4 waits on 3, 5 on a foreign owner */
+        let report = LockReport::new(vec![
+            lock_wait(3, "M1", Some(2)),
+            lock_wait(2, "M2", Some(3)),
+            lock_wait(4, "M2", Some(3)),
+            lock_wait(5, "M3", None),
+        ]);
+
+        assert_eq!(report.cycles, vec![vec![2, 3]]);
+        assert_eq!(
+            report.describe_cycles(),
+            vec![
+                "thread 2 holds M1 waiting on M2 held by thread 3",
+                "thread 3 holds M2 waiting on M1 held by thread 2",
+            ]
+        );
+
+        let report = LockReport::new(vec![lock_wait(6, "M4", Some(6))]);
+        assert_eq!(
+            report.describe_cycles(),
+            vec!["thread 6 holds M4 waiting on M4 held by thread 6"]
+        );
+
+        let report = LockReport::new(vec![lock_wait(4, "M2", Some(3))]);
+        assert!(report.cycles.is_empty());
+        assert!(report.describe_cycles().is_empty());
+    }
+
+    #[test]
+    fn stripped_frame_offsets() {
+        let stripped = |offset| DebugFrame {
+            offset: Some(offset),
+            from: Some("/opt/vendor/lib/libsolver.so".to_string()),
+            ..frame("progress")
+        };
+
+        /* Two call sites of a function without line info stay apart */
+        let a = BacktraceState::from(&stripped(0x1a));
+        let b = BacktraceState::from(&stripped(0x40));
+        assert_ne!(a.get_hash(), b.get_hash());
+        assert_eq!(
+            a.get_hash(),
+            BacktraceState::from(&stripped(0x1a)).get_hash()
+        );
+
+        /* With line info the offset is dropped, the lines tell them apart */
+        let lined = |offset| DebugFrame {
+            line: Some(12),
+            ..stripped(offset)
+        };
+        assert_eq!(
+            BacktraceState::from(&lined(0x1a)).get_hash(),
+            BacktraceState::from(&lined(0x40)).get_hash()
+        );
+
+        match a {
+            BacktraceState::Frame(f) => assert_eq!(f.location(), "progress+0x1a"),
+            _ => panic!("Expected a frame"),
+        }
+    }
+
+    #[test]
+    fn inferior_tracking() {
+        let records = [
+            "thread-group-added,id=\"i1\"",
+            "thread-group-started,id=\"i1\",pid=\"4242\"",
+            "thread-created,id=\"1\",group-id=\"i1\"",
+            "thread-group-added,id=\"i2\"",
+            "thread-group-started,id=\"i2\",pid=\"4243\"",
+            "thread-created,id=\"2\",group-id=\"i2\"",
+            "thread-created,id=\"3\",group-id=\"i2\"",
+            "thread-exited,id=\"3\",group-id=\"i2\"",
+            "library-loaded,id=\"/lib/libc.so.6\",thread-group=\"i2\"",
+        ];
+
+        let mut inferiors = HashMap::new();
+        for r in records {
+            Inferior::track(&mut inferiors, r);
+        }
+
+        assert_eq!(inferiors.len(), 2);
+        assert_eq!(inferiors[&1].pid, Some(4242));
+        assert_eq!(inferiors[&1].threads, [1]);
+        assert_eq!(inferiors[&2].threads, [2]);
+        assert!(inferiors[&2].is_live());
+
+        /* Exit codes are printed in octal */
+        Inferior::track(
+            &mut inferiors,
+            "thread-group-exited,id=\"i2\",exit-code=\"017\"",
+        );
+        assert_eq!(inferiors[&2].exit_code, Some(15));
+        assert!(inferiors[&2].threads.is_empty());
+        assert!(!inferiors[&2].is_live());
+        assert!(Inferior::primary(&inferiors).unwrap().is_live());
+
+        Inferior::track(&mut inferiors, "thread-group-exited,id=\"i1\"");
+        assert_eq!(inferiors[&1].exit_code, None);
+        assert!(!Inferior::primary(&inferiors).unwrap().is_live());
+
+        Inferior::track(&mut inferiors, "thread-group-removed,id=\"i2\"");
+        assert_eq!(inferiors.len(), 1);
+    }
+
+    #[test]
+    fn crash_registers() {
+        let named = |names: &[&str]| -> Vec<(String, String)> {
+            names
+                .iter()
+                .map(|n| (n.to_string(), "0x0".to_string()))
+                .collect()
+        };
+
+        /* LLDB follows the x86_64 status register with the segment and sub registers */
+        let regs = named(&["rax", "rip", "rflags", "cs", "eax", "ax"]);
+        let kept: Vec<String> = CrashInfo::general_registers(regs)
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(kept, ["rax", "rip", "rflags"]);
+
+        let many: Vec<String> = (0..100).map(|i| format!("x{}", i)).collect();
+        let many: Vec<&str> = many.iter().map(|v| v.as_str()).collect();
+        assert_eq!(
+            CrashInfo::general_registers(named(&many)).len(),
+            MAX_CRASH_REGISTERS
+        );
+    }
 }
@@ -5,35 +5,74 @@ use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::health::SubtreeHealth;
+use crate::health::Unreachable;
 use crate::metadata::BacktraceState;
+use crate::metadata::LibraryReport;
+use crate::metadata::LockReport;
+use crate::metadata::MpiRankState;
 use crate::metadata::ProcessInfo;
+use crate::metadata::ProgramSnapshot;
 use crate::metadata::RunState;
+use crate::metadata::SnapshotPart;
+use crate::metadata::StopReason;
 use crate::metadata::SymbolTable;
+use crate::topology::Placement;
+use crate::transport::Endpoint;
+use crate::wire::{Capabilities, Hello, WireFormat};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum GdbMachineCommand {
+    /* Always sent as JSON: a peer predating it fails on the unknown variant
+    name, in a binary format it would take the index 0 for another command */
+    Hello(Hello),
     Start,
     Count,
     Stop,
     Continue,
     GetState,
     GetSnapshot,
+    /* Answered by SnapshotPart responses followed by Ok */
+    StreamSnapshot,
+    GetProcessSnapshot(u64),
+    Eval(String),
+    GetProcesses,
     GetSymbols,
-    /* Process Info, Server Address */
-    Pivot(ProcessInfo, String),
-    Join(String),
+    GetLocks,
+    GetMpiWaits,
+    GetLibraries,
+    /* Process Info, Server Address, Capabilities of the process */
+    Pivot(ProcessInfo, Endpoint, Capabilities),
+    /* Server Address, Id, Span, Format the parent has to use, Capabilities */
+    Join(Endpoint, u64, u64, WireFormat, Capabilities),
+    /* Liveness check answered outside of the command path */
+    Heartbeat,
+    /* Run a command on the listed ids only */
+    Targeted(Vec<u64>, Box<GdbMachineCommand>),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum GdbMachineResponse {
+    /* Answer to Hello, in JSON as the command */
+    Hello(Hello),
     Error(String),
     Ok,
     State(HashMap<u64, RunState>),
     Snapshot(HashMap<u64, (u64, Vec<BacktraceState>)>),
+    SnapshotPart(SnapshotPart),
+    ProcessSnapshot(Option<Box<ProgramSnapshot>>),
     Symbols(SymbolTable),
-    /* Returns Join URL and TreeDynamic */
-    Pivot(u64, String),
+    Values(HashMap<u64, String>),
+    Processes(HashMap<u64, ProcessInfo>),
+    Locks(HashMap<u64, LockReport>),
+    MpiWaits(HashMap<u64, MpiRankState>),
+    Libraries(LibraryReport),
+    /* Returns the placement and if the session already started */
+    Pivot(Placement, bool),
     Count(u64),
+    /* Start, Stop or Continue did not reach the listed subtrees */
+    Unreachable(Vec<u64>),
+    Health(Vec<SubtreeHealth>),
 }
 
 impl GdbMachineResponse {
@@ -41,6 +80,10 @@ impl GdbMachineResponse {
         match &self {
             GdbMachineResponse::Ok => Ok(()),
             GdbMachineResponse::Error(e) => Err(anyhow!("Error: {}", e)),
+            GdbMachineResponse::Unreachable(subtrees) => Err(Unreachable {
+                subtrees: subtrees.clone(),
+            }
+            .into()),
             _ => Err(anyhow!("This is not a return type")),
         }
     }
@@ -55,7 +98,10 @@ impl GdbMachineResponse {
     pub fn from_result(res: Result<()>) -> GdbMachineResponse {
         match res {
             Ok(_) => GdbMachineResponse::Ok,
-            Err(e) => GdbMachineResponse::Error(e.to_string()),
+            Err(e) => match e.downcast_ref::<Unreachable>() {
+                Some(u) => GdbMachineResponse::Unreachable(u.subtrees.clone()),
+                None => GdbMachineResponse::Error(e.to_string()),
+            },
         }
     }
 
@@ -68,6 +114,15 @@ impl GdbMachineResponse {
         }
     }
 
+    pub fn process_snapshot_from_result(
+        ret: Result<Option<ProgramSnapshot>>,
+    ) -> GdbMachineResponse {
+        match ret {
+            Ok(ps) => GdbMachineResponse::ProcessSnapshot(ps.map(Box::new)),
+            Err(e) => GdbMachineResponse::Error(e.to_string()),
+        }
+    }
+
     pub fn symbols_from_result(ret: Result<SymbolTable>) -> GdbMachineResponse {
         match ret {
             Ok(st) => GdbMachineResponse::Symbols(st),
@@ -75,6 +130,81 @@ impl GdbMachineResponse {
         }
     }
 
+    pub fn locks_from_result(ret: Result<HashMap<u64, LockReport>>) -> GdbMachineResponse {
+        match ret {
+            Ok(lk) => GdbMachineResponse::Locks(lk),
+            Err(e) => GdbMachineResponse::Error(e.to_string()),
+        }
+    }
+
+    pub fn mpi_waits_from_result(ret: Result<HashMap<u64, MpiRankState>>) -> GdbMachineResponse {
+        match ret {
+            Ok(mw) => GdbMachineResponse::MpiWaits(mw),
+            Err(e) => GdbMachineResponse::Error(e.to_string()),
+        }
+    }
+
+    pub fn libraries_from_result(ret: Result<LibraryReport>) -> GdbMachineResponse {
+        match ret {
+            Ok(libs) => GdbMachineResponse::Libraries(libs),
+            Err(e) => GdbMachineResponse::Error(e.to_string()),
+        }
+    }
+
+    pub fn values_from_result(ret: Result<HashMap<u64, String>>) -> GdbMachineResponse {
+        match ret {
+            Ok(v) => GdbMachineResponse::Values(v),
+            Err(e) => GdbMachineResponse::Error(e.to_string()),
+        }
+    }
+
+    pub fn processes_from_result(ret: Result<HashMap<u64, ProcessInfo>>) -> GdbMachineResponse {
+        match ret {
+            Ok(p) => GdbMachineResponse::Processes(p),
+            Err(e) => GdbMachineResponse::Error(e.to_string()),
+        }
+    }
+
+    /// Response standing for the processes `ids` of `subtree` which could not answer `cmd`
+    ///
+    /// Returns `None` for commands whose result cannot be partial.
+    pub fn unreachable(
+        cmd: &GdbMachineCommand,
+        subtree: u64,
+        ids: &[u64],
+    ) -> Option<GdbMachineResponse> {
+        match cmd {
+            GdbMachineCommand::Start | GdbMachineCommand::Stop | GdbMachineCommand::Continue => {
+                Some(GdbMachineResponse::Unreachable(vec![subtree]))
+            }
+            /* The processes are still part of the tree */
+            GdbMachineCommand::Count => Some(GdbMachineResponse::Count(ids.len() as u64)),
+            GdbMachineCommand::GetState => Some(GdbMachineResponse::State(
+                ids.iter()
+                    .map(|v| (*v, RunState::Stopped(Box::new(StopReason::unreachable()))))
+                    .collect(),
+            )),
+            GdbMachineCommand::GetSnapshot => Some(GdbMachineResponse::Snapshot(
+                ProgramSnapshot::unreachable_components(ids.len() as u64),
+            )),
+            GdbMachineCommand::Eval(_) => Some(GdbMachineResponse::Values(
+                ids.iter()
+                    .map(|v| (*v, "<unreachable>".to_string()))
+                    .collect(),
+            )),
+            GdbMachineCommand::GetProcesses => Some(GdbMachineResponse::Processes(HashMap::new())),
+            GdbMachineCommand::GetLocks => Some(GdbMachineResponse::Locks(HashMap::new())),
+            GdbMachineCommand::GetMpiWaits => Some(GdbMachineResponse::MpiWaits(HashMap::new())),
+            GdbMachineCommand::GetLibraries => {
+                Some(GdbMachineResponse::Libraries(LibraryReport::default()))
+            }
+            GdbMachineCommand::Targeted(_, cmd) => {
+                GdbMachineResponse::unreachable(cmd, subtree, ids)
+            }
+            _ => None,
+        }
+    }
+
     pub fn state(self) -> HashMap<u64, RunState> {
         if let GdbMachineResponse::State(st) = self {
             return st;
@@ -91,6 +221,54 @@ impl GdbMachineResponse {
         Err(anyhow!("Failed to retrieve snapshot from command"))
     }
 
+    pub fn process_snapshot(self) -> Result<Option<ProgramSnapshot>> {
+        match self {
+            GdbMachineResponse::ProcessSnapshot(ps) => Ok(ps.map(|v| *v)),
+            GdbMachineResponse::Error(e) => Err(anyhow!("Error: {}", e)),
+            _ => Err(anyhow!("Failed to retrieve process snapshot from command")),
+        }
+    }
+
+    pub fn locks(self) -> Result<HashMap<u64, LockReport>> {
+        match self {
+            GdbMachineResponse::Locks(lk) => Ok(lk),
+            GdbMachineResponse::Error(e) => Err(anyhow!("Error: {}", e)),
+            _ => Err(anyhow!("Failed to retrieve lock report from command")),
+        }
+    }
+
+    pub fn mpi_waits(self) -> Result<HashMap<u64, MpiRankState>> {
+        match self {
+            GdbMachineResponse::MpiWaits(mw) => Ok(mw),
+            GdbMachineResponse::Error(e) => Err(anyhow!("Error: {}", e)),
+            _ => Err(anyhow!("Failed to retrieve MPI waits from command")),
+        }
+    }
+
+    pub fn libraries(self) -> Result<LibraryReport> {
+        match self {
+            GdbMachineResponse::Libraries(libs) => Ok(libs),
+            GdbMachineResponse::Error(e) => Err(anyhow!("Error: {}", e)),
+            _ => Err(anyhow!("Failed to retrieve libraries from command")),
+        }
+    }
+
+    pub fn values(self) -> Result<HashMap<u64, String>> {
+        match self {
+            GdbMachineResponse::Values(v) => Ok(v),
+            GdbMachineResponse::Error(e) => Err(anyhow!("Error: {}", e)),
+            _ => Err(anyhow!("Failed to retrieve values from command")),
+        }
+    }
+
+    pub fn processes(self) -> Result<HashMap<u64, ProcessInfo>> {
+        match self {
+            GdbMachineResponse::Processes(p) => Ok(p),
+            GdbMachineResponse::Error(e) => Err(anyhow!("Error: {}", e)),
+            _ => Err(anyhow!("Failed to retrieve processes from command")),
+        }
+    }
+
     pub fn symbols(self) -> Result<SymbolTable> {
         if let GdbMachineResponse::Symbols(sy) = self {
             return Ok(sy);
@@ -0,0 +1,51 @@
+# Python frames of the selected thread, sourced in GDB by snapped
+#
+# Relies on the helpers of libpython (python-gdb.py) which GDB auto-loads
+# along with the debug information of the interpreter (e.g. python3-dbg).
+# Prints one line per Python frame, innermost first:
+#   <level of the C frame>\t<function>\t<file>\t<line>
+
+import gdb
+
+
+def _snapped_pyframes():
+    try:
+        helper = Frame
+    except NameError:
+        print("nohelpers")
+        return
+
+    level = 0
+    frame = gdb.newest_frame()
+
+    while frame is not None:
+        try:
+            pyframe = helper(frame)
+
+            if pyframe.is_evalframe():
+                pyop = pyframe.get_pyop()
+
+                # Since 3.11 a C frame runs all the Python calls it inlined
+                while pyop is not None and not pyop.is_optimized_out():
+                    if hasattr(pyop, "is_shim") and pyop.is_shim():
+                        break
+
+                    print(
+                        "%d\t%s\t%s\t%d"
+                        % (
+                            level,
+                            pyop.co_name.proxyval(set()),
+                            pyop.filename(),
+                            pyop.current_line_num() or 0,
+                        )
+                    )
+
+                    if not hasattr(pyop, "previous") or pyop.is_entry():
+                        break
+
+                    pyop = pyop.previous()
+        except Exception:
+            pass
+
+        level += 1
+        frame = frame.older()
@@ -0,0 +1,206 @@
+//! Python-aware stack unwinding
+//!
+//! Threads running CPython show up as a pile of `_PyEval_EvalFrameDefault`
+//! frames. When enabled, the C frames evaluating Python code are replaced by
+//! one frame per Python call with the function, file and line of the script
+//! so that the snapshot tree groups processes by Python call site.
+
+use anyhow::{anyhow, Result};
+
+use crate::metadata::DebugFrame;
+
+/// Set to `1` to unwind the Python frames of CPython threads
+pub const PYTHON_FRAMES_ENV: &str = "GDBW_PYTHON_FRAMES";
+
+/// GDB script printing the Python frames with the helpers of libpython
+pub const HELPER_SCRIPT: &str = include_str!("pyframes.py");
+
+/// Printed by [HELPER_SCRIPT] when libpython did not provide its helpers
+pub const NO_HELPERS: &str = "nohelpers";
+
+/// C frames evaluating Python code
+pub const EVAL_FRAME_FUNCTIONS: [&str; 2] = ["_PyEval_EvalFrameDefault", "PyEval_EvalFrameEx"];
+
+/// Interpreter plumbing between two evaluation frames, hidden once Python frames are known
+const INTERPRETER_FRAME_PREFIXES: [&str; 16] = [
+    "_PyEval_",
+    "PyEval_",
+    "_PyFunction_",
+    "_PyObject_Call",
+    "_PyObject_Vectorcall",
+    "_PyObject_MakeTpCall",
+    "PyObject_Call",
+    "PyObject_Vectorcall",
+    "_PyVectorcall",
+    "method_vectorcall",
+    "cfunction_",
+    "slot_tp_",
+    "run_eval_code_obj",
+    "run_mod",
+    "_PyRun_",
+    "PyRun_",
+];
+
+/// Ways of reading a frame without the helpers, from the oldest CPython
+///
+/// Each entry is the code object of the frame given as argument to the
+/// evaluation function and the expression of its current line.
+pub const FRAME_LAYOUTS: [(&str, &str); 3] = [
+    /* Up to 3.10 */
+    ("f->f_code", "PyFrame_GetLineNumber(f)"),
+    /* 3.11 and 3.12, as _PyInterpreterFrame_GetLine which is not exported */
+    (
+        "frame->f_code",
+        "PyCode_Addr2Line(frame->f_code, (int)((char *)frame->prev_instr - (char *)frame->f_code->co_code_adaptive))",
+    ),
+    /* 3.13 */
+    (
+        "((PyCodeObject *)frame->f_executable)",
+        "PyUnstable_InterpreterFrame_GetLine(frame)",
+    ),
+];
+
+/// GDB command defining the functions of [HELPER_SCRIPT]
+///
+/// The script is passed inline rather than through a file so that nothing
+/// is written where other users could tamper with it.
+pub fn helper_command() -> String {
+    exec_command(HELPER_SCRIPT)
+}
+
+/// GDB command running a multi-line Python `script`
+pub fn exec_command(script: &str) -> String {
+    let script = script
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+
+    format!("python exec(\"{}\")", script)
+}
+
+pub fn enabled() -> bool {
+    std::env::var(PYTHON_FRAMES_ENV).is_ok_and(|v| v == "1")
+}
+
+/// Is this a C frame evaluating Python code
+pub fn is_eval_frame(f: &DebugFrame) -> bool {
+    EVAL_FRAME_FUNCTIONS.contains(&f.func.as_str())
+}
+
+fn is_interpreter_frame(f: &DebugFrame) -> bool {
+    INTERPRETER_FRAME_PREFIXES
+        .iter()
+        .any(|p| f.func.starts_with(p))
+}
+
+/// Expression of the name of a Python string object
+///
+/// Identifiers and paths are compact ASCII strings whose characters
+/// directly follow the object, this avoids calling into the inferior.
+pub fn ascii_expr(obj: &str) -> String {
+    format!("(char *)((PyASCIIObject *)({}) + 1)", obj)
+}
+
+/// A Python call of a thread
+#[derive(Debug, Clone)]
+pub struct PyFrame {
+    /// Level of the C frame evaluating it
+    pub level: u32,
+    pub func: String,
+    pub file: String,
+    pub line: u32,
+}
+
+impl PyFrame {
+    /// Parse a line printed by [HELPER_SCRIPT]
+    pub fn new(desc: &str) -> Result<PyFrame> {
+        let fields: Vec<&str> = desc.trim_end().split('\t').collect();
+
+        if let [level, func, file, line] = fields.as_slice() {
+            return Ok(PyFrame {
+                level: level.parse::<u32>()?,
+                func: func.to_string(),
+                file: file.to_string(),
+                line: line.parse::<u32>()?,
+            });
+        }
+
+        Err(anyhow!("Bad Python frame descriptor '{}'", desc))
+    }
+
+    fn to_frame(&self, parent: &DebugFrame) -> DebugFrame {
+        DebugFrame {
+            level: parent.level,
+            addr: parent.addr.clone(),
+            func: self.func.clone(),
+            file: Some(self.file.clone()),
+            fullname: Some(self.file.clone()),
+            line: Some(self.line),
+            from: None,
+            offset: None,
+            disassembly: None,
+            inlined: false,
+            arch: None,
+            args: None,
+            locals: None,
+        }
+    }
+}
+
+/// Substitute the Python frames to the C frames of the interpreter
+///
+/// `pyframes` are innermost first as the backtrace. Interpreter frames
+/// are only dropped when Python frames were found, evaluation frames
+/// which could not be read are kept.
+pub fn decorate(bt: Vec<DebugFrame>, pyframes: &[PyFrame]) -> Vec<DebugFrame> {
+    if pyframes.is_empty() {
+        return bt;
+    }
+
+    let mut ret = Vec::new();
+
+    for frame in bt {
+        let calls: Vec<&PyFrame> = pyframes.iter().filter(|p| p.level == frame.level).collect();
+
+        if !calls.is_empty() {
+            ret.extend(calls.iter().map(|p| p.to_frame(&frame)));
+        } else if is_eval_frame(&frame) || !is_interpreter_frame(&frame) {
+            ret.push(frame);
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn helper_command_defines_the_helpers() {
+        let cmd = helper_command();
+        assert!(!cmd.contains('\n'));
+
+        /* Run what GDB runs, with a stand-in for its module */
+        let code = format!(
+            "import sys, types\nsys.modules['gdb'] = types.ModuleType('gdb')\n{}\n_snapped_pyframes()",
+            cmd.strip_prefix("python ").unwrap()
+        );
+
+        let out = match Command::new("python3").arg("-c").arg(&code).output() {
+            Ok(out) => out,
+            Err(_) => {
+                eprintln!("python3 not found, skipping");
+                return;
+            }
+        };
+
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        assert_eq!(String::from_utf8_lossy(&out.stdout).trim(), NO_HELPERS);
+    }
+}
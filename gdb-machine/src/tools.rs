@@ -4,7 +4,7 @@ use std::fs::File;
 use std::io::BufReader;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::{cmp::max, collections::HashMap, io::Read};
+use std::{collections::HashMap, io::Read};
 
 pub fn dominating_numa_id() -> Result<u64> {
     let numa_maps = PathBuf::from_str("/proc/self/numa_maps")?;
@@ -61,21 +61,6 @@ pub fn dominating_numa_id() -> Result<u64> {
     }
 }
 
-pub fn strdistance(a: &String, b: &String) -> u64 {
-    let len = max(a.len(), b.len());
-
-    let mut ret: u64 = 0;
-
-    for i in 0..len {
-        let va: u64 = a.chars().nth(i).and_then(|v| Some(v as u64)).unwrap_or(0);
-        let vb: u64 = b.chars().nth(i).and_then(|v| Some(v as u64)).unwrap_or(0);
-
-        ret += va.abs_diff(vb);
-    }
-
-    ret
-}
-
 pub fn parse_gdb_equal_list(list: &str) -> HashMap<String, String> {
     let re = Regex::new("([a-z\\-]+)=\"([^\"]+)\"").unwrap();
 
@@ -92,15 +77,6 @@ pub fn parse_gdb_equal_list(list: &str) -> HashMap<String, String> {
     HashMap::from_iter(cap)
 }
 
-pub fn extract_gdb_group(list: &str) -> Vec<String> {
-    let re = Regex::new("\\{([^{}]*)\\}").unwrap();
-
-    re.captures_iter(list)
-        .flat_map(|v| v.get(1))
-        .map(|v| v.as_str().to_string())
-        .collect()
-}
-
 pub fn parse_response_with_token(marker: &str, resp: &str) -> Option<(u64, String)> {
     let re = Regex::new(format!("^([0-9]+){}(.*)\n", marker).as_str()).ok()?;
 
@@ -122,27 +98,160 @@ pub fn gdb_output_to_json_repr(resp: &str) -> Result<String> {
     Ok(resp_json.to_string())
 }
 
-pub fn read_until_null(stream: &mut impl Read) -> Result<String> {
-    let mut ret: String = String::new();
+/// Split a GDB-MI list into its top-level `{...}` tuples
+///
+/// Nested tuples and lists are kept verbatim inside each returned entry
+/// so that `threads=[{id="1",frame={...}},{id="2",...}]` yields one
+/// string per thread.
+pub fn split_gdb_tuples(list: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    let mut depth = 0;
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut current = String::new();
 
-    loop {
-        let mut data = [0; 1];
-        match stream.read(&mut data) {
-            Ok(0) => {
-                return Ok(ret);
+    for c in list.chars() {
+        if in_str {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_str = false;
             }
-            Ok(n) => {
-                for i in 0..n {
-                    if data[i] as char == '\0' {
-                        return Ok(ret);
-                    } else {
-                        ret.push_str(std::str::from_utf8(&data[i..i + 1])?);
-                    }
-                }
+        } else if c == '"' {
+            in_str = true;
+        } else if c == '{' {
+            depth += 1;
+            if depth == 1 {
+                continue;
             }
-            Err(e) => {
-                return Err(anyhow!(e));
+        } else if c == '}' {
+            depth -= 1;
+            if depth == 0 {
+                ret.push(current.clone());
+                current.clear();
+                continue;
             }
         }
+
+        if depth > 0 {
+            current.push(c);
+        }
+    }
+
+    ret
+}
+
+/// Drop every nested tuple or list from a GDB-MI record
+///
+/// This keeps only the `key="value"` pairs of the outer level which can
+/// then be safely handled by [parse_gdb_equal_list].
+pub fn gdb_toplevel_fields(record: &str) -> String {
+    let mut ret = String::new();
+    let mut depth = 0;
+    let mut in_str = false;
+    let mut escaped = false;
+
+    for c in record.chars() {
+        if in_str {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_str = false;
+            }
+        } else if c == '"' {
+            in_str = true;
+        } else if c == '{' || c == '[' {
+            depth += 1;
+            continue;
+        } else if c == '}' || c == ']' {
+            depth -= 1;
+            continue;
+        }
+
+        if depth == 0 {
+            ret.push(c);
+        }
+    }
+
+    ret
+}
+
+/// Top-level `key="value"` pairs of a GDB-MI tuple with their values decoded
+///
+/// Unlike [parse_gdb_equal_list] values may be empty or hold escaped
+/// quotes, as the values of `char *` variables do.
+pub fn parse_gdb_fields(tuple: &str) -> HashMap<String, String> {
+    let re = Regex::new(r#"([a-z\-]+)="((?:[^"\\]|\\.)*)""#).unwrap();
+
+    re.captures_iter(&gdb_toplevel_fields(tuple))
+        .flat_map(|c| {
+            Some((
+                c.get(1)?.as_str().to_string(),
+                decode_escapes(c.get(2)?.as_str()),
+            ))
+        })
+        .collect()
+}
+
+/// Decode a GDB-MI c-string such as the `"text\n"` of console records
+pub fn unescape_gdb_string(s: &str) -> String {
+    let s = s.trim_end();
+    let s = s.strip_prefix('"').unwrap_or(s);
+    let s = s.strip_suffix('"').unwrap_or(s);
+
+    decode_escapes(s)
+}
+
+fn decode_escapes(s: &str) -> String {
+    let mut ret = String::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => ret.push('\n'),
+            Some('t') => ret.push('\t'),
+            Some(other) => ret.push(other),
+            None => {}
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `-thread-info` of a process stopped in a barrier
+    const THREAD_INFO: &str = r#"threads=[{id="1",target-id="Thread 0x7ffff7d8a740 (LWP 4242)",name="solver",frame={level="0",addr="0x00007ffff7e9b117",func="pthread_barrier_wait",args=[{name="barrier",value="0x5555555580a0 <bar>"}],from="/lib/x86_64-linux-gnu/libc.so.6",arch="i386:x86-64"},state="stopped",core="2"},{id="2",target-id="Thread 0x7ffff7589640 (LWP 4243)",name="worker {1}",frame={level="0",addr="0x0000555555555209",func="spin",args=[],file="solver.c",fullname="/src/solver.c",line="12",arch="i386:x86-64"},state="stopped",core="5"}],current-thread-id="1""#;
+
+    #[test]
+    fn gdb_tuples() {
+        let threads = split_gdb_tuples(THREAD_INFO);
+        assert_eq!(threads.len(), 2);
+        assert!(threads[0].starts_with("id=\"1\""));
+        assert!(threads[0].ends_with("core=\"2\""));
+        /* Braces in strings do not end the tuple */
+        assert!(threads[1].contains("name=\"worker {1}\""));
+
+        let fields = parse_gdb_equal_list(&gdb_toplevel_fields(&threads[0]));
+        assert_eq!(fields["id"], "1");
+        assert_eq!(fields["core"], "2");
+        assert_eq!(fields["state"], "stopped");
+        /* Fields of the nested frame and arguments are dropped */
+        assert!(!fields.contains_key("func"));
+        assert!(!fields.contains_key("level"));
+        assert!(!fields.contains_key("value"));
+
+        assert!(split_gdb_tuples("threads=[]").is_empty());
     }
 }
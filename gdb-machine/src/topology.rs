@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::locality::{short_host, Locality, LocalityGroups};
+use crate::metadata::{ProcessInfo, TreeIdFactory};
+use crate::transport::Endpoint;
+
+/// Default number of children of a node in the tree
+pub const DEFAULT_ARITY: u64 = 24;
+
+/// Children slots of the root in a flat tree
+const FLAT_ARITY: u64 = 1 << 20;
+
+/// Slots kept on a host aggregator for the other processes of its host
+const HOST_SLOTS: u64 = 1024;
+
+/// Strategy used to place pivoting processes in the tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Topology {
+    /// Every process is a child of the root
+    Flat,
+    /// Each node has at most `k` children, processes join the closest node
+    /// (same NUMA, then same host, then same rack/switch group)
+    Kary(u64),
+    /// One aggregator per host, aggregators form a `k`-ary tree
+    Host(u64),
+    /// As `Host` with aggregators laid out in the order of a node list
+    Hostlist(u64, Vec<String>),
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Topology::Kary(DEFAULT_ARITY)
+    }
+}
+
+impl std::fmt::Display for Topology {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Topology::Flat => write!(f, "flat"),
+            Topology::Kary(k) => write!(f, "kary:{}", k),
+            Topology::Host(k) => write!(f, "host:{}", k),
+            Topology::Hostlist(k, hosts) => write!(f, "hostlist ({} hosts, k={})", hosts.len(), k),
+        }
+    }
+}
+
+fn parse_arity(k: &str) -> Result<u64> {
+    match k.parse::<u64>() {
+        Ok(k) if k > 0 => Ok(k),
+        _ => Err(anyhow!("Bad arity '{}' (expected a positive integer)", k)),
+    }
+}
+
+impl FromStr for Topology {
+    type Err = anyhow::Error;
+
+    /// Parse `flat`, `kary[:K]`, `host[:K]` or `hostlist[:K][:NODELIST]`
+    ///
+    /// Without an explicit node list `hostlist` uses `SLURM_JOB_NODELIST`.
+    fn from_str(s: &str) -> Result<Self> {
+        Topology::parse_with(s, std::env::var("SLURM_JOB_NODELIST").ok())
+    }
+}
+
+impl Topology {
+    /// Parse a topology, `nodelist` is used by `hostlist` without a node list
+    pub fn parse_with(s: &str, nodelist: Option<String>) -> Result<Topology> {
+        let (kind, arg) = s.split_once(':').unwrap_or((s, ""));
+
+        match (kind, arg) {
+            ("flat", "") => Ok(Topology::Flat),
+            ("kary", "") => Ok(Topology::Kary(DEFAULT_ARITY)),
+            ("kary", k) => Ok(Topology::Kary(parse_arity(k)?)),
+            ("host", "") => Ok(Topology::Host(DEFAULT_ARITY)),
+            ("host", k) => Ok(Topology::Host(parse_arity(k)?)),
+            ("hostlist", arg) => {
+                /* A leading number is the arity, node lists have no ':' */
+                let (k, list) = match arg.split_once(':') {
+                    Some((k, list)) if k.parse::<u64>().is_ok() => (parse_arity(k)?, list),
+                    _ if !arg.is_empty() && arg.parse::<u64>().is_ok() => (parse_arity(arg)?, ""),
+                    _ => (DEFAULT_ARITY, arg),
+                };
+
+                let list = if list.is_empty() {
+                    nodelist.ok_or_else(|| {
+                        anyhow!("hostlist needs a node list or SLURM_JOB_NODELIST")
+                    })?
+                } else {
+                    list.to_string()
+                };
+
+                Ok(Topology::Hostlist(k, expand_hostlist(&list)?))
+            }
+            _ => Err(anyhow!(
+                "Unknown topology '{}' (flat, kary[:K], host[:K] or hostlist[:K][:NODELIST])",
+                s
+            )),
+        }
+    }
+}
+
+/// Expand a Slurm host list such as `node[01-03,7],login1` or `r[1-2]n[01-04]`
+pub fn expand_hostlist(list: &str) -> Result<Vec<String>> {
+    let mut ret = Vec::new();
+
+    /* Split on the commas which are not in a range */
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in list.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&list[start..]);
+
+    for item in items
+        .into_iter()
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+    {
+        ret.extend(expand_host(item)?);
+    }
+
+    Ok(ret)
+}
+
+/// Expand one host of a list, each bracket group multiplies the names
+fn expand_host(item: &str) -> Result<Vec<String>> {
+    let (prefix, rest) = match item.split_once('[') {
+        Some(v) => v,
+        None => return Ok(vec![item.to_string()]),
+    };
+
+    let (ranges, suffix) = rest
+        .split_once(']')
+        .ok_or(anyhow!("Unbalanced brackets in host list '{}'", item))?;
+
+    let suffixes = expand_host(suffix)?;
+    let mut ret = Vec::new();
+
+    for range in ranges.split(',') {
+        let (lo, hi) = range.split_once('-').unwrap_or((range, range));
+        let width = lo.len();
+        let lo = lo.parse::<u64>()?;
+        let hi = hi.parse::<u64>()?;
+
+        for v in lo..=hi {
+            for suffix in suffixes.iter() {
+                ret.push(format!("{}{:0width$}{}", prefix, v, suffix, width = width));
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Where a pivoting process was placed in the tree
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Placement {
+    /// Id of the process
+    pub id: u64,
+    /// Width of the id range of its subtree
+    pub span: u64,
+    /// Depth of the process in the tree (root is 0)
+    pub depth: u32,
+    /// Server of the parent the process has to join
+    pub parent: Endpoint,
+}
+
+/// A node of the tree as laid out by the root
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TreeLayout {
+    pub id: u64,
+    pub host: String,
+    pub pid: u64,
+    pub rank: Option<u32>,
+    pub children: Vec<TreeLayout>,
+}
+
+struct PlanNode {
+    locator: String,
+    info: Option<ProcessInfo>,
+    /* Parsed locator (None for the root) */
+    locality: Option<Locality>,
+    endpoint: Endpoint,
+    ids: TreeIdFactory,
+    depth: u32,
+    children: Vec<usize>,
+    /* Host aggregators below this node (host strategies) */
+    aggregators: usize,
+}
+
+/// Placement of the processes in the tree (only known by the root)
+pub struct TreePlan {
+    topology: Topology,
+    nodes: Vec<PlanNode>,
+    groups: LocalityGroups,
+    by_locator: HashMap<String, usize>,
+    /* Aggregator node of each host */
+    host_aggregator: HashMap<String, usize>,
+}
+
+impl TreePlan {
+    pub fn new(topology: Topology, root: Endpoint) -> TreePlan {
+        let arity = match topology {
+            Topology::Flat => FLAT_ARITY,
+            Topology::Kary(k) | Topology::Host(k) | Topology::Hostlist(k, _) => k,
+        };
+
+        let groups = LocalityGroups::from_env().unwrap_or_else(|e| {
+            log::warn!("Ignoring rack/switch groups : {}", e);
+            LocalityGroups::default()
+        });
+
+        TreePlan {
+            topology,
+            groups,
+            nodes: vec![PlanNode {
+                locator: "ROOT".to_string(),
+                info: None,
+                locality: None,
+                endpoint: root,
+                ids: TreeIdFactory::new(arity),
+                depth: 0,
+                children: Vec::new(),
+                aggregators: 0,
+            }],
+            by_locator: HashMap::new(),
+            host_aggregator: HashMap::new(),
+        }
+    }
+
+    /// Number of processes placed in the tree
+    pub fn len(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ids of the processes placed in the half-open id range `[start, end)`
+    pub fn ids_in(&self, (start, end): (u64, u64)) -> Vec<u64> {
+        self.nodes[1..]
+            .iter()
+            .map(|n| n.ids.id())
+            .filter(|id| (start..end).contains(id))
+            .collect()
+    }
+
+    fn arity(&self) -> u64 {
+        match self.topology {
+            Topology::Flat => FLAT_ARITY,
+            Topology::Kary(k) | Topology::Host(k) | Topology::Hostlist(k, _) => k,
+        }
+    }
+
+    fn has_room(&self, node: usize) -> bool {
+        !self.nodes[node].ids.full()
+    }
+
+    /// Distance of a node to a process (the root is as far as another group)
+    fn distance(&self, node: usize, locality: &Locality) -> u32 {
+        self.nodes[node]
+            .locality
+            .as_ref()
+            .map(|l| l.distance(locality))
+            .unwrap_or(3)
+    }
+
+    /// Closest node with free slots, the shallowest one among equally close nodes
+    ///
+    /// Preferring shallow nodes keeps the depth logarithmic in the number of
+    /// processes of each host while intra-host links stay local.
+    fn closest(&self, locality: &Locality) -> Option<usize> {
+        (0..self.nodes.len())
+            .filter(|i| self.has_room(*i))
+            .min_by_key(|i| (self.distance(*i, locality), self.nodes[*i].depth, *i))
+    }
+
+    /// Closest shallow node which can still take a host aggregator
+    fn aggregator_slot(&self, candidates: &[usize], locality: &Locality) -> Option<usize> {
+        candidates
+            .iter()
+            .filter(|i| self.nodes[**i].aggregators < self.arity() as usize && self.has_room(**i))
+            .min_by_key(|i| (self.distance(**i, locality), self.nodes[**i].depth, **i))
+            .cloned()
+    }
+
+    /// Parent of a new host aggregator
+    fn aggregator_parent(&self, host: &str, locality: &Locality) -> Option<usize> {
+        if let Topology::Hostlist(k, hosts) = &self.topology {
+            if let Some(mut idx) = hosts.iter().position(|h| short_host(h) == host) {
+                /* Heap layout over the node list: the first k hosts are below the root */
+                loop {
+                    if (idx as u64) < *k {
+                        break;
+                    }
+
+                    idx = (idx as u64 / k - 1) as usize;
+
+                    if let Some(agg) = self.host_aggregator.get(short_host(&hosts[idx])) {
+                        if self.has_room(*agg) {
+                            return Some(*agg);
+                        }
+                    }
+                }
+
+                if self.has_room(0) {
+                    return Some(0);
+                }
+            }
+        }
+
+        let mut candidates = vec![0];
+        candidates.extend(self.host_aggregator.values().cloned());
+        self.aggregator_slot(&candidates, locality)
+    }
+
+    /// Place a new process, returns its parent and the slots it offers
+    fn parent_of(&self, info: &ProcessInfo, locality: &Locality) -> Result<(usize, u64, bool)> {
+        let host = short_host(&info.hostname);
+
+        let ret = match &self.topology {
+            Topology::Flat => Some((0, 1, false)),
+            Topology::Kary(k) => self.closest(locality).map(|p| (p, *k, false)),
+            Topology::Host(k) | Topology::Hostlist(k, _) => match self.host_aggregator.get(host) {
+                Some(agg) if self.has_room(*agg) => Some((*agg, 1, false)),
+                Some(_) => None,
+                None => self
+                    .aggregator_parent(host, locality)
+                    .map(|p| (p, k + HOST_SLOTS, true)),
+            },
+        };
+
+        ret.ok_or(anyhow!(
+            "No room left in the {} tree for {}",
+            self.topology,
+            info.locality_descriptor
+        ))
+    }
+
+    /// Place a process serving on `endpoint` in the tree
+    ///
+    /// A parent on the same host is joined over its Unix socket.
+    pub fn place(&mut self, info: &ProcessInfo, endpoint: Endpoint) -> Result<Placement> {
+        let locator = info.locality_descriptor.clone();
+
+        if self.by_locator.contains_key(&locator) {
+            return Err(anyhow!("Process {:?} is already registered", locator));
+        }
+
+        let locality = Locality::parse(&locator, &self.groups)?;
+
+        let (parent, arity, aggregator) = self.parent_of(info, &locality)?;
+
+        let ids = self.nodes[parent].ids.inherit(arity)?;
+        let depth = self.nodes[parent].depth + 1;
+
+        log::info!(
+            "PIVOT {} is Joining {}",
+            locator,
+            self.nodes[parent].locator
+        );
+
+        let placement = Placement {
+            id: ids.id(),
+            span: ids.span(),
+            depth,
+            parent: self.nodes[parent].endpoint.clone(),
+        };
+
+        let idx = self.nodes.len();
+
+        self.nodes.push(PlanNode {
+            locator: locator.clone(),
+            info: Some(info.clone()),
+            locality: Some(locality),
+            endpoint,
+            ids,
+            depth,
+            children: Vec::new(),
+            aggregators: 0,
+        });
+        self.nodes[parent].children.push(idx);
+        self.by_locator.insert(locator, idx);
+
+        if aggregator {
+            self.nodes[parent].aggregators += 1;
+            self.host_aggregator
+                .insert(short_host(&info.hostname).to_string(), idx);
+        }
+
+        Ok(placement)
+    }
+
+    fn _layout(&self, idx: usize) -> TreeLayout {
+        let node = &self.nodes[idx];
+
+        let (host, pid, rank) = match &node.info {
+            Some(info) => (info.hostname.clone(), info.pid, info.mpirank),
+            None => (node.locator.clone(), 0, None),
+        };
+
+        TreeLayout {
+            id: node.ids.id(),
+            host,
+            pid,
+            rank,
+            children: node.children.iter().map(|c| self._layout(*c)).collect(),
+        }
+    }
+
+    /// The tree as built so far
+    pub fn layout(&self) -> TreeLayout {
+        self._layout(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hostlist_arity() {
+        let hosts = vec!["n1".to_string(), "n2".to_string()];
+
+        assert_eq!(
+            "hostlist:n[1-2]".parse::<Topology>().unwrap(),
+            Topology::Hostlist(DEFAULT_ARITY, hosts.clone())
+        );
+        assert_eq!(
+            "hostlist:4:n[1-2]".parse::<Topology>().unwrap(),
+            Topology::Hostlist(4, hosts.clone())
+        );
+
+        assert_eq!(
+            Topology::parse_with("hostlist:8", Some("n[1-2]".to_string())).unwrap(),
+            Topology::Hostlist(8, hosts)
+        );
+        assert!(Topology::parse_with("hostlist:8", None).is_err());
+
+        assert!("hostlist:0:n1".parse::<Topology>().is_err());
+    }
+
+    #[test]
+    fn expand_hostlists() {
+        assert_eq!(
+            expand_hostlist("node[01-03,7],login1").unwrap(),
+            ["node01", "node02", "node03", "node7", "login1"]
+        );
+        assert_eq!(
+            expand_hostlist("r[1-2]n[01-02]-ib,gpu[3]").unwrap(),
+            ["r1n01-ib", "r1n02-ib", "r2n01-ib", "r2n02-ib", "gpu3"]
+        );
+        assert!(expand_hostlist("node[1-2").is_err());
+        assert!(expand_hostlist("r[1-2]n[0x]").is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use std::ffi::CStr;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Interface (or address) the servers of the tree listen on
+pub const BIND_INTERFACE_ENV: &str = "GDBW_BIND_INTERFACE";
+
+/// Host name or address the servers give to the processes connecting to them
+pub const ADVERTISE_ADDR_ENV: &str = "GDBW_ADVERTISE_ADDR";
+
+/// Prefix of the URLs of Unix sockets (`unix:@name` for abstract sockets)
+const UNIX_PREFIX: &str = "unix:";
+
+/// Host name of this node
+pub fn local_host() -> Result<String> {
+    Ok(gethostname::gethostname()
+        .to_str()
+        .context("Failed to convert hostname to string")?
+        .to_string())
+}
+
+/// Address of a network interface given by name (e.g. `ib0`) or address
+pub fn interface_address(iface: &str) -> Result<IpAddr> {
+    if let Ok(ip) = IpAddr::from_str(iface) {
+        return Ok(ip);
+    }
+
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+
+    if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+        return Err(anyhow!("Failed to list the network interfaces"));
+    }
+
+    let mut ret = None;
+    let mut cur = addrs;
+
+    while !cur.is_null() {
+        let ifa = unsafe { &*cur };
+        cur = ifa.ifa_next;
+
+        if ifa.ifa_addr.is_null() {
+            continue;
+        }
+
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) }.to_string_lossy();
+
+        if name != iface {
+            continue;
+        }
+
+        /* Prefer IPv4 as the rest of the cluster most likely uses it */
+        match unsafe { (*ifa.ifa_addr).sa_family } as i32 {
+            libc::AF_INET => {
+                let sin = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                ret = Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                    sin.sin_addr.s_addr,
+                ))));
+                break;
+            }
+            libc::AF_INET6 if ret.is_none() => {
+                let sin6 = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+                ret = Some(IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)));
+            }
+            _ => {}
+        }
+    }
+
+    unsafe { libc::freeifaddrs(addrs) };
+
+    ret.ok_or(anyhow!("No address on network interface '{}'", iface))
+}
+
+/// Is [BIND_INTERFACE_ENV] an address rather than an interface name
+///
+/// An address only exists on one host, it is not passed to the leaves.
+pub fn bind_is_address() -> bool {
+    std::env::var(BIND_INTERFACE_ENV).is_ok_and(|v| IpAddr::from_str(&v).is_ok())
+}
+
+/// Address the TCP server binds to, all interfaces unless [BIND_INTERFACE_ENV] is set
+pub fn bind_address() -> Result<SocketAddr> {
+    let ip = match std::env::var(BIND_INTERFACE_ENV) {
+        Ok(iface) => interface_address(&iface)?,
+        Err(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    };
+
+    Ok(SocketAddr::new(ip, 0))
+}
+
+/// Host put in the URL of a server bound to `bind`
+pub fn advertised_host(bind: &SocketAddr) -> Result<String> {
+    if let Ok(addr) = std::env::var(ADVERTISE_ADDR_ENV) {
+        return Ok(addr);
+    }
+
+    match bind.ip() {
+        ip if ip.is_unspecified() => local_host(),
+        IpAddr::V4(ip) => Ok(ip.to_string()),
+        IpAddr::V6(ip) => Ok(format!("[{}]", ip)),
+    }
+}
+
+/// Listen on an abstract Unix socket (no file to clean up)
+pub fn unix_listener(tag: u16) -> Result<(UnixListener, String)> {
+    let name = format!("snapped-{}-{}", std::process::id(), tag);
+    let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?;
+
+    Ok((
+        UnixListener::bind_addr(&addr)?,
+        format!("{}@{}", UNIX_PREFIX, name),
+    ))
+}
+
+/// How to reach a server
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Endpoint {
+    /// Host the server runs on
+    pub host: String,
+    /// TCP URL reachable from the other hosts
+    pub tcp: String,
+    /// Unix socket URL reachable from the same host
+    pub unix: Option<String>,
+}
+
+impl Endpoint {
+    /// URL a process running on `host` should connect to
+    pub fn url_from(&self, host: &str) -> &str {
+        match &self.unix {
+            Some(unix) if self.host == host => unix,
+            _ => &self.tcp,
+        }
+    }
+
+    /// Connect from `host`, returns the connection and the URL it used
+    ///
+    /// Abstract Unix sockets only exist in the network namespace of the
+    /// server (e.g. another container on the same host), TCP is used when
+    /// they cannot be reached.
+    pub fn connect(&self, host: &str) -> Result<(Stream, &str)> {
+        let url = self.url_from(host);
+
+        match Stream::connect(url) {
+            Ok(sock) => Ok((sock, url)),
+            Err(e) if url != self.tcp => {
+                log::debug!("Cannot reach {} ({}), using {}", url, e, self.tcp);
+                Ok((Stream::connect(&self.tcp)?, &self.tcp))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// A connection over TCP or a Unix socket
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    /// Connect to a `host:port` or `unix:` URL
+    pub fn connect(url: &str) -> Result<Stream> {
+        match url.strip_prefix(UNIX_PREFIX) {
+            Some(path) => {
+                let sock = match path.strip_prefix('@') {
+                    Some(name) => UnixStream::connect_addr(
+                        &std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?,
+                    )?,
+                    None => UnixStream::connect(path)?,
+                };
+                Ok(Stream::Unix(sock))
+            }
+            None => Ok(Stream::Tcp(TcpStream::connect(url)?)),
+        }
+    }
+
+    pub fn try_clone(&self) -> Result<Stream> {
+        Ok(match self {
+            Stream::Tcp(s) => Stream::Tcp(s.try_clone()?),
+            Stream::Unix(s) => Stream::Unix(s.try_clone()?),
+        })
+    }
+
+    pub fn read_timeout(&self) -> Result<Option<Duration>> {
+        Ok(match self {
+            Stream::Tcp(s) => s.read_timeout()?,
+            Stream::Unix(s) => s.read_timeout()?,
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        match self {
+            Stream::Tcp(s) => s.set_read_timeout(timeout)?,
+            Stream::Unix(s) => s.set_read_timeout(timeout)?,
+        }
+        Ok(())
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        match self {
+            Stream::Tcp(s) => s.set_write_timeout(timeout)?,
+            Stream::Unix(s) => s.set_write_timeout(timeout)?,
+        }
+        Ok(())
+    }
+
+    /// Close both directions, a blocked peer sees the end of the stream
+    pub fn shutdown(&self) {
+        let _ = match self {
+            Stream::Tcp(s) => s.shutdown(std::net::Shutdown::Both),
+            Stream::Unix(s) => s.shutdown(std::net::Shutdown::Both),
+        };
+    }
+
+    /// Description of the other end for the logs
+    pub fn peer(&self) -> String {
+        match self {
+            Stream::Tcp(s) => s
+                .peer_addr()
+                .map(|a| a.to_string())
+                .unwrap_or("unknown".to_string()),
+            Stream::Unix(_) => "local process".to_string(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            Stream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            Stream::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            Stream::Unix(s) => s.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn endpoint(tcp: &str, unix: Option<&str>) -> Endpoint {
+        Endpoint {
+            host: "node1".to_string(),
+            tcp: tcp.to_string(),
+            unix: unix.map(|u| u.to_string()),
+        }
+    }
+
+    #[test]
+    fn url_selection() {
+        let ep = endpoint("node1:4000", Some("unix:@snapped-1-4000"));
+        assert_eq!(ep.url_from("node1"), "unix:@snapped-1-4000");
+        assert_eq!(ep.url_from("node2"), "node1:4000");
+
+        let ep = endpoint("node1:4000", None);
+        assert_eq!(ep.url_from("node1"), "node1:4000");
+    }
+
+    #[test]
+    fn unix_round_trip() {
+        let (listener, url) = unix_listener(1).unwrap();
+        assert!(url.starts_with("unix:@snapped-"));
+
+        let mut client = Stream::connect(&url).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let mut server = Stream::Unix(server);
+
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        assert_eq!(server.peer(), "local process");
+    }
+
+    #[test]
+    fn unix_falls_back_to_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp = listener.local_addr().unwrap().to_string();
+
+        /* The Unix socket of a server in another network namespace */
+        let ep = endpoint(&tcp, Some("unix:@snapped-not-here"));
+        let (sock, url) = ep.connect("node1").unwrap();
+        assert!(matches!(sock, Stream::Tcp(_)));
+        assert_eq!(url, tcp);
+
+        drop(listener);
+        assert!(ep.connect("node1").is_err());
+    }
+
+    #[test]
+    fn interface_addresses() {
+        assert_eq!(
+            interface_address("10.1.2.3").unwrap(),
+            IpAddr::from_str("10.1.2.3").unwrap()
+        );
+        assert_eq!(
+            interface_address("lo").unwrap(),
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        );
+        assert!(interface_address("nosuchif0").is_err());
+    }
+}
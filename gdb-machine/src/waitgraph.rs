@@ -0,0 +1,137 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// A directed wait-for graph
+///
+/// An edge `a -> b` means that `a` is blocked waiting on `b`. Any cycle in this
+/// graph is a deadlock as none of its members can make progress.
+pub struct WaitForGraph<T> {
+    edges: HashMap<T, Vec<T>>,
+}
+
+impl<T: Eq + Hash + Ord + Clone> WaitForGraph<T> {
+    pub fn new() -> WaitForGraph<T> {
+        WaitForGraph {
+            edges: HashMap::new(),
+        }
+    }
+
+    /// Record that `waiter` is waiting on `holder`
+    pub fn add_edge(&mut self, waiter: T, holder: T) {
+        let targets = self.edges.entry(waiter).or_default();
+
+        if !targets.contains(&holder) {
+            targets.push(holder);
+        }
+    }
+
+    /// Entities `waiter` is waiting on
+    pub fn waits_on(&self, waiter: &T) -> &[T] {
+        self.edges.get(waiter).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    fn _cycles(
+        &self,
+        node: &T,
+        path: &mut Vec<T>,
+        on_path: &mut HashSet<T>,
+        done: &mut HashSet<T>,
+        ret: &mut Vec<Vec<T>>,
+    ) {
+        path.push(node.clone());
+        on_path.insert(node.clone());
+
+        for next in self.waits_on(node) {
+            if on_path.contains(next) {
+                /* Back edge we have a cycle from next to the end of path */
+                if let Some(start) = path.iter().position(|v| v == next) {
+                    ret.push(path[start..].to_vec());
+                }
+            } else if !done.contains(next) {
+                self._cycles(next, path, on_path, done, ret);
+            }
+        }
+
+        on_path.remove(node);
+        done.insert(node.clone());
+        path.pop();
+    }
+
+    /// List the cycles found with a depth-first search
+    ///
+    /// When each waiter waits on a single holder (mutexes, blocking point-to-point
+    /// calls) every cycle is found, otherwise at least one cycle is reported for
+    /// each group of deadlocked entities. Each cycle is rotated to start with its
+    /// smallest member so that the output is stable whatever the traversal order was.
+    pub fn cycles(&self) -> Vec<Vec<T>> {
+        let mut ret = Vec::new();
+        let mut done = HashSet::new();
+
+        let mut roots: Vec<&T> = self.edges.keys().collect();
+        roots.sort();
+
+        for root in roots {
+            if !done.contains(root) {
+                self._cycles(
+                    root,
+                    &mut Vec::new(),
+                    &mut HashSet::new(),
+                    &mut done,
+                    &mut ret,
+                );
+            }
+        }
+
+        for cycle in ret.iter_mut() {
+            if let Some(min) = cycle.iter().enumerate().min_by_key(|(_, v)| *v) {
+                let start = min.0;
+                cycle.rotate_left(start);
+            }
+        }
+
+        ret.sort();
+        ret.dedup();
+
+        ret
+    }
+}
+
+impl<T: Eq + Hash + Ord + Clone> Default for WaitForGraph<T> {
+    fn default() -> Self {
+        WaitForGraph::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(u32, u32)]) -> WaitForGraph<u32> {
+        let mut ret = WaitForGraph::new();
+        for (waiter, holder) in edges {
+            ret.add_edge(*waiter, *holder);
+        }
+        ret
+    }
+
+    #[test]
+    fn cycles() {
+        /* A thread relocking the mutex it holds */
+        assert_eq!(graph(&[(4, 4)]).cycles(), vec![vec![4]]);
+
+        /* Reported once from its smallest member whatever the insertion order */
+        assert_eq!(graph(&[(7, 3), (3, 7)]).cycles(), vec![vec![3, 7]]);
+        assert_eq!(graph(&[(3, 7), (7, 3)]).cycles(), vec![vec![3, 7]]);
+
+        /* Chains and waits on the members of a cycle are not cycles */
+        assert!(graph(&[(1, 2), (2, 3), (4, 3)]).cycles().is_empty());
+        assert_eq!(
+            graph(&[(1, 5), (5, 6), (6, 9), (9, 5), (2, 2)]).cycles(),
+            vec![vec![2], vec![5, 6, 9]]
+        );
+
+        let g = graph(&[(1, 2), (1, 2), (1, 3)]);
+        assert_eq!(g.waits_on(&1), [2, 3]);
+        assert!(g.waits_on(&2).is_empty());
+    }
+}
@@ -0,0 +1,424 @@
+use std::io::{BufRead, Read, Write};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+
+/// Version of the messages exchanged over the tree
+///
+/// Bump it whenever a command or a response changes in an incompatible way.
+pub const PROTOCOL_VERSION: u32 = 13;
+
+/// Environment variable selecting the wire format a node asks its parent for
+pub const WIRE_FORMAT_ENV: &str = "GDBW_WIRE_FORMAT";
+
+/// First byte of a binary frame (never starts a JSON message)
+const FRAME_MAGIC: u8 = 0xB5;
+
+/// The payload of the frame is LZ4 compressed
+const FLAG_LZ4: u8 = 0x1;
+
+/// The sender compresses its large frames and wants compressed answers
+const FLAG_ACCEPT_LZ4: u8 = 0x2;
+
+/// Frames smaller than this are not worth compressing
+const COMPRESS_THRESHOLD: usize = 4096;
+
+/// Refuse messages larger than this
+///
+/// Frames are read as they arrive rather than allocated from their header,
+/// which is read before the peer is authenticated.
+const MAX_FRAME_LEN: u64 = 1 << 30;
+
+/// Encoding of the messages over a tree link
+///
+/// `Json` is the historical `\0` terminated JSON. The binary formats use a
+/// `[magic, flags, length (u64 LE)]` header followed by a bincode payload.
+/// A server tells them apart from the first byte of each message and
+/// answers in the format it was asked in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireFormat {
+    Json,
+    Binary,
+    /// Binary with LZ4 compression of the large frames
+    #[default]
+    Compressed,
+}
+
+impl std::fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WireFormat::Json => write!(f, "json"),
+            WireFormat::Binary => write!(f, "binary"),
+            WireFormat::Compressed => write!(f, "lz4"),
+        }
+    }
+}
+
+impl FromStr for WireFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(WireFormat::Json),
+            "binary" => Ok(WireFormat::Binary),
+            "lz4" => Ok(WireFormat::Compressed),
+            _ => Err(anyhow!("Unknown wire format '{}' (json, binary or lz4)", s)),
+        }
+    }
+}
+
+impl WireFormat {
+    /// Format set in [WIRE_FORMAT_ENV] or the default one
+    pub fn from_env() -> WireFormat {
+        std::env::var(WIRE_FORMAT_ENV)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Closest format the given capabilities allow
+    pub fn restrict(&self, caps: &Capabilities) -> WireFormat {
+        match self {
+            WireFormat::Compressed if caps.binary && caps.compression => WireFormat::Compressed,
+            WireFormat::Compressed | WireFormat::Binary if caps.binary => WireFormat::Binary,
+            _ => WireFormat::Json,
+        }
+    }
+
+    /// Parse a message read by [recv_message]
+    pub fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T> {
+        match self {
+            WireFormat::Json => Ok(serde_json::from_slice(payload)?),
+            _ => Ok(bincode::deserialize(payload)?),
+        }
+    }
+
+    /// Serialize a message as a single buffer ready to be written
+    pub fn encode<T: Serialize>(&self, msg: &T) -> Result<Vec<u8>> {
+        if *self == WireFormat::Json {
+            let mut ret = serde_json::to_vec(msg)?;
+            ret.push(0);
+            return Ok(ret);
+        }
+
+        let mut payload = bincode::serialize(msg)?;
+        let mut flags = 0;
+
+        if *self == WireFormat::Compressed {
+            flags |= FLAG_ACCEPT_LZ4;
+
+            if payload.len() >= COMPRESS_THRESHOLD {
+                payload = lz4_flex::compress_prepend_size(&payload);
+                flags |= FLAG_LZ4;
+            }
+        }
+
+        let mut ret = Vec::with_capacity(payload.len() + 10);
+        ret.push(FRAME_MAGIC);
+        ret.push(flags);
+        ret.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        ret.extend_from_slice(&payload);
+
+        Ok(ret)
+    }
+
+    /// Write a message with a single write
+    pub fn send<T: Serialize>(&self, out: &mut impl Write, msg: &T) -> Result<()> {
+        out.write_all(&self.encode(msg)?)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+fn recv_frame(input: &mut impl BufRead) -> Result<(Vec<u8>, WireFormat)> {
+    let mut header = [0; 10];
+    input.read_exact(&mut header)?;
+
+    let flags = header[1];
+    let len = u64::from_le_bytes(header[2..].try_into()?);
+
+    if len > MAX_FRAME_LEN {
+        return Err(anyhow!("Frame of {} bytes is too large", len));
+    }
+
+    let mut payload = Vec::new();
+    input.take(len).read_to_end(&mut payload)?;
+
+    if (payload.len() as u64) < len {
+        return Err(anyhow!(
+            "Connection closed after {} of {} bytes",
+            payload.len(),
+            len
+        ));
+    }
+
+    if flags & FLAG_LZ4 != 0 {
+        /* The decompressed size prefixes the payload */
+        let size = payload
+            .get(..4)
+            .map(|v| u32::from_le_bytes(v.try_into().unwrap()))
+            .ok_or(anyhow!("Bad compressed frame : missing size"))?;
+
+        if size as u64 > MAX_FRAME_LEN {
+            return Err(anyhow!("Compressed frame of {} bytes is too large", size));
+        }
+
+        payload = lz4_flex::decompress_size_prepended(&payload)
+            .map_err(|e| anyhow!("Bad compressed frame : {}", e))?;
+    }
+
+    let format = if flags & FLAG_ACCEPT_LZ4 != 0 {
+        WireFormat::Compressed
+    } else {
+        WireFormat::Binary
+    };
+
+    Ok((payload, format))
+}
+
+/// Read the next message whatever its format without parsing it
+///
+/// Returns `None` when the other side closed the connection.
+pub fn recv_message(input: &mut impl BufRead) -> Result<Option<(Vec<u8>, WireFormat)>> {
+    let first = match input.fill_buf()?.first() {
+        Some(b) => *b,
+        None => return Ok(None),
+    };
+
+    if first == FRAME_MAGIC {
+        return recv_frame(input).map(Some);
+    }
+
+    let mut data = Vec::new();
+    input.take(MAX_FRAME_LEN + 1).read_until(0, &mut data)?;
+
+    if data.last() == Some(&0) {
+        data.pop();
+    } else if data.len() as u64 > MAX_FRAME_LEN {
+        return Err(anyhow!("Message of more than {} bytes", MAX_FRAME_LEN));
+    }
+
+    if data.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some((data, WireFormat::Json)))
+}
+
+/// Read and parse the next message whatever its format
+///
+/// Returns `None` when the other side closed the connection.
+pub fn recv<T: DeserializeOwned>(input: &mut impl BufRead) -> Result<Option<(T, WireFormat)>> {
+    match recv_message(input)? {
+        Some((payload, format)) => Ok(Some((format.decode(&payload)?, format))),
+        None => Ok(None),
+    }
+}
+
+/// Optional features of a node
+///
+/// Missing fields decode as unsupported so that a [Hello] from another
+/// version still parses and fails on its version instead.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct Capabilities {
+    /// Can report the local variables of the frames
+    pub locals: bool,
+    /// Can list the symbols of its program
+    pub symbols: bool,
+    /// Talks in binary frames
+    pub binary: bool,
+    /// Talks in LZ4 compressed frames
+    pub compression: bool,
+}
+
+impl Capabilities {
+    /// Features of this process given its wire format and debugger backend
+    pub fn local() -> Capabilities {
+        Capabilities::of(
+            WireFormat::from_env(),
+            Backend::from_env().unwrap_or_default(),
+        )
+    }
+
+    /// Features of a process talking `format` and debugging with `backend`
+    pub fn of(format: WireFormat, backend: Backend) -> Capabilities {
+        Capabilities {
+            locals: true,
+            symbols: backend == Backend::Gdb,
+            binary: format != WireFormat::Json,
+            compression: format == WireFormat::Compressed,
+        }
+    }
+
+    /// Features supported by both
+    pub fn common(&self, other: &Capabilities) -> Capabilities {
+        Capabilities {
+            locals: self.locals && other.locals,
+            symbols: self.symbols && other.symbols,
+            binary: self.binary && other.binary,
+            compression: self.compression && other.compression,
+        }
+    }
+}
+
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flags: Vec<&str> = [
+            (self.locals, "locals"),
+            (self.symbols, "symbols"),
+            (self.binary, "binary"),
+            (self.compression, "compression"),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, name)| *name)
+        .collect();
+
+        if flags.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", flags.join(","))
+        }
+    }
+}
+
+/// First message of a connection telling what a node speaks
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hello {
+    pub version: u32,
+    pub capabilities: Capabilities,
+}
+
+impl Hello {
+    pub fn local() -> Hello {
+        Hello {
+            version: PROTOCOL_VERSION,
+            capabilities: Capabilities::local(),
+        }
+    }
+
+    /// Refuse peers speaking another version of the protocol
+    pub fn check(&self) -> Result<()> {
+        if self.version != PROTOCOL_VERSION {
+            return Err(anyhow!(
+                "Protocol version mismatch: peer speaks version {} and this process version {} (all processes must run the same snapped build)",
+                self.version,
+                PROTOCOL_VERSION
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+
+    fn message(len: usize) -> Vec<String> {
+        (0..len).map(|i| format!("frame {}", i % 7)).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        for format in [WireFormat::Json, WireFormat::Binary, WireFormat::Compressed] {
+            for len in [0, 3, 5000] {
+                let msg = message(len);
+
+                /* Two messages back to back as on a connection */
+                let mut data = format.encode(&msg).unwrap();
+                data.extend(format.encode(&Hello::local()).unwrap());
+
+                let mut input = BufReader::new(Cursor::new(data));
+
+                let (got, got_format) = recv::<Vec<String>>(&mut input).unwrap().unwrap();
+                assert_eq!(got, msg, "{}", format);
+                assert_eq!(got_format, format);
+
+                let (hello, _) = recv::<Hello>(&mut input).unwrap().unwrap();
+                hello.check().unwrap();
+
+                assert!(recv_message(&mut input).unwrap().is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn large_frames_compressed() {
+        let small = WireFormat::Compressed.encode(&message(3)).unwrap();
+        let large = WireFormat::Compressed.encode(&message(5000)).unwrap();
+
+        assert_eq!(small[1] & FLAG_LZ4, 0);
+        assert_ne!(large[1] & FLAG_LZ4, 0);
+        assert!(large.len() < WireFormat::Binary.encode(&message(5000)).unwrap().len());
+    }
+
+    #[test]
+    fn capabilities_follow_configuration() {
+        let caps = Capabilities::of(WireFormat::Json, Backend::Lldb);
+
+        assert!(caps.locals);
+        assert!(!caps.symbols && !caps.binary && !caps.compression);
+        assert_eq!(WireFormat::Compressed.restrict(&caps), WireFormat::Json);
+
+        let caps = Capabilities::of(WireFormat::Compressed, Backend::Gdb);
+        assert!(caps.symbols && caps.binary && caps.compression);
+        assert_eq!(
+            WireFormat::Compressed.restrict(&caps),
+            WireFormat::Compressed
+        );
+    }
+
+    #[test]
+    fn hello_from_other_version() {
+        /* Older peer without some of the capabilities */
+        let hello = br#"{"Hello":{"version":0,"capabilities":{"locals":true}}}"#;
+        let mut data = hello.to_vec();
+        data.push(0);
+
+        let (cmd, format) = recv::<crate::protocol::GdbMachineCommand>(&mut Cursor::new(data))
+            .unwrap()
+            .unwrap();
+        assert_eq!(format, WireFormat::Json);
+
+        match cmd {
+            crate::protocol::GdbMachineCommand::Hello(h) => {
+                assert!(h.capabilities.locals && !h.capabilities.binary);
+                assert!(h.check().unwrap_err().to_string().contains("version 0"));
+            }
+            _ => panic!("Expected a hello"),
+        }
+    }
+
+    #[test]
+    fn oversized_frames_refused() {
+        let mut header = vec![FRAME_MAGIC, 0];
+        header.extend_from_slice(&(MAX_FRAME_LEN + 1).to_le_bytes());
+
+        let err = recv_message(&mut Cursor::new(header)).unwrap_err();
+        assert!(err.to_string().contains("too large"));
+
+        /* The header claims more than the connection carries */
+        let mut frame = vec![FRAME_MAGIC, 0];
+        frame.extend_from_slice(&(1u64 << 29).to_le_bytes());
+        frame.extend_from_slice(b"short");
+
+        let err = recv_message(&mut Cursor::new(frame)).unwrap_err();
+        assert!(err.to_string().contains("closed"));
+
+        /* A compressed frame announcing a huge decompressed size */
+        let mut bomb = vec![FRAME_MAGIC, FLAG_LZ4];
+        bomb.extend_from_slice(&8u64.to_le_bytes());
+        bomb.extend_from_slice(&u32::MAX.to_le_bytes());
+        bomb.extend_from_slice(&[0; 4]);
+
+        let err = recv_message(&mut Cursor::new(bomb)).unwrap_err();
+        assert!(err.to_string().contains("too large"));
+    }
+}
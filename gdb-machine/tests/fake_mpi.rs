@@ -0,0 +1,111 @@
+//! MPI wait-for analysis on the `example_c/fake_mpi.c` fixture
+//!
+//! Needs `cc` and `gdb`, the test is skipped when either is missing.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread::sleep;
+use std::time::Duration;
+
+use gdb_machine::debugger::Debugger;
+use gdb_machine::gdbmi::GdbMi;
+use gdb_machine::metadata::MpiReport;
+
+const RANKS: u64 = 3;
+
+fn available(tool: &str) -> bool {
+    Command::new(tool)
+        .arg("--version")
+        .output()
+        .is_ok_and(|o| o.status.success())
+}
+
+fn build_fixture() -> PathBuf {
+    let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("fake_mpi");
+    let src = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("example_c/fake_mpi.c");
+
+    let status = Command::new("cc")
+        .args(["-g", "-O0", "-o"])
+        .arg(&out)
+        .arg(&src)
+        .status()
+        .unwrap();
+    assert!(status.success(), "Failed to build {}", src.display());
+
+    out
+}
+
+/// Run the ranks in `mode` until they block and analyze their MPI calls
+fn analyze(binary: &Path, mode: &str) -> MpiReport {
+    let mut ranks: Vec<GdbMi> = Vec::new();
+
+    std::env::set_var("PMI_SIZE", RANKS.to_string());
+
+    for rank in 0..RANKS {
+        std::env::set_var("PMI_RANK", rank.to_string());
+
+        let mut dbg = GdbMi::run(&[binary.to_str().unwrap(), mode]).unwrap();
+        dbg.set_id(rank);
+        dbg.start().unwrap();
+        ranks.push(dbg);
+    }
+
+    sleep(Duration::from_millis(500));
+
+    let mut states = HashMap::new();
+
+    for (rank, dbg) in ranks.iter_mut().enumerate() {
+        dbg.stop().unwrap();
+
+        for _ in 0..50 {
+            if !dbg.all_running().unwrap() {
+                break;
+            }
+            sleep(Duration::from_millis(100));
+        }
+
+        /* The rank of the leaf is read from its environment */
+        std::env::set_var("PMI_RANK", rank.to_string());
+        states.extend(dbg.mpi_waits().unwrap());
+
+        /* Fails for the ranks which already exited */
+        let _ = dbg.detach();
+    }
+
+    MpiReport::new(&states)
+}
+
+#[test]
+fn fake_mpi_wait_for_graph() {
+    if !available("cc") || !available("gdb") {
+        eprintln!("cc or gdb not found, skipping");
+        return;
+    }
+
+    let binary = build_fixture();
+
+    for mode in ["ring", "wait"] {
+        let report = analyze(&binary, mode);
+
+        assert_eq!(report.blocked, RANKS as usize, "{}", mode);
+        assert_eq!(report.undetermined, 0, "{}", mode);
+        assert_eq!(report.cycles.len(), 1, "{}", mode);
+
+        let mut cycle = report.cycles[0].clone();
+        cycle.sort();
+        assert_eq!(cycle, vec![0, 1, 2], "{}", mode);
+    }
+
+    let report = analyze(&binary, "exit");
+
+    assert!(report.cycles.is_empty());
+    assert_eq!(
+        report
+            .waits_on_exited
+            .iter()
+            .map(|(rank, peer, _)| (*rank, *peer))
+            .collect::<Vec<_>>(),
+        vec![(1, 0), (2, 0)]
+    );
+}
@@ -0,0 +1,203 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+/// Configuration file read by the root, instead of `~/.snappedrc`
+pub const CONFIG_ENV: &str = "GDBW_CONFIG";
+
+/// Keys known in each section, keys can be repeated to give several values
+const SECTIONS: [(&str, &[&str]); 1] = [("gdb", &["path", "args", "init", "commands"])];
+
+/// Settings read from an INI-style file
+///
+/// ```ini
+/// [gdb]
+/// path = /opt/gdb/14/bin/gdb
+/// init = printers.gdb
+/// commands = set sysroot /
+/// ```
+///
+/// Lines starting with `#` or `;` are comments. The command line takes
+/// precedence: its single values replace those of the file and its
+/// repeated values come after them.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    /// Directory relative paths are given from
+    dir: PathBuf,
+    /// (section, key, value) in the order of the file
+    entries: Vec<(String, String, String)>,
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut ret = Config::default();
+        let mut section: Option<&str> = None;
+
+        for (num, line) in s.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let err = |msg: String| anyhow!("Line {} of the configuration : {}", num + 1, msg);
+
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| err(format!("unterminated section '{}'", line)))?
+                    .trim();
+
+                section = Some(
+                    SECTIONS
+                        .iter()
+                        .find(|(s, _)| *s == name)
+                        .map(|(s, _)| *s)
+                        .ok_or_else(|| err(format!("unknown section [{}]", name)))?,
+                );
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| err(format!("expected 'key = value', got '{}'", line)))?;
+            let key = key.trim();
+
+            let section = section.ok_or_else(|| err(format!("'{}' is not in a section", key)))?;
+
+            let keys = SECTIONS
+                .iter()
+                .find(|(s, _)| *s == section)
+                .map(|(_, k)| *k)
+                .unwrap_or_default();
+
+            if !keys.contains(&key) {
+                return Err(err(format!(
+                    "unknown key '{}' in [{}] ({})",
+                    key,
+                    section,
+                    keys.join(", ")
+                )));
+            }
+
+            ret.entries.push((
+                section.to_string(),
+                key.to_string(),
+                value.trim().to_string(),
+            ));
+        }
+
+        Ok(ret)
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Cannot read configuration {} : {}", path.display(), e))?;
+
+        let mut ret: Config = data
+            .parse()
+            .map_err(|e| anyhow!("{} in {}", e, path.display()))?;
+
+        ret.dir = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+
+        Ok(ret)
+    }
+
+    /// Configuration given on the command line, in [CONFIG_ENV] or in
+    /// `~/.snappedrc`, the default file is optional
+    pub fn find(path: Option<&str>) -> Result<Config> {
+        if let Some(path) = path
+            .map(|p| p.to_string())
+            .or(std::env::var(CONFIG_ENV).ok())
+        {
+            return Config::load(Path::new(&path));
+        }
+
+        match std::env::var("HOME").map(|h| PathBuf::from(h).join(".snappedrc")) {
+            Ok(path) if path.is_file() => Config::load(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    /// All the values of a key
+    pub fn values(&self, section: &str, key: &str) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|(s, k, _)| s == section && k == key)
+            .map(|(_, _, v)| v.clone())
+            .collect()
+    }
+
+    /// Last value of a key
+    pub fn value(&self, section: &str, key: &str) -> Option<String> {
+        self.values(section, key).pop()
+    }
+
+    /// Values of a key naming files, relative to the configuration file
+    pub fn paths(&self, section: &str, key: &str) -> Vec<String> {
+        self.values(section, key)
+            .into_iter()
+            .map(|v| self.dir.join(v).to_string_lossy().to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let config: Config = "
+            # Site GDB
+            [gdb]
+            path = /opt/gdb/bin/gdb
+            init = printers.gdb
+            init = /etc/gdb/site.gdb
+            ; applied in order
+            commands = set sysroot /
+            commands = set print pretty on
+            args=-nx
+        "
+        .parse()
+        .unwrap();
+
+        assert_eq!(config.value("gdb", "path").unwrap(), "/opt/gdb/bin/gdb");
+        assert_eq!(config.values("gdb", "args"), ["-nx"]);
+        assert_eq!(
+            config.values("gdb", "commands"),
+            ["set sysroot /", "set print pretty on"]
+        );
+        assert_eq!(config.value("gdb", "missing"), None);
+
+        let config = Config {
+            dir: PathBuf::from("/etc/snapped"),
+            ..config
+        };
+        assert_eq!(
+            config.paths("gdb", "init"),
+            ["/etc/snapped/printers.gdb", "/etc/gdb/site.gdb"]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("[gdb", "unterminated section"),
+            ("[lldb]\npath = lldb-dap", "unknown section"),
+            ("path = gdb", "not in a section"),
+            ("[gdb]\npath gdb", "expected 'key = value'"),
+            ("[gdb]\n\ninits = a.gdb", "Line 3"),
+        ];
+
+        for (text, error) in cases {
+            let err = text.parse::<Config>().unwrap_err().to_string();
+            assert!(err.contains(error), "{:?} gave '{}'", text, err);
+        }
+    }
+}
@@ -21,20 +21,44 @@
 //! Naturally, the same syntax applies to parallel runs using `srun`:
 //!
 //! ̀`snapped -p 1000 srun -n 1000 -p rome ./snapped a.out`
+//!
+//! # Interactive Usage
+//!
+//! With `snapped -I ./a.out` the debugger is kept alive after the first snapshot
+//! and a console allows to `stop`, `cont`, `snap`, `eval`, ... the program.
 
 use anyhow::{anyhow, Result};
 use clap::Parser;
 use colored::*;
+use config::Config;
+use gdb_machine::auth::{AuthMode, AUTH_MODE_ENV};
+use gdb_machine::backend::{Backend, BACKEND_ENV};
 use gdb_machine::debugger::Debugger;
-use gdb_machine::{GdbMachine, RootDebugger};
-use render::Renderer;
+use gdb_machine::gdbmi::{
+    DETACH_ON_FORK_ENV, DISASSEMBLE_ENV, FOLLOW_FORK_ENV, GDB_ARGS_ENV, GDB_COMMANDS_ENV,
+    GDB_INIT_ENV, GDB_PATH_ENV,
+};
+use gdb_machine::health::Unreachable;
+use gdb_machine::locality::{LocalityGroups, LOCALITY_FILE_ENV, RACK_PATTERN_ENV};
+use gdb_machine::pyframes::PYTHON_FRAMES_ENV;
+use gdb_machine::topology::Topology;
+use gdb_machine::transport::{self, ADVERTISE_ADDR_ENV, BIND_INTERFACE_ENV};
+use gdb_machine::wire::{WireFormat, WIRE_FORMAT_ENV};
+use gdb_machine::{GdbMachine, RootDebugger, CHILD_TIMEOUT_ENV, START_TIMEOUT_ENV};
+use names::NameStyle;
+use render::ThreadView;
+use repl::Repl;
 use std::process::{exit, Command, Stdio};
 use std::sync::Mutex;
 use std::time::Duration;
 use std::time::Instant;
 use std::{env, thread};
 
+mod config;
+mod names;
 mod render;
+mod repl;
+mod report;
 
 static WAS_INTERRUPTED: Mutex<u32> = Mutex::new(0);
 
@@ -42,6 +66,20 @@ fn snap_log(out: &str) {
     println!("{} {}", "=SNAPPED=".bold().blue(), out);
 }
 
+/// Carry on when only some subtrees missed a control command
+fn partial(ret: Result<()>) -> Result<()> {
+    match ret {
+        Err(e) => match e.downcast_ref::<Unreachable>() {
+            Some(u) => {
+                snap_log(&format!("{}", u.to_string().yellow()));
+                Ok(())
+            }
+            None => Err(e),
+        },
+        ok => ok,
+    }
+}
+
 fn interrupted() -> bool {
     let ret = if let Ok(st) = WAS_INTERRUPTED.lock() {
         *st > 0
@@ -63,6 +101,13 @@ fn set_interrupted() {
     }
 }
 
+/// Start counting interrupts again, the console calls it for each command
+fn reset_interrupted() {
+    if let Ok(mut l) = WAS_INTERRUPTED.lock() {
+        *l = 0;
+    }
+}
+
 fn timeout(time: u32) {
     thread::spawn(move || {
         let mut current = 0;
@@ -90,6 +135,88 @@ struct Arguments {
     /// Should the program act as a GDB server
     #[arg(short, long)]
     pivot_processes: Option<usize>,
+    /// How threads should be shown in the snapshot tree
+    #[arg(short, long, value_enum, default_value_t = ThreadView::Label)]
+    threads: ThreadView,
+    /// Parts of the C++ names dropped in the tree: full or a list of templates, args, namespaces
+    #[arg(long, default_value_t = NameStyle::default())]
+    simplify_names: NameStyle,
+    /// Print all threads with args and locals for the given ranks (e.g. 3,17)
+    #[arg(short = 'F', long)]
+    full_backtrace: Option<String>,
+    /// Seconds to wait for the processes to join before starting with those which did
+    #[arg(short = 'j', long)]
+    join_timeout: Option<u64>,
+    /// Let processes arriving after the join timeout attach to the running session
+    #[arg(short = 'L', long)]
+    late_join: bool,
+    /// Seconds given to each child of the tree to answer before it is considered dead
+    #[arg(short = 'T', long)]
+    child_timeout: Option<u64>,
+    /// Seconds given to each child of the tree to start its programs
+    #[arg(long)]
+    start_timeout: Option<u64>,
+    /// Shape of the tree: flat, kary[:K], host[:K] or hostlist[:K][:NODELIST]
+    #[arg(long, default_value_t = Topology::default())]
+    topology: Topology,
+    /// Regex giving the rack/switch of a host name (first capture, e.g. 'r(\d+)n')
+    #[arg(long)]
+    rack_pattern: Option<String>,
+    /// File of '<group> <hostlist>' lines giving the rack/switch of the hosts
+    #[arg(long)]
+    locality_file: Option<String>,
+    /// Encoding of the messages over the tree: json, binary or lz4
+    #[arg(long)]
+    wire_format: Option<WireFormat>,
+    /// Authentication of the tree links: token (default) or hmac for every message
+    #[arg(long)]
+    auth: Option<AuthMode>,
+    /// Network interface (e.g. ib0) or address the tree servers listen on
+    #[arg(long)]
+    bind_interface: Option<String>,
+    /// Host name or address given to the processes connecting to this server
+    #[arg(long)]
+    advertise_addr: Option<String>,
+    /// Debugger driving the processes: gdb (default) or lldb (through lldb-dap)
+    #[arg(long)]
+    backend: Option<Backend>,
+    /// Configuration file with a [gdb] section (default ~/.snappedrc)
+    #[arg(long)]
+    config: Option<String>,
+    /// GDB executable run for each process (e.g. the one of a loaded module)
+    #[arg(long)]
+    gdb: Option<String>,
+    /// Option given to GDB (e.g. -nx), can be repeated
+    #[arg(long, allow_hyphen_values = true)]
+    gdb_arg: Vec<String>,
+    /// GDB command file sourced before loading the program, can be repeated
+    #[arg(long)]
+    gdb_init: Vec<String>,
+    /// GDB command run before starting the program (e.g. 'set sysroot /'), can be repeated
+    #[arg(long)]
+    gdb_cmd: Vec<String>,
+    /// Print the tree once the processes joined
+    #[arg(long)]
+    print_tree: bool,
+    /// Print the shared libraries loaded by the processes after the snapshot
+    #[arg(long)]
+    libs: bool,
+    /// Instructions shown around the PC of frames without line info in full backtraces
+    #[arg(long)]
+    disassemble: Option<usize>,
+    /// Show the Python calls instead of the CPython interpreter frames
+    #[arg(long)]
+    python: bool,
+    /// Process debugged after a fork: parent (GDB default) or child
+    #[arg(long, value_parser = ["parent", "child"])]
+    follow_fork: Option<String>,
+    /// Keep debugging both processes after a fork (e.g. workers forked by the application)
+    #[arg(long)]
+    keep_forks: bool,
+    /// Keep the debugger alive after the snapshot with an interactive console
+    /* -i has always been --interrupt-after, reusing it would break existing scripts */
+    #[arg(short = 'I', long)]
+    interactive: bool,
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     command: Option<Vec<String>>,
 }
@@ -102,16 +229,23 @@ fn timer_print(text: &str, start: Instant) {
     ));
 }
 
-fn run_in_snapshot_mode(dbg: &mut impl Debugger) -> Result<()> {
+fn run_in_snapshot_mode(
+    dbg: &mut impl Debugger,
+    threads: ThreadView,
+    names: NameStyle,
+    full_backtrace: &Option<String>,
+    libs: bool,
+    interactive: bool,
+) -> Result<()> {
     let bstart = Instant::now();
-    dbg.start()?;
+    partial(dbg.start())?;
     timer_print("Started processes", bstart);
 
     loop {
         if !dbg.all_running()? || interrupted() {
             /* Stop all  */
             let bstop = Instant::now();
-            dbg.stop()?;
+            partial(dbg.stop())?;
             timer_print("Stopped processes", bstop);
 
             break;
@@ -120,21 +254,39 @@ fn run_in_snapshot_mode(dbg: &mut impl Debugger) -> Result<()> {
     }
 
     let bsnap = Instant::now();
-    let snap = dbg.snapshot()?;
+    let snap = report::collect_snapshot(dbg)?;
     timer_print("Collected backtraces", bsnap);
 
-    let render = Renderer::new(snap);
-    render.print_tree()?;
+    report::print_snapshot(dbg, &snap, threads, names)?;
+
+    if libs {
+        report::print_libraries(dbg)?;
+    }
+
+    if let Some(ranks) = full_backtrace {
+        let rank_ids = report::rank_ids(dbg)?;
+        let count = rank_ids.keys().max().map(|r| *r as usize + 1).unwrap_or(0);
+        let ranks = repl::parse_ranks(ranks, count)?;
+        report::print_full_backtraces(dbg, &rank_ids, &ranks)?;
+    }
+
+    if interactive {
+        Repl::new(dbg, threads, names).run(snap)?;
+    }
 
     Ok(())
 }
 
-fn be_root_server(child_count: usize, cmd: &Option<Vec<String>>) -> Result<RootDebugger> {
-    let (srv, mut rdbg) = GdbMachine::run_as_root()?;
+fn be_root_server(child_count: usize, args: &Arguments) -> Result<RootDebugger> {
+    let (srv, mut rdbg) = GdbMachine::run_as_root(args.topology.clone())?;
 
-    snap_log(&format!("root server is running on {}", srv.url()?));
+    snap_log(&format!(
+        "root server is running on {} ({} tree)",
+        srv.url()?,
+        args.topology
+    ));
 
-    if let Some(command) = cmd {
+    if let Some(command) = &args.command {
         env::set_var("GDBW_ROOT_SERVER", srv.url()?);
 
         let child = Command::new(&command[0])
@@ -145,13 +297,19 @@ fn be_root_server(child_count: usize, cmd: &Option<Vec<String>>) -> Result<RootD
         rdbg.set_child(child);
     }
     let bstart = Instant::now();
-    srv.wait_for_child(child_count)?;
-    timer_print(
-        &format!("Built a tree of {} processes", child_count),
-        bstart,
-    );
+    let joined = srv.wait_for_child(child_count, args.join_timeout.map(Duration::from_secs))?;
+    srv.close_joins(args.late_join);
+    timer_print(&format!("Built a tree of {} processes", joined), bstart);
 
-    snap_log("All processes joined root server");
+    if joined < child_count {
+        report::print_missing_ranks(&mut rdbg, child_count)?;
+    } else {
+        snap_log("All processes joined root server");
+    }
+
+    if args.print_tree {
+        report::print_layout(&mut rdbg)?;
+    }
 
     Ok(rdbg)
 }
@@ -165,21 +323,121 @@ fn main() -> Result<()> {
 
     let args = Arguments::parse();
 
-    if let Some(time) = args.interrupt_after {
-        timeout(time);
+    if let Some(timeout) = args.child_timeout {
+        /* Inherited by the leaves through the launcher */
+        env::set_var(CHILD_TIMEOUT_ENV, timeout.to_string());
     }
 
-    //if let Some(target) = args.target_server {}
+    if let Some(timeout) = args.start_timeout {
+        env::set_var(START_TIMEOUT_ENV, timeout.to_string());
+    }
 
-    /* Get root server either from env */
-    let root_server = if let Some(root) = args.root_server {
-        Some(root)
-    } else if let Ok(env) = env::var("GDBW_ROOT_SERVER") {
-        Some(env)
+    if let Some(format) = args.wire_format {
+        /* Inherited by the leaves which ask their parent for it when joining */
+        env::set_var(WIRE_FORMAT_ENV, format.to_string());
+    }
+
+    if let Some(auth) = args.auth {
+        env::set_var(AUTH_MODE_ENV, auth.to_string());
+    }
+
+    if let Some(iface) = &args.bind_interface {
+        /* Interface names are the same on all the nodes and inherited as well,
+        an address is dropped once the root is bound to it */
+        env::set_var(BIND_INTERFACE_ENV, iface);
+        transport::bind_address()?;
+    }
+
+    if let Some(addr) = &args.advertise_addr {
+        env::set_var(ADVERTISE_ADDR_ENV, addr);
+    }
+
+    if let Some(backend) = args.backend {
+        /* Read by each leaf when wrapping its process */
+        env::set_var(BACKEND_ENV, backend.to_string());
+    }
+
+    /* Get root server either from the command line or from env */
+    let root_server = args
+        .root_server
+        .clone()
+        .or_else(|| env::var("GDBW_ROOT_SERVER").ok());
+
+    /* The leaves inherit the settings the root read */
+    let config = if root_server.is_some() {
+        Config::default()
     } else {
-        None
+        Config::find(args.config.as_deref())?
     };
 
+    if let Some(gdb) = args.gdb.clone().or(config.value("gdb", "path")) {
+        env::set_var(GDB_PATH_ENV, gdb);
+    }
+
+    let gdb_args = [config.values("gdb", "args"), args.gdb_arg.clone()].concat();
+
+    if !gdb_args.is_empty() {
+        env::set_var(GDB_ARGS_ENV, gdb_args.join("\n"));
+    }
+
+    let gdb_init = [config.paths("gdb", "init"), args.gdb_init.clone()].concat();
+
+    if !gdb_init.is_empty() {
+        /* The leaves may not run in the same directory */
+        let files = gdb_init
+            .iter()
+            .map(|f| {
+                std::fs::canonicalize(f)
+                    .map(|p| p.to_string_lossy().to_string())
+                    .map_err(|e| anyhow!("Cannot read GDB init file {} : {}", f, e))
+            })
+            .collect::<Result<Vec<String>>>()?;
+
+        env::set_var(GDB_INIT_ENV, files.join("\n"));
+    }
+
+    let gdb_cmd = [config.values("gdb", "commands"), args.gdb_cmd.clone()].concat();
+
+    if !gdb_cmd.is_empty() {
+        env::set_var(GDB_COMMANDS_ENV, gdb_cmd.join("\n"));
+    }
+
+    if let Some(window) = args.disassemble {
+        /* Read by the leaves when taking the full state of a process */
+        env::set_var(DISASSEMBLE_ENV, window.to_string());
+    }
+
+    if let Some(mode) = &args.follow_fork {
+        env::set_var(FOLLOW_FORK_ENV, mode);
+    }
+
+    if args.keep_forks {
+        env::set_var(DETACH_ON_FORK_ENV, "off");
+    }
+
+    if args.python {
+        env::set_var(PYTHON_FRAMES_ENV, "1");
+    }
+
+    if let Some(pattern) = &args.rack_pattern {
+        env::set_var(RACK_PATTERN_ENV, pattern);
+    }
+
+    if let Some(file) = &args.locality_file {
+        env::set_var(LOCALITY_FILE_ENV, file);
+    }
+
+    if args.pivot_processes.is_some() {
+        /* Report bad groups now rather than when the tree is built */
+        LocalityGroups::from_env()?;
+    }
+
+    if let Some(time) = args.interrupt_after {
+        timeout(time);
+    }
+
+    //if let Some(target) = args.target_server {}
+
     if let Some(root) = root_server {
         if let Some(command) = &args.command {
             GdbMachine::run_as_leaf(root, command)?;
@@ -192,13 +450,27 @@ fn main() -> Result<()> {
 
     if let Some(count_proc) = args.pivot_processes {
         /* Server MODE */
-        let mut srv = be_root_server(count_proc, &args.command)?;
-        run_in_snapshot_mode(&mut srv)?;
+        let mut srv = be_root_server(count_proc, &args)?;
+        run_in_snapshot_mode(
+            &mut srv,
+            args.threads,
+            args.simplify_names,
+            &args.full_backtrace,
+            args.libs,
+            args.interactive,
+        )?;
         srv.kill_child();
     } else if let Some(cmd) = &args.command {
         /* If we are here we are not doing Client / Server we launch locally */
         let mut dbg = GdbMachine::local(cmd)?;
-        run_in_snapshot_mode(&mut dbg)?;
+        run_in_snapshot_mode(
+            &mut dbg,
+            args.threads,
+            args.simplify_names,
+            &args.full_backtrace,
+            args.libs,
+            args.interactive,
+        )?;
         dbg.kill_child();
     }
 
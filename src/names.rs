@@ -0,0 +1,303 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+/// Parts of the demangled C++ names dropped in the snapshot tree
+///
+/// Frames are grouped by the simplified name, the snapshots saved as JSON
+/// keep the names given by GDB.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NameStyle {
+    /// `std::vector<int>::push_back` becomes `std::vector::push_back`
+    pub templates: bool,
+    /// `foo(int, double) const` becomes `foo`
+    pub args: bool,
+    /// `ns::detail::Solver::step` becomes `Solver::step`
+    pub namespaces: bool,
+}
+
+impl FromStr for NameStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut ret = NameStyle::default();
+
+        for part in s.split(',').map(|v| v.trim()) {
+            match part {
+                "full" => {}
+                "templates" => ret.templates = true,
+                "args" => ret.args = true,
+                "namespaces" => ret.namespaces = true,
+                other => {
+                    return Err(anyhow!(
+                        "Unknown name simplification '{}' (full, templates, args or namespaces)",
+                        other
+                    ))
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+}
+
+impl Display for NameStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+
+        if self.templates {
+            parts.push("templates");
+        }
+        if self.args {
+            parts.push("args");
+        }
+        if self.namespaces {
+            parts.push("namespaces");
+        }
+
+        if parts.is_empty() {
+            return write!(f, "full");
+        }
+
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+/// Is the bracket `c` following `before` part of an operator name (e.g. `operator<<`)
+fn is_operator(before: &str, c: char) -> bool {
+    match c {
+        '(' => before.ends_with("operator"),
+        _ => before
+            .trim_end_matches(['<', '>', '=', '-'])
+            .ends_with("operator"),
+    }
+}
+
+/// Drop the `open`..`close` groups at the top level of a name
+///
+/// Groups for which `keep` returns true (given their content) are kept.
+/// Argument lists are only dropped outside of template arguments and
+/// lambdas (`{lambda(int)#1}`).
+fn strip_groups(name: &str, open: char, close: char, keep: impl Fn(&str) -> bool) -> String {
+    let mut ret = String::new();
+    let mut group = String::new();
+    let mut depth = 0;
+    /* Nesting in the other groups */
+    let mut nested = 0;
+
+    for c in name.chars() {
+        if depth == 0 {
+            let angle = open == '(' && !is_operator(&ret, c);
+
+            match c {
+                '{' => nested += 1,
+                '<' if angle => nested += 1,
+                '}' => nested -= 1,
+                '>' if angle => nested -= 1,
+                _ => {}
+            }
+
+            if c == open && nested == 0 && !is_operator(&ret, c) {
+                depth = 1;
+                group.clear();
+            } else {
+                ret.push(c);
+            }
+            continue;
+        }
+
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+
+            if depth == 0 {
+                if keep(&group) {
+                    ret.push(open);
+                    ret.push_str(&group);
+                    ret.push(close);
+                }
+                continue;
+            }
+        }
+
+        group.push(c);
+    }
+
+    ret
+}
+
+/// Split a name on the `::` which are not nested in a group
+fn scopes(name: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut chars = name.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '<' | '(' | '{' | '[' => depth += 1,
+            '>' | ')' | '}' | ']' => depth -= 1,
+            ':' if depth == 0 && chars.peek() == Some(&':') => {
+                chars.next();
+                ret.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+
+        current.push(c);
+    }
+
+    ret.push(current);
+    ret
+}
+
+impl NameStyle {
+    pub fn is_full(&self) -> bool {
+        !(self.templates || self.args || self.namespaces)
+    }
+
+    /// Simplify a demangled function name
+    pub fn simplify(&self, name: &str) -> String {
+        let mut ret = name.to_string();
+
+        if self.args {
+            ret = strip_groups(&ret, '(', ')', |g| g == "anonymous namespace");
+            ret = ret.trim_end_matches(" const").trim_end().to_string();
+        }
+
+        if self.templates {
+            ret = strip_groups(&ret, '<', '>', |_| false)
+                .trim_end()
+                .to_string();
+        }
+
+        if self.namespaces {
+            let scopes = scopes(&ret);
+            /* The innermost scope is kept for the class of methods */
+            ret = scopes[scopes.len().saturating_sub(2)..].join("::");
+        }
+
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(s: &str) -> NameStyle {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn operators() {
+        let cases = [
+            ("operator", '(', true),
+            ("std::ostream::operator", '<', true),
+            ("std::ostream::operator<", '<', true),
+            ("Foo::operator", '>', true),
+            ("Foo::operator-", '>', true),
+            ("Foo::operator()", '(', false),
+            ("std::vector", '<', false),
+            ("foo", '(', false),
+        ];
+
+        for (before, c, expected) in cases {
+            assert_eq!(is_operator(before, c), expected, "{:?} {:?}", before, c);
+        }
+    }
+
+    #[test]
+    fn groups() {
+        let cases = [
+            ("foo(int, double)", '(', ')', "foo"),
+            ("operator()(int)", '(', ')', "operator()"),
+            ("operator<<(std::ostream&, int)", '(', ')', "operator<<"),
+            ("a<b<c>, d>::e<f>", '<', '>', "a::e"),
+            ("operator<< <int>(int)", '<', '>', "operator<< (int)"),
+            ("operator->()", '(', ')', "operator->"),
+            ("f<void (int)>(int)", '(', ')', "f<void (int)>"),
+            ("(anonymous namespace)::f(int)", '(', ')', "::f"),
+        ];
+
+        for (name, open, close, expected) in cases {
+            assert_eq!(strip_groups(name, open, close, |_| false), expected);
+        }
+
+        assert_eq!(
+            strip_groups("(anonymous namespace)::f(int)", '(', ')', |g| {
+                g == "anonymous namespace"
+            }),
+            "(anonymous namespace)::f"
+        );
+    }
+
+    #[test]
+    fn simplify() {
+        let cases = [
+            (
+                "full",
+                "ns::Foo<int>::bar(int) const",
+                "ns::Foo<int>::bar(int) const",
+            ),
+            ("args", "ns::Foo<int>::bar(int) const", "ns::Foo<int>::bar"),
+            (
+                "templates",
+                "ns::Foo<int>::bar(int) const",
+                "ns::Foo::bar(int) const",
+            ),
+            ("namespaces", "ns::detail::Foo::bar(int)", "Foo::bar(int)"),
+            ("args", "Foo::operator()(int)", "Foo::operator()"),
+            (
+                "args,templates",
+                "std::operator<< <std::char_traits<char> >(std::ostream&, char const*)",
+                "std::operator<<",
+            ),
+            (
+                "args,templates",
+                "std::map<int, std::vector<std::pair<int, double> > >::operator[](int const&)",
+                "std::map::operator[]",
+            ),
+            (
+                "args,namespaces",
+                "(anonymous namespace)::Solver::step(double)",
+                "Solver::step",
+            ),
+            (
+                "args,namespaces",
+                "(anonymous namespace)::step(double)",
+                "(anonymous namespace)::step",
+            ),
+            (
+                "args",
+                "main::{lambda(int)#1}::operator()(int) const",
+                "main::{lambda(int)#1}::operator()",
+            ),
+            (
+                "args,templates,namespaces",
+                "ns::run<ns::Job>(ns::Job&)::{lambda()#2}::operator()() const",
+                "{lambda()#2}::operator()",
+            ),
+            (
+                "args",
+                "std::function<void (int)>::operator()(int) const",
+                "std::function<void (int)>::operator()",
+            ),
+        ];
+
+        for (s, name, expected) in cases {
+            assert_eq!(style(s).simplify(name), expected, "{} {}", s, name);
+        }
+    }
+
+    #[test]
+    fn parse_style() {
+        assert!(style("full").is_full());
+        assert_eq!(style("args, namespaces").to_string(), "args,namespaces");
+        assert_eq!(style("full").to_string(), "full");
+        assert!("lambdas".parse::<NameStyle>().is_err());
+    }
+}
@@ -3,7 +3,9 @@ use std::{collections::HashMap, io::Read, path::PathBuf, str::FromStr};
 use anyhow::Result;
 use ascii_tree::{write_tree, Tree};
 use colored::*;
-use gdb_machine::metadata::{BacktraceState, DisplayFrame, DisplayState};
+use gdb_machine::metadata::{BacktraceState, DisplayFrame, DisplayState, ThreadRole};
+
+use crate::names::NameStyle;
 
 fn line_from_src(spath: &Option<String>, line: &Option<u32>) -> Option<String> {
     if let (Some(spath), Some(line)) = (spath, line) {
@@ -48,10 +50,22 @@ fn keep_file(fullpath: &Option<String>) -> Option<String> {
     None
 }
 
+/// How threads are presented in the rendered tree
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ThreadView {
+    /// Threads are merged regardless of their role
+    Merge,
+    /// Threads are merged and leaves are labeled with their roles
+    Label,
+    /// The tree is split by thread role (main, worker, named threads)
+    Split,
+}
+
 #[derive(Debug)]
 pub struct FrameTree {
     pub frame: BacktraceState,
     pub counter: u64,
+    pub roles: HashMap<ThreadRole, u64>,
     pub child: HashMap<u64, FrameTree>,
 }
 
@@ -60,10 +74,38 @@ impl FrameTree {
         FrameTree {
             frame: BacktraceState::root(),
             counter: 0,
+            roles: HashMap::new(),
             child: HashMap::new(),
         }
     }
 
+    fn descriptor_thread(r: &ThreadRole) -> String {
+        let role = match r {
+            ThreadRole::Main => "main thread".to_string().bright_green(),
+            ThreadRole::Worker => "worker threads".to_string().bright_blue(),
+            ThreadRole::Named(n) => format!("thread '{}'", n).bright_purple(),
+        };
+
+        format!("{}", role.bold())
+    }
+
+    fn descriptor_roles(&self) -> Option<String> {
+        if self.roles.is_empty() {
+            return None;
+        }
+
+        let mut roles: Vec<(&ThreadRole, &u64)> = self.roles.iter().collect();
+        roles.sort();
+
+        Some(
+            roles
+                .iter()
+                .map(|(r, c)| format!("{}x {}", c, r))
+                .collect::<Vec<String>>()
+                .join(", "),
+        )
+    }
+
     fn descriptor_frame(f: &DisplayFrame, allow_code: bool) -> String {
         let line = if let (Some(l), true) = (line_from_src(&f.file, &f.line), allow_code) {
             format!(" -> {}", l.bold().truecolor(100, 100, 100))
@@ -71,14 +113,22 @@ impl FrameTree {
             "".normal().to_string()
         };
 
-        let func_str = f.func.to_string().cyan();
+        let func_str = f.location().cyan();
         let loc_str = if let (Some(f), Some(l)) = (keep_file(&f.file), &f.line) {
             format!(" {}:{}", f, l).magenta()
+        } else if let Some(from) = keep_file(&f.from) {
+            format!(" in {}", from).magenta()
         } else {
             "".to_string().normal()
         };
 
-        format!("{}{}{}", func_str, loc_str, line)
+        let inlined = if f.inlined {
+            " [inlined]".truecolor(120, 120, 120).to_string()
+        } else {
+            "".to_string()
+        };
+
+        format!("{}{}{}{}", func_str, inlined, loc_str, line)
     }
 
     fn descriptor_stopstate(s: &DisplayState, _allow_code: bool) -> String {
@@ -86,6 +136,7 @@ impl FrameTree {
             "exited" => "Exited Badly".bright_yellow(),
             "exited-normally" => "Exited Normally".green(),
             "signal-received" => "Received a Signal".red(),
+            "unreachable" => "Processes Unreachable".bright_red(),
             other => other.red(),
         };
 
@@ -126,6 +177,7 @@ impl FrameTree {
         let content = match &self.frame {
             BacktraceState::Frame(f) => FrameTree::descriptor_frame(f, allow_code),
             BacktraceState::State(s) => FrameTree::descriptor_stopstate(s, allow_code),
+            BacktraceState::Thread(r) => FrameTree::descriptor_thread(r),
         };
 
         format!("{} {}", counter_str, content)
@@ -171,6 +223,13 @@ impl FrameTree {
                             sig.truecolor(180, 180, 180).bold()
                         ))
                     }
+                    if let Some(crash) = &s.crash {
+                        content.push(format!(
+                            "{}{}",
+                            " ".repeat(cnt_len),
+                            crash.summary().bright_red()
+                        ))
+                    }
                     if let Some(exit_code) = &s.exit_code {
                         let exit = format!("Exit Code {}", exit_code);
                         content.push(format!(
@@ -180,6 +239,15 @@ impl FrameTree {
                         ))
                     }
                 }
+                BacktraceState::Thread(_) => {}
+            }
+
+            if let Some(roles) = self.descriptor_roles() {
+                content.push(format!(
+                    "{}{}",
+                    " ".repeat(cnt_len),
+                    format!("[{}]", roles).truecolor(120, 120, 200)
+                ))
             }
 
             return Tree::Leaf(content);
@@ -204,13 +272,18 @@ impl From<&BacktraceState> for FrameTree {
         FrameTree {
             frame: value.clone(),
             counter: 0,
+            roles: HashMap::new(),
             child: HashMap::new(),
         }
     }
 }
 
-impl From<&HashMap<u64, (u64, Vec<BacktraceState>)>> for FrameTree {
-    fn from(components: &HashMap<u64, (u64, Vec<BacktraceState>)>) -> Self {
+impl FrameTree {
+    fn build(
+        components: &HashMap<u64, (u64, Vec<BacktraceState>)>,
+        threads: ThreadView,
+        names: NameStyle,
+    ) -> Self {
         /* HASH to (contributors, Frames) */
 
         let mut root = FrameTree::default();
@@ -221,12 +294,35 @@ impl From<&HashMap<u64, (u64, Vec<BacktraceState>)>> for FrameTree {
         let mut current_node = &mut root;
 
         for (counter, backtraces) in components.values() {
+            let role = backtraces.iter().find_map(|v| match v {
+                BacktraceState::Thread(r) => Some(r.clone()),
+                _ => None,
+            });
+
             for frame in backtraces.iter().rev() {
+                if matches!(frame, BacktraceState::Thread(_)) && threads != ThreadView::Split {
+                    continue;
+                }
+
+                /* Frames are grouped by their simplified name */
+                let frame = match frame {
+                    BacktraceState::Frame(f) if !names.is_full() => {
+                        let mut f = f.clone();
+                        f.func = names.simplify(&f.func);
+                        BacktraceState::Frame(f)
+                    }
+                    other => other.clone(),
+                };
+
                 current_node = current_node
                     .child
                     .entry(frame.get_hash())
-                    .or_insert(FrameTree::from(frame));
+                    .or_insert(FrameTree::from(&frame));
                 current_node.counter += counter;
+
+                if let (Some(role), ThreadView::Label) = (&role, threads) {
+                    *current_node.roles.entry(role.clone()).or_insert(0) += counter;
+                }
             }
             /* Return to root */
             current_node = &mut root;
@@ -238,15 +334,31 @@ impl From<&HashMap<u64, (u64, Vec<BacktraceState>)>> for FrameTree {
 
 pub struct Renderer {
     components: HashMap<u64, (u64, Vec<BacktraceState>)>,
+    threads: ThreadView,
+    names: NameStyle,
 }
 
 impl Renderer {
     pub fn new(components: HashMap<u64, (u64, Vec<BacktraceState>)>) -> Renderer {
-        Renderer { components }
+        Renderer {
+            components,
+            threads: ThreadView::Label,
+            names: NameStyle::default(),
+        }
+    }
+
+    pub fn with_threads(mut self, threads: ThreadView) -> Renderer {
+        self.threads = threads;
+        self
+    }
+
+    pub fn with_names(mut self, names: NameStyle) -> Renderer {
+        self.names = names;
+        self
     }
 
     fn astree(&self) -> FrameTree {
-        FrameTree::from(&self.components)
+        FrameTree::build(&self.components, self.threads, self.names)
     }
 
     pub fn print_tree(&self) -> Result<()> {
@@ -261,3 +373,63 @@ impl Renderer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(func: &str, offset: Option<u64>) -> BacktraceState {
+        BacktraceState::Frame(DisplayFrame {
+            func: func.to_string(),
+            file: None,
+            line: None,
+            offset,
+            from: Some("/opt/vendor/lib/libsolver.so".to_string()),
+            inlined: false,
+        })
+    }
+
+    /// Locations of the children of a node with their counts
+    fn children(node: &FrameTree) -> Vec<(String, u64)> {
+        let mut ret: Vec<(String, u64)> = node
+            .child
+            .values()
+            .map(|c| match &c.frame {
+                BacktraceState::Frame(f) => (f.location(), c.counter),
+                _ => (String::new(), c.counter),
+            })
+            .collect();
+        ret.sort();
+        ret
+    }
+
+    #[test]
+    fn stripped_call_sites() {
+        /* Innermost frame first, two call sites of the same stripped function */
+        let components = HashMap::from([
+            (
+                1,
+                (3, vec![frame("poll", None), frame("progress", Some(0x1a))]),
+            ),
+            (
+                2,
+                (2, vec![frame("poll", None), frame("progress", Some(0x1a))]),
+            ),
+            (
+                3,
+                (4, vec![frame("wait", None), frame("progress", Some(0x40))]),
+            ),
+        ]);
+
+        let tree = FrameTree::build(&components, ThreadView::Merge, NameStyle::default());
+
+        assert_eq!(tree.counter, 9);
+        assert_eq!(
+            children(&tree),
+            [
+                ("progress+0x1a".to_string(), 5),
+                ("progress+0x40".to_string(), 4)
+            ]
+        );
+    }
+}
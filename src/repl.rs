@@ -0,0 +1,434 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use colored::*;
+use gdb_machine::debugger::{Debugger, Targets};
+use gdb_machine::metadata::BacktraceState;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::names::NameStyle;
+use crate::render::ThreadView;
+use crate::{report, reset_interrupted, snap_log};
+
+const COMMANDS: [(&str, &str); 13] = [
+    ("stop", "Stop the focused processes"),
+    ("cont", "Continue the focused processes"),
+    ("snap", "Snapshot the focused processes"),
+    (
+        "eval",
+        "eval <expr> : Evaluate an expression on the focused processes",
+    ),
+    (
+        "bt",
+        "bt <ranks> : Show all threads with args and locals (e.g. 3,17)",
+    ),
+    (
+        "focus",
+        "focus <ranks|all> : Restrict all commands to ranks (e.g. 0,4-7)",
+    ),
+    ("save", "save [file] : Save the last snapshot as JSON"),
+    ("health", "Show the liveness of the tree"),
+    ("tree", "Show how the processes are laid out in the tree"),
+    (
+        "bins",
+        "Show the binaries run by the processes and their ranks",
+    ),
+    (
+        "libs",
+        "Show the shared libraries and the ranks loading other builds",
+    ),
+    ("help", "Show this help"),
+    ("quit", "Leave the console"),
+];
+
+struct ReplHelper {
+    functions: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+
+        let start = line
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+            .map(|v| v + 1)
+            .unwrap_or(0);
+        let word = &line[start..];
+
+        let candidates: Vec<&str> = if start == 0 {
+            COMMANDS.iter().map(|(c, _)| *c).collect()
+        } else {
+            self.functions.iter().map(|v| v.as_str()).collect()
+        };
+
+        let ret = candidates
+            .into_iter()
+            .filter(|c| c.starts_with(word))
+            .map(|c| Pair {
+                display: c.to_string(),
+                replacement: c.to_string(),
+            })
+            .collect();
+
+        Ok((start, ret))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Parse a rank list such as `0,2,4-7`
+///
+/// Ranges cannot go past `count`, the number of ranks known.
+pub fn parse_ranks(list: &str, count: usize) -> Result<HashSet<u32>> {
+    let mut ret = HashSet::new();
+
+    for r in list.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+        if let Some((start, end)) = r.split_once('-') {
+            let start = start.trim().parse::<u32>()?;
+            let end = end.trim().parse::<u32>()?;
+
+            if start > end {
+                return Err(anyhow!("Reversed rank range {}", r));
+            }
+
+            if end as usize >= count {
+                return Err(anyhow!(
+                    "Rank range {} goes past the {} known ranks",
+                    r,
+                    count
+                ));
+            }
+
+            ret.extend(start..=end);
+        } else {
+            ret.insert(r.parse::<u32>()?);
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Interactive console on top of a debugger
+///
+/// The debugger is kept alive between commands so that a hung job can be
+/// inspected iteratively instead of being relaunched for each question.
+pub struct Repl<'a, D: Debugger> {
+    dbg: &'a mut D,
+    threads: ThreadView,
+    names: NameStyle,
+    /// Debugger id for each rank
+    ranks: HashMap<u32, u64>,
+    /// Currently focused ranks (None is all ranks), commands are only routed to them
+    focus: Option<HashSet<u32>>,
+    last_snapshot: Option<HashMap<u64, (u64, Vec<BacktraceState>)>>,
+}
+
+impl<'a, D: Debugger> Repl<'a, D> {
+    pub fn new(dbg: &'a mut D, threads: ThreadView, names: NameStyle) -> Repl<'a, D> {
+        Repl {
+            dbg,
+            threads,
+            names,
+            ranks: HashMap::new(),
+            focus: None,
+            last_snapshot: None,
+        }
+    }
+
+    /// Ranks are numbered from 0 up to the highest known one
+    fn rank_count(&self) -> usize {
+        self.ranks
+            .keys()
+            .max()
+            .map(|r| *r as usize + 1)
+            .unwrap_or(0)
+    }
+
+    fn load_ranks(&mut self) -> Result<()> {
+        self.ranks = report::rank_ids(self.dbg)?;
+        Ok(())
+    }
+
+    fn rank_ids(&self, ranks: &HashSet<u32>) -> Result<Vec<u64>> {
+        ranks
+            .iter()
+            .map(|r| {
+                self.ranks
+                    .get(r)
+                    .cloned()
+                    .ok_or(anyhow!("No such rank {}", r))
+            })
+            .collect()
+    }
+
+    fn id_ranks(&self) -> HashMap<u64, u32> {
+        self.ranks.iter().map(|(r, id)| (*id, *r)).collect()
+    }
+
+    fn function_names(&mut self) -> Vec<String> {
+        match self.dbg.symbols() {
+            Ok(table) => {
+                let mut names: Vec<String> = table
+                    .symbols()
+                    .map(|s| s.name.clone())
+                    .collect::<HashSet<String>>()
+                    .into_iter()
+                    .collect();
+                names.sort();
+                names
+            }
+            Err(e) => {
+                log::debug!("No symbols for completion : {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn snap(&mut self) -> Result<()> {
+        /* Late joiners may have attached since the last snapshot */
+        if self.focus.is_none() {
+            self.load_ranks()?;
+        }
+
+        let snap = report::collect_snapshot(self.dbg)?;
+
+        report::print_snapshot(self.dbg, &snap, self.threads, self.names)?;
+
+        self.last_snapshot = Some(snap);
+
+        Ok(())
+    }
+
+    fn eval(&mut self, expr: &str) -> Result<()> {
+        if expr.is_empty() {
+            return Err(anyhow!("Usage: eval <expr>"));
+        }
+
+        let values = self.dbg.eval(expr)?;
+        let id_ranks = self.id_ranks();
+
+        /* Group ranks by value */
+        let mut by_value: HashMap<String, Vec<u32>> = HashMap::new();
+
+        for (id, v) in values {
+            if let Some(rank) = id_ranks.get(&id) {
+                by_value.entry(v).or_default().push(*rank);
+            }
+        }
+
+        let mut by_value: Vec<(String, Vec<u32>)> = by_value.into_iter().collect();
+        by_value.sort_by_key(|v| std::cmp::Reverse(v.1.len()));
+
+        for (value, mut ranks) in by_value {
+            ranks.sort();
+            let ranks: Vec<String> = ranks.iter().map(|v| v.to_string()).collect();
+            println!(
+                "{} {} {}",
+                format!("[{}]", ranks.join(",")).magenta(),
+                "=".bold(),
+                value.cyan()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn bt(&mut self, ranks: &str) -> Result<()> {
+        if ranks.is_empty() {
+            return Err(anyhow!("Usage: bt <ranks>"));
+        }
+
+        let ranks = parse_ranks(ranks, self.rank_count())?;
+
+        report::print_full_backtraces(self.dbg, &self.ranks, &ranks)
+    }
+
+    fn apply_focus(&mut self) -> Result<()> {
+        let targets = match &self.focus {
+            Some(ranks) => Some(Targets::Ids(self.rank_ids(ranks)?)),
+            None => None,
+        };
+
+        self.dbg.set_targets(targets)
+    }
+
+    fn focus(&mut self, ranks: &str) -> Result<()> {
+        match ranks.trim() {
+            "" => {}
+            "all" => self.focus = None,
+            list => {
+                let ranks = parse_ranks(list, self.rank_count())?;
+                /* Make sure all ranks exist */
+                self.rank_ids(&ranks)?;
+                self.focus = Some(ranks);
+            }
+        }
+
+        self.apply_focus()?;
+
+        match &self.focus {
+            Some(f) => {
+                let mut f: Vec<&u32> = f.iter().collect();
+                f.sort();
+                snap_log(&format!("Focus on ranks {:?}", f));
+            }
+            None => snap_log(&format!("Focus on all {} ranks", self.ranks.len())),
+        }
+
+        Ok(())
+    }
+
+    fn save(&mut self, path: &str) -> Result<()> {
+        let path = if path.is_empty() {
+            "snapped.json"
+        } else {
+            path
+        };
+
+        if let Some(snap) = &self.last_snapshot {
+            let f = File::create(path)?;
+            serde_json::to_writer_pretty(f, snap)?;
+            snap_log(&format!("Saved snapshot to {}", path));
+            return Ok(());
+        }
+
+        Err(anyhow!("No snapshot to save, run snap first"))
+    }
+
+    fn help() {
+        for (cmd, desc) in COMMANDS.iter() {
+            println!("{:>6} {}", cmd.bold(), desc);
+        }
+    }
+
+    /// Run a single console command returns false when leaving
+    fn command(&mut self, line: &str) -> Result<bool> {
+        let (cmd, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim();
+
+        match cmd {
+            "stop" => self.dbg.stop()?,
+            "cont" => self.dbg.cont()?,
+            "snap" => self.snap()?,
+            "eval" => self.eval(args)?,
+            "bt" => self.bt(args)?,
+            "focus" => self.focus(args)?,
+            "save" => self.save(args)?,
+            "health" => report::print_health(&self.dbg.health()?),
+            "tree" => report::print_layout(self.dbg)?,
+            "bins" => report::print_binaries(self.dbg)?,
+            "libs" => report::print_libraries(self.dbg)?,
+            "help" => Repl::<D>::help(),
+            "quit" | "exit" => return Ok(false),
+            other => return Err(anyhow!("Unknown command '{}' (see help)", other)),
+        }
+
+        Ok(true)
+    }
+
+    fn history_file() -> Option<PathBuf> {
+        std::env::var("HOME")
+            .ok()
+            .map(|h| PathBuf::from(h).join(".snapped_history"))
+    }
+
+    pub fn run(&mut self, last_snapshot: HashMap<u64, (u64, Vec<BacktraceState>)>) -> Result<()> {
+        self.last_snapshot = Some(last_snapshot);
+        self.load_ranks()?;
+
+        let mut editor: Editor<ReplHelper, rustyline::history::DefaultHistory> = Editor::new()?;
+        editor.set_helper(Some(ReplHelper {
+            functions: self.function_names(),
+        }));
+
+        let history = Repl::<D>::history_file();
+
+        if let Some(h) = &history {
+            let _ = editor.load_history(h);
+        }
+
+        snap_log("Interactive console, type 'help' for the list of commands");
+
+        loop {
+            let line = match editor.readline(&format!("{} ", "(snapped)".bold().blue())) {
+                Ok(l) => l,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(anyhow!(e)),
+            };
+
+            let line = line.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let _ = editor.add_history_entry(line);
+
+            /* Interrupting a command must not count toward leaving snapped */
+            reset_interrupted();
+
+            match self.command(line) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => snap_log(&format!("{} {}", "Error:".red(), e)),
+            }
+        }
+
+        if let Some(h) = &history {
+            let _ = editor.save_history(h);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_lists() {
+        let ranks = parse_ranks("0, 2,4-7,,5", 8).unwrap();
+        let mut ranks: Vec<u32> = ranks.into_iter().collect();
+        ranks.sort();
+        assert_eq!(ranks, [0, 2, 4, 5, 6, 7]);
+
+        assert_eq!(parse_ranks("3-3", 4).unwrap().len(), 1);
+        /* Single ranks are checked against the processes */
+        assert!(parse_ranks("12", 4).is_ok());
+
+        for (list, error) in [
+            ("7-4", "Reversed"),
+            ("0-4000000000", "known ranks"),
+            ("0-8", "8 known ranks"),
+            ("a", "invalid digit"),
+            ("1-", "empty string"),
+        ] {
+            let err = parse_ranks(list, 8).unwrap_err().to_string();
+            assert!(err.contains(error), "{:?} gave '{}'", list, err);
+        }
+    }
+}
@@ -0,0 +1,856 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use ascii_tree::{write_tree, Tree};
+use colored::*;
+use gdb_machine::debugger::Debugger;
+use gdb_machine::health::{Liveness, SubtreeHealth};
+use gdb_machine::metadata::{
+    BacktraceState, BinarySymbols, CrashInfo, DebugFrame, DisplayState, Inferior, LibraryReport,
+    LockReport, MpiRankState, MpiReport, ProcessInfo, ProgramSnapshot, RunState, SnapshotPart,
+};
+use gdb_machine::topology::TreeLayout;
+
+use crate::names::NameStyle;
+use crate::render::{Renderer, ThreadView};
+use crate::{snap_log, timer_print};
+
+/// Render a snapshot followed by the deadlock analyses it calls for
+pub fn print_snapshot(
+    dbg: &mut impl Debugger,
+    snap: &HashMap<u64, (u64, Vec<BacktraceState>)>,
+    threads: ThreadView,
+    names: NameStyle,
+) -> Result<()> {
+    let render = Renderer::new(snap.clone())
+        .with_threads(threads)
+        .with_names(names);
+    render.print_tree()?;
+
+    print_crashes(snap);
+
+    if has_unreachable(snap) {
+        print_unreachable(dbg)?;
+    }
+
+    let health = dbg.health()?;
+
+    if !health.iter().all(|h| h.healthy()) {
+        print_health(&health);
+    }
+
+    if has_mutex_waits(snap) {
+        let block = Instant::now();
+        let locks = dbg.mutex_deadlocks()?;
+        timer_print("Analyzed mutex waits", block);
+        print_lock_report(&locks);
+    }
+
+    if has_mpi_waits(snap) {
+        let bmpi = Instant::now();
+        let waits = dbg.mpi_waits()?;
+        timer_print("Analyzed MPI waits", bmpi);
+        print_mpi_report(&waits);
+    }
+
+    Ok(())
+}
+
+/// Registers shown on each line of a crash report
+const REGISTERS_PER_LINE: usize = 4;
+
+/// Print the fault and the registers of a faulting thread
+fn print_crash(crash: &CrashInfo) {
+    println!("{}", crash.summary().bright_red());
+
+    for regs in crash.registers.chunks(REGISTERS_PER_LINE) {
+        let line: Vec<String> = regs
+            .iter()
+            .map(|(name, value)| format!("{:>6} {:<18}", name.bold(), value))
+            .collect();
+        println!("{}", line.join(" "));
+    }
+}
+
+/// Print the details of each distinct crash found in a snapshot
+fn print_crashes(snap: &HashMap<u64, (u64, Vec<BacktraceState>)>) {
+    for (cnt, comp) in snap.values() {
+        for st in comp {
+            if let BacktraceState::State(DisplayState {
+                crash: Some(crash), ..
+            }) = st
+            {
+                snap_log(&format!(
+                    "{} in {} thread(s)",
+                    crash.signal.red().bold(),
+                    cnt
+                ));
+                print_crash(crash);
+            }
+        }
+    }
+}
+
+/// Time between two refreshes of the progress of a snapshot
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Stacks shown while a snapshot is being collected
+const PROGRESS_STACKS: usize = 5;
+
+/// Collect a snapshot, showing the dominant stacks while the processes report
+pub fn collect_snapshot(
+    dbg: &mut impl Debugger,
+) -> Result<HashMap<u64, (u64, Vec<BacktraceState>)>> {
+    let total = dbg.count()?;
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|s| {
+        let collect = s.spawn(move || dbg.snapshot_stream(&tx));
+
+        let mut snap = SnapshotPart::default();
+        let mut progress = Progress::new(total);
+        let mut last = Instant::now();
+
+        for part in rx {
+            snap.merge(part);
+
+            if last.elapsed() >= PROGRESS_INTERVAL && snap.reported < total {
+                progress.show(&snap)?;
+                last = Instant::now();
+            }
+        }
+
+        progress.clear()?;
+
+        collect
+            .join()
+            .map_err(|_| anyhow!("Snapshot collection panicked"))??;
+
+        Ok(snap.components)
+    })
+}
+
+/// Progress of a snapshot, redrawn in place on a terminal
+struct Progress {
+    total: u64,
+    live: bool,
+    /* Lines drawn by the last refresh */
+    lines: usize,
+}
+
+impl Progress {
+    fn new(total: u64) -> Progress {
+        Progress {
+            total,
+            live: std::io::stdout().is_terminal(),
+            lines: 0,
+        }
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        if self.lines > 0 {
+            /* Move up and erase what the last refresh drew */
+            print!("\x1b[{}A\x1b[J", self.lines);
+            std::io::stdout().flush()?;
+            self.lines = 0;
+        }
+
+        Ok(())
+    }
+
+    fn show(&mut self, snap: &SnapshotPart) -> Result<()> {
+        self.clear()?;
+
+        snap_log(&format!(
+            "{}/{} processes reported",
+            snap.reported, self.total
+        ));
+
+        if !self.live {
+            return Ok(());
+        }
+
+        let stacks = snap.dominant(PROGRESS_STACKS);
+
+        for (count, comp) in stacks.iter() {
+            /* Innermost frame (or stop reason) of the stack */
+            let top = comp
+                .iter()
+                .find(|v| !matches!(v, BacktraceState::Thread(_)))
+                .map(|v| v.print())
+                .unwrap_or_default();
+            println!("  {:>8} {}", count.to_string().bold(), top);
+        }
+
+        self.lines = 1 + stacks.len();
+
+        Ok(())
+    }
+}
+
+/// Map ranks to debugger ids
+pub fn rank_ids(dbg: &mut impl Debugger) -> Result<HashMap<u32, u64>> {
+    let procs = dbg.processes()?;
+
+    Ok(number_ranks(
+        procs.iter().map(|(id, p)| (*id, p.mpirank)).collect(),
+    ))
+}
+
+/// Processes without a known MPI rank are numbered by increasing debugger id
+/// after the highest MPI rank, so that they never take the rank of another one.
+/// The same goes for the processes repeating a rank (e.g. several jobs).
+fn number_ranks(mut procs: Vec<(u64, Option<u32>)>) -> HashMap<u32, u64> {
+    procs.sort();
+
+    let mut ret = HashMap::new();
+    let mut unranked = Vec::new();
+
+    for (id, rank) in procs {
+        match rank {
+            Some(rank) if !ret.contains_key(&rank) => {
+                ret.insert(rank, id);
+            }
+            Some(rank) => {
+                log::warn!("Process {} repeats MPI rank {}", id, rank);
+                unranked.push(id);
+            }
+            None => unranked.push(id),
+        }
+    }
+
+    let first = ret.keys().max().map(|r| r + 1).unwrap_or(0);
+
+    ret.extend((first..).zip(unranked));
+
+    ret
+}
+
+fn frame_line(f: &DebugFrame) -> String {
+    let args = f
+        .args
+        .as_ref()
+        .map(|a| {
+            a.iter()
+                .map(|(n, v)| format!("{}={}", n, v))
+                .collect::<Vec<String>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+
+    let func = match f.offset {
+        Some(off) => format!("{}+{:#x}", f.func, off),
+        None => f.func.to_string(),
+    };
+
+    let mut ret = format!(
+        "#{:<3}{} in {} ({})",
+        f.level,
+        f.addr.blue(),
+        func.yellow(),
+        args
+    );
+
+    if f.inlined {
+        ret += " [inlined]";
+    }
+
+    if let (Some(file), Some(line)) = (&f.file, &f.line) {
+        ret += &format!(" at {}:{}", file.green(), line);
+    } else if let Some(from) = &f.from {
+        ret += &format!(" from {}", from.green());
+    }
+
+    ret
+}
+
+/// Print the full state of a process as `thread apply all bt full` does
+pub fn print_full_backtrace(rank: u32, snap: &ProgramSnapshot) {
+    snap_log(&format!(
+        "Full backtrace of rank {}",
+        rank.to_string().bold()
+    ));
+
+    if let Some(stop) = &snap.stop_state {
+        if let Some(sig) = &stop.signal_name {
+            println!("Program received signal {}", sig.red().bold());
+
+            if let Some(crash) = &snap.crash {
+                print_crash(crash);
+            }
+        } else if let Some(code) = stop.exit_code {
+            println!("Program exited with code {}", code);
+        }
+    }
+
+    if snap.inferiors.len() > 1 {
+        let mut inferiors: Vec<_> = snap.inferiors.values().collect();
+        inferiors.sort_by_key(|inf| Inferior::number(&inf.group));
+
+        for inf in inferiors {
+            let pid = inf.pid.map(|v| v.to_string()).unwrap_or("-".to_string());
+
+            match (inf.exited, inf.exit_code) {
+                (true, Some(code)) => println!(
+                    "Inferior {} (process {}) exited with code {}",
+                    inf.group, pid, code
+                ),
+                (true, None) => println!("Inferior {} (process {}) exited", inf.group, pid),
+                _ => println!(
+                    "Inferior {} (process {}) with {} threads",
+                    inf.group,
+                    pid,
+                    inf.threads.len()
+                ),
+            }
+        }
+    }
+
+    let mut threads: Vec<&u32> = snap.state.keys().collect();
+    /* GDB walks threads from the last one */
+    threads.sort_by(|a, b| b.cmp(a));
+
+    for th in threads {
+        let desc = match snap.threads.get(th) {
+            Some(info) => match &info.name {
+                Some(name) => format!("{} \"{}\"", info.target_id, name),
+                None => info.target_id.clone(),
+            },
+            None => format!("Thread {}", th),
+        };
+
+        let desc = match snap.inferior_of(*th) {
+            Some(inf) => format!(
+                "{}, inferior {} process {}",
+                desc,
+                inf.group,
+                inf.pid.map(|v| v.to_string()).unwrap_or_default()
+            ),
+            None => desc,
+        };
+
+        println!("\n{} ({}):", format!("Thread {}", th).bold(), desc);
+
+        for f in &snap.state[th] {
+            println!("{}", frame_line(f));
+
+            for insn in f.disassembly.iter().flatten() {
+                let marker = if insn.address == f.addr { "=>" } else { "  " };
+                println!("     {} {}", marker.bold(), insn);
+            }
+
+            match &f.locals {
+                Some(locals) => {
+                    for (name, value) in locals {
+                        println!("        {} = {}", name, value);
+                    }
+                }
+                None => println!("        No locals."),
+            }
+        }
+    }
+
+    println!();
+}
+
+/// Format a rank list compacting consecutive ranks (e.g. `0-3,7`)
+pub fn format_ranks(ranks: &[u32]) -> String {
+    let mut ranks = ranks.to_vec();
+    ranks.sort();
+    ranks.dedup();
+
+    let mut ret: Vec<String> = Vec::new();
+    let mut i = 0;
+
+    while i < ranks.len() {
+        let start = ranks[i];
+        while i + 1 < ranks.len() && ranks[i + 1] == ranks[i] + 1 {
+            i += 1;
+        }
+
+        if start == ranks[i] {
+            ret.push(start.to_string());
+        } else {
+            ret.push(format!("{}-{}", start, ranks[i]));
+        }
+
+        i += 1;
+    }
+
+    ret.join(",")
+}
+
+/// Report the processes which never joined the tree
+///
+/// Missing ranks can only be listed when the launcher provided them.
+pub fn print_missing_ranks(dbg: &mut impl Debugger, expected: usize) -> Result<()> {
+    let procs = dbg.processes()?;
+    let missing = expected.saturating_sub(procs.len());
+
+    if let Some(absent) = absent_ranks(&procs, expected) {
+        snap_log(&format!(
+            "{} {} processes did not join (ranks {})",
+            "Missing:".red().bold(),
+            missing,
+            format_ranks(&absent)
+        ));
+    } else {
+        snap_log(&format!(
+            "{} {} processes did not join",
+            "Missing:".red().bold(),
+            missing
+        ));
+    }
+
+    Ok(())
+}
+
+/// Ranks below `expected` no process has, None when some processes have no rank
+fn absent_ranks(procs: &HashMap<u64, ProcessInfo>, expected: usize) -> Option<Vec<u32>> {
+    let ranks: HashSet<u32> = procs.values().filter_map(|p| p.mpirank).collect();
+
+    if ranks.len() != procs.len() {
+        return None;
+    }
+
+    Some(
+        (0..expected as u32)
+            .filter(|r| !ranks.contains(r))
+            .collect(),
+    )
+}
+
+/// Fetch and print the full state of each of the given ranks
+pub fn print_full_backtraces(
+    dbg: &mut impl Debugger,
+    rank_ids: &HashMap<u32, u64>,
+    ranks: &HashSet<u32>,
+) -> Result<()> {
+    let mut ranks: Vec<&u32> = ranks.iter().collect();
+    ranks.sort();
+
+    if !dbg.capabilities()?.locals {
+        snap_log("Some processes cannot report local variables, they are not shown");
+    }
+
+    for rank in ranks {
+        let id = rank_ids.get(rank).ok_or(anyhow!("No such rank {}", rank))?;
+
+        match dbg.process_snapshot(*id)? {
+            Some(snap) => print_full_backtrace(*rank, &snap),
+            None => return Err(anyhow!("Rank {} did not return its state", rank)),
+        }
+    }
+
+    Ok(())
+}
+
+fn unhealthy_lines(h: &SubtreeHealth, depth: usize, out: &mut Vec<String>) {
+    if h.healthy() {
+        return;
+    }
+
+    let liveness = match h.liveness {
+        Liveness::Live => h.liveness.to_string().green(),
+        Liveness::Suspect => h.liveness.to_string().yellow(),
+        Liveness::Dead => h.liveness.to_string().red(),
+    };
+
+    let missed = if h.missed > 0 {
+        format!(" ({} missed heartbeats)", h.missed)
+    } else {
+        "".to_string()
+    };
+
+    out.push(format!(
+        "{}subtree {} is {}{}",
+        "  ".repeat(depth),
+        h.id,
+        liveness.bold(),
+        missed
+    ));
+
+    for c in &h.children {
+        unhealthy_lines(c, depth + 1, out);
+    }
+}
+
+/// Print the liveness of the tree listing the subtrees which are not healthy
+pub fn print_health(health: &[SubtreeHealth]) {
+    let mut lines = Vec::new();
+    let mut counts = (0, 0, 0);
+
+    for h in health {
+        let (l, s, d) = h.count();
+        counts.0 += l;
+        counts.1 += s;
+        counts.2 += d;
+        unhealthy_lines(h, 1, &mut lines);
+    }
+
+    snap_log(&format!(
+        "Tree health: {} live, {} suspect, {} dead nodes",
+        counts.0, counts.1, counts.2
+    ));
+
+    for l in lines {
+        snap_log(&l);
+    }
+}
+
+fn layout_tree(node: &TreeLayout) -> Tree {
+    let rank = node
+        .rank
+        .map(|r| format!(" rank {}", r))
+        .unwrap_or_default();
+
+    let label = if node.id == 0 {
+        format!("{}", "root".bold())
+    } else {
+        format!(
+            "{} {}:{}{}",
+            format!("#{}", node.id).bold(),
+            node.host,
+            node.pid,
+            rank
+        )
+    };
+
+    if node.children.is_empty() {
+        Tree::Leaf(vec![label])
+    } else {
+        let fanout = format!(" ({} children)", node.children.len()).truecolor(120, 120, 200);
+        Tree::Node(
+            format!("{}{}", label, fanout),
+            node.children.iter().map(layout_tree).collect(),
+        )
+    }
+}
+
+/// Print the TBON as placed by the root
+pub fn print_layout(dbg: &mut impl Debugger) -> Result<()> {
+    let layout = dbg.layout()?;
+
+    let mut out = String::new();
+    write_tree(&mut out, &layout_tree(&layout))?;
+
+    snap_log("Tree layout:");
+    println!("{}", out);
+
+    Ok(())
+}
+
+/// List the binaries run by the processes, flagging paths run with several builds
+pub fn print_binaries(dbg: &mut impl Debugger) -> Result<()> {
+    let table = dbg.symbols()?;
+    let procs = dbg.processes()?;
+
+    if !dbg.capabilities()?.symbols {
+        snap_log("Some processes cannot list their symbols (LLDB backend), they are not shown");
+    }
+
+    let ranks_of = |ids: &[u64]| -> String {
+        let ranks: Vec<u32> = ids
+            .iter()
+            .filter_map(|id| procs.get(id).and_then(|p| p.mpirank))
+            .collect();
+
+        if ranks.len() == ids.len() {
+            format!("ranks {}", format_ranks(&ranks))
+        } else {
+            format!("{} processes", ids.len())
+        }
+    };
+
+    let mut binaries: Vec<&BinarySymbols> = table.binaries.values().collect();
+    binaries.sort_by(|a, b| a.path.cmp(&b.path).then(b.ids.len().cmp(&a.ids.len())));
+
+    snap_log(&format!("{} binaries:", binaries.len()));
+
+    for bin in binaries.iter() {
+        println!(
+            "  {} [{}] {} symbols on {}",
+            bin.path.bold(),
+            bin.build_id.as_deref().unwrap_or("no build-id"),
+            bin.symbol_count(),
+            ranks_of(&bin.ids)
+        );
+    }
+
+    for (path, builds) in table.mismatches() {
+        let desc: Vec<String> = builds
+            .iter()
+            .map(|b| {
+                format!(
+                    "{} on {}",
+                    b.build_id.as_deref().unwrap_or("no build-id"),
+                    ranks_of(&b.ids)
+                )
+            })
+            .collect();
+
+        snap_log(&format!(
+            "{} {} differs across processes : {}",
+            "Mismatch:".red().bold(),
+            path,
+            desc.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Hosts listed for the processes of an uncommon library build
+const MISMATCH_HOSTS: usize = 8;
+
+/// Describe where a set of processes run, e.g. `1 rank (node17)`
+fn describe_processes(ids: &[u64], procs: &HashMap<u64, ProcessInfo>, with_hosts: bool) -> String {
+    let ranks: Vec<u32> = ids
+        .iter()
+        .filter_map(|id| procs.get(id).and_then(|p| p.mpirank))
+        .collect();
+
+    let what = if ranks.len() == ids.len() && !ids.is_empty() {
+        if ranks.len() == 1 {
+            "1 rank".to_string()
+        } else {
+            format!("{} ranks", ranks.len())
+        }
+    } else if ids.len() == 1 {
+        "1 process".to_string()
+    } else {
+        format!("{} processes", ids.len())
+    };
+
+    let mut hosts: Vec<&str> = ids
+        .iter()
+        .filter_map(|id| procs.get(id).map(|p| p.hostname.as_str()))
+        .collect();
+    hosts.sort();
+    hosts.dedup();
+
+    if !with_hosts || hosts.is_empty() || hosts.len() > MISMATCH_HOSTS {
+        return what;
+    }
+
+    format!("{} ({})", what, hosts.join(","))
+}
+
+/// Warn about the libraries loaded with several builds
+pub fn print_library_mismatches(dbg: &mut impl Debugger, libs: &LibraryReport) -> Result<()> {
+    let mismatches = libs.mismatches();
+
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+
+    let procs = dbg.processes()?;
+
+    for m in mismatches {
+        let builds: Vec<String> = m
+            .builds
+            .iter()
+            .enumerate()
+            .map(|(i, (build, ids))| {
+                let build = if build.is_empty() {
+                    "without build-id".to_string()
+                } else {
+                    format!("build-id {}", build)
+                };
+
+                /* The hosts only matter for the outliers */
+                format!("{} on {}", build, describe_processes(ids, &procs, i > 0))
+            })
+            .collect();
+
+        snap_log(&format!(
+            "{} {} {}",
+            "Library mismatch:".red().bold(),
+            m.name.bold(),
+            builds.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// List the shared libraries loaded by the processes and their builds
+pub fn print_libraries(dbg: &mut impl Debugger) -> Result<()> {
+    let libs = dbg.libraries()?;
+
+    let mut names: Vec<&String> = libs.libraries.keys().collect();
+    names.sort();
+
+    snap_log(&format!("{} shared libraries:", names.len()));
+
+    for name in names {
+        let builds = &libs.libraries[name];
+        let count: usize = builds.values().map(|v| v.len()).sum();
+
+        let desc = if builds.len() == 1 {
+            let build = builds.keys().next().unwrap();
+            if build.is_empty() {
+                "no build-id".to_string()
+            } else {
+                build.clone()
+            }
+        } else {
+            format!("{} builds", builds.len()).red().to_string()
+        };
+
+        println!("  {:<32} {} on {} processes", name, desc, count);
+    }
+
+    print_library_mismatches(dbg, &libs)
+}
+
+/// Check if some processes did not answer the snapshot
+pub fn has_unreachable(components: &HashMap<u64, (u64, Vec<BacktraceState>)>) -> bool {
+    components
+        .values()
+        .any(|(_, bt)| bt.iter().any(|f| f.is_unreachable()))
+}
+
+/// List the processes the tree could not reach
+pub fn print_unreachable(dbg: &mut impl Debugger) -> Result<()> {
+    let mut ids: Vec<u64> = dbg
+        .state()?
+        .into_iter()
+        .filter_map(|(id, st)| match st {
+            RunState::Stopped(s) if s.is_unreachable() => Some(id),
+            _ => None,
+        })
+        .collect();
+    ids.sort();
+
+    let ids: Vec<String> = ids.iter().map(|v| v.to_string()).collect();
+
+    snap_log(&format!(
+        "{} {} processes did not answer (ids {})",
+        "Unreachable:".red().bold(),
+        ids.len(),
+        ids.join(",")
+    ));
+
+    Ok(())
+}
+
+/// Check if any of the snapshot's stacks is parked on a pthread mutex
+pub fn has_mutex_waits(components: &HashMap<u64, (u64, Vec<BacktraceState>)>) -> bool {
+    components
+        .values()
+        .any(|(_, bt)| bt.iter().any(|f| f.is_mutex_wait()))
+}
+
+/// Check if any of the snapshot's stacks is blocked in MPI point-to-point calls
+pub fn has_mpi_waits(components: &HashMap<u64, (u64, Vec<BacktraceState>)>) -> bool {
+    components
+        .values()
+        .any(|(_, bt)| bt.iter().any(|f| f.is_mpi_wait()))
+}
+
+pub fn print_mpi_report(states: &HashMap<u64, MpiRankState>) {
+    let report = MpiReport::new(states);
+
+    for line in report.describe() {
+        snap_log(&format!("{} {}", "MPI deadlock:".red().bold(), line));
+    }
+
+    for line in report.describe_unknown() {
+        snap_log(&format!("{} {}", "MPI unknown peer:".yellow().bold(), line));
+    }
+
+    snap_log(&format!(
+        "{} ranks blocked in MPI calls, {} cycles, {} with an undetermined peer",
+        report.blocked,
+        report.cycles.len(),
+        report.undetermined
+    ));
+}
+
+pub fn print_lock_report(reports: &HashMap<u64, LockReport>) {
+    let mut ids: Vec<&u64> = reports.keys().collect();
+    ids.sort();
+
+    let mut deadlocked = 0;
+
+    for id in ids {
+        let report = &reports[id];
+
+        if report.cycles.is_empty() {
+            continue;
+        }
+
+        deadlocked += 1;
+
+        snap_log(&format!(
+            "{} in process {}",
+            "Mutex deadlock".red().bold(),
+            id
+        ));
+
+        for line in report.describe_cycles() {
+            snap_log(&format!("  {}", line));
+        }
+    }
+
+    let waiting: usize = reports.values().map(|r| r.waits.len()).sum();
+
+    snap_log(&format!(
+        "{} threads waiting on a mutex in {} processes, {} processes deadlocked",
+        waiting,
+        reports.len(),
+        deadlocked
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(mpirank: Option<u32>) -> ProcessInfo {
+        ProcessInfo {
+            mpirank,
+            pid: 1000,
+            hostname: "node1".to_string(),
+            locality_descriptor: "node1-0-1000".to_string(),
+        }
+    }
+
+    #[test]
+    fn missing_ranks() {
+        /* The launcher of rank 2 never connected */
+        let procs: HashMap<u64, ProcessInfo> = [0, 1, 3]
+            .into_iter()
+            .map(|r| (r as u64 + 10, process(Some(r))))
+            .collect();
+        assert_eq!(absent_ranks(&procs, 5), Some(vec![2, 4]));
+        assert_eq!(absent_ranks(&procs, 4), Some(vec![2]));
+
+        let mut procs = procs;
+        procs.insert(20, process(None));
+        assert_eq!(absent_ranks(&procs, 5), None);
+    }
+
+    #[test]
+    fn unranked_processes() {
+        /* Ranks are taken from MPI, the others come after the highest one */
+        let ranks = number_ranks(vec![(7, None), (3, Some(1)), (5, Some(0)), (4, None)]);
+        let mut ranks: Vec<(u32, u64)> = ranks.into_iter().collect();
+        ranks.sort();
+        assert_eq!(ranks, [(0, 5), (1, 3), (2, 4), (3, 7)]);
+
+        let ranks = number_ranks(vec![(9, None), (2, None)]);
+        assert_eq!(ranks[&0], 2);
+        assert_eq!(ranks[&1], 9);
+
+        /* A repeated rank keeps the lowest debugger id */
+        let ranks = number_ranks(vec![(8, Some(0)), (6, Some(0))]);
+        assert_eq!(ranks[&0], 6);
+        assert_eq!(ranks[&1], 8);
+    }
+}